@@ -0,0 +1,451 @@
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::BoxFuture;
+use crate::Builder;
+use crate::error::{Error, Result, SqlState};
+use crate::value::{Format, ToSql};
+
+/// Parameters of a statement, referred to as `$1`, `$2`, ... in its SQL.
+pub type Params<'a> = &'a [&'a (dyn ToSql + Sync)];
+
+/// A connection to a database that statements can be sent over.
+///
+/// Platt does not talk to the database itself; drivers implement this trait
+/// (see the `postgres` feature) and everything else is built on top of it.
+pub trait Connection: Send {
+    fn batch_execute<'a>(&'a mut self, sql: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// Runs a single statement, returning the number of rows it affected.
+    fn execute<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<u64>>;
+
+    fn query<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<Vec<Row>>>;
+
+    fn in_transaction(&self) -> bool {
+        false
+    }
+}
+
+impl<C: Connection + ?Sized> Connection for &mut C {
+    fn batch_execute<'a>(&'a mut self, sql: &'a str) -> BoxFuture<'a, Result<()>> {
+        (**self).batch_execute(sql)
+    }
+
+    fn execute<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<u64>> {
+        (**self).execute(sql, params)
+    }
+
+    fn query<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<Vec<Row>>> {
+        (**self).query(sql, params)
+    }
+
+    fn in_transaction(&self) -> bool {
+        (**self).in_transaction()
+    }
+}
+
+/// A row returned by [`Connection::query`], holding the raw value of every
+/// column as sent by the server (`None` for `NULL`) in `format`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    columns: Arc<[String]>,
+    format: Format,
+    values: Vec<Option<Vec<u8>>>
+}
+
+impl Row {
+    pub fn new(columns: Arc<[String]>, format: Format, values: Vec<Option<Vec<u8>>>) -> Self {
+        Self { columns, format, values }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn index_of(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|name| name == column)
+    }
+
+    /// The raw value of `column`, or `None` if the row has no such column.
+    pub fn get_raw(&self, column: &str) -> Option<Option<&[u8]>> {
+        self.index_of(column).map(|index| self.values[index].as_deref())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable
+}
+
+impl IsolationLevel {
+    fn sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE"
+        }
+    }
+}
+
+/// How long to wait before re-running a transaction that failed with a
+/// retryable error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backoff {
+    None,
+    Fixed(Duration),
+    /// Waits `initial`, then doubles the delay after every attempt up to `max`.
+    Exponential { initial: Duration, max: Duration }
+}
+
+impl Backoff {
+    /// The delay before attempt number `attempt + 1`, where `attempt` starts at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self {
+            Backoff::None => Duration::from_secs(0),
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+                initial.checked_mul(factor).map_or(*max, |delay| delay.min(*max))
+            }
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_secs(1)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TransactionEvent {
+    /// Attempt `attempt` failed with `code` and the transaction will be run
+    /// again after `delay`.
+    Retry {
+        attempt: u32,
+        code: SqlState,
+        delay: Duration
+    }
+}
+
+pub type EventHandler = Arc<dyn Fn(&TransactionEvent) + Send + Sync>;
+
+#[derive(Clone, Builder)]
+#[builder(pattern = "owned")]
+pub struct TransactionOptions {
+    #[builder(default)]
+    isolation: IsolationLevel,
+    #[builder(default)]
+    read_only: bool,
+    /// Total number of times the transaction is run, including the first.
+    #[builder(default = "1")]
+    max_attempts: u32,
+    #[builder(default)]
+    backoff: Backoff,
+    #[builder(default, setter(strip_option))]
+    on_event: Option<EventHandler>
+}
+
+impl TransactionOptions {
+    /// Serializable isolation, retried up to `max_attempts` times on
+    /// serialization failures and deadlocks.
+    pub fn serializable(max_attempts: u32) -> Self {
+        TransactionOptionsBuilder::default()
+            .isolation(IsolationLevel::Serializable)
+            .max_attempts(max_attempts)
+            .build()
+            .unwrap()
+    }
+
+    fn begin_sql(&self) -> String {
+        let mut sql = format!("BEGIN ISOLATION LEVEL {}", self.isolation.sql());
+        if self.read_only {
+            sql += " READ ONLY";
+        }
+        sql
+    }
+
+    fn emit(&self, event: TransactionEvent) {
+        if let Some(handler) = &self.on_event {
+            handler(&event);
+        }
+    }
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        TransactionOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// A connection with an open transaction, handed to the body of [`transaction`].
+pub struct Transaction<'c, C: Connection + ?Sized> {
+    conn: &'c mut C
+}
+
+impl<C: Connection + ?Sized> Connection for Transaction<'_, C> {
+    fn batch_execute<'a>(&'a mut self, sql: &'a str) -> BoxFuture<'a, Result<()>> {
+        self.conn.batch_execute(sql)
+    }
+
+    fn execute<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<u64>> {
+        self.conn.execute(sql, params)
+    }
+
+    fn query<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<Vec<Row>>> {
+        self.conn.query(sql, params)
+    }
+
+    fn in_transaction(&self) -> bool {
+        true
+    }
+}
+
+/// Runs `body` inside a transaction, committing if it returns `Ok` and
+/// rolling back otherwise.
+///
+/// If the body or the commit fails with a serialization failure (`40001`) or
+/// a deadlock (`40P01`) the whole body is run again, up to
+/// `options.max_attempts` times in total, so it must not have side effects
+/// outside the database.
+pub async fn transaction<C, T, F>(conn: &mut C, options: &TransactionOptions, mut body: F) -> Result<T>
+where
+    C: Connection + ?Sized,
+    F: for<'t> FnMut(&'t mut Transaction<'_, C>) -> BoxFuture<'t, Result<T>>
+{
+    let begin = options.begin_sql();
+    let mut attempt = 1;
+    loop {
+        conn.batch_execute(&begin).await?;
+        let result = body(&mut Transaction { conn: &mut *conn }).await;
+        let error = match result {
+            Ok(value) => match conn.batch_execute("COMMIT").await {
+                Ok(()) => return Ok(value),
+                Err(error) => error
+            },
+            Err(error) => {
+                // The error of the body is the one to report and decide the
+                // retry on. Should the rollback fail as well, the connection
+                // is broken and the next statement says so.
+                let _ = conn.batch_execute("ROLLBACK").await;
+                error
+            }
+        };
+
+        if !error.is_retryable() {
+            return Err(error);
+        }
+        if attempt >= options.max_attempts {
+            if attempt == 1 {
+                return Err(error);
+            }
+            return Err(Error::RetriesExhausted { attempts: attempt, last: Box::new(error) });
+        }
+
+        let delay = options.backoff.delay(attempt);
+        options.emit(TransactionEvent::Retry {
+            attempt,
+            code: error.code().cloned().unwrap(),
+            delay
+        });
+        if delay > Duration::from_secs(0) {
+            futures_timer::Delay::new(delay).await;
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+    use futures::FutureExt;
+    use futures::executor::block_on;
+    use super::*;
+
+    type Respond = Box<dyn FnMut(&str) -> Result<Vec<Row>> + Send>;
+
+    /// A connection recording the statements sent over it, each answered by
+    /// `respond`.
+    pub(crate) struct MockConnection {
+        pub statements: Vec<String>,
+        pub in_transaction: bool,
+        respond: Respond
+    }
+
+    impl MockConnection {
+        pub fn new(respond: impl FnMut(&str) -> Result<Vec<Row>> + Send + 'static) -> Self {
+            Self { statements: Vec::new(), in_transaction: false, respond: Box::new(respond) }
+        }
+
+        /// A connection every statement succeeds on, returning no rows.
+        pub fn ok() -> Self {
+            Self::new(|_| Ok(Vec::new()))
+        }
+
+        fn run(&mut self, sql: &str) -> Result<Vec<Row>> {
+            self.statements.push(sql.to_string());
+            (self.respond)(sql)
+        }
+    }
+
+    impl Connection for MockConnection {
+        fn batch_execute<'a>(&'a mut self, sql: &'a str) -> BoxFuture<'a, Result<()>> {
+            let result = self.run(sql).map(|_| ());
+            async move { result }.boxed()
+        }
+
+        fn execute<'a>(&'a mut self, sql: &'a str, _params: Params<'a>) -> BoxFuture<'a, Result<u64>> {
+            let result = self.run(sql).map(|rows| rows.len() as u64);
+            async move { result }.boxed()
+        }
+
+        fn query<'a>(&'a mut self, sql: &'a str, _params: Params<'a>) -> BoxFuture<'a, Result<Vec<Row>>> {
+            let result = self.run(sql);
+            async move { result }.boxed()
+        }
+
+        fn in_transaction(&self) -> bool {
+            self.in_transaction
+        }
+    }
+
+    fn serialization_failure() -> Error {
+        Error::database(Some(SqlState::SERIALIZATION_FAILURE), "could not serialize access")
+    }
+
+    /// Options retrying up to `max_attempts` times without waiting, along
+    /// with the events they report.
+    fn options(max_attempts: u32) -> (TransactionOptions, Arc<Mutex<Vec<TransactionEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let options = TransactionOptionsBuilder::default()
+            .isolation(IsolationLevel::Serializable)
+            .max_attempts(max_attempts)
+            .backoff(Backoff::None)
+            .on_event(Arc::new(move |event: &TransactionEvent| recorded.lock().unwrap().push(event.clone())))
+            .build()
+            .unwrap();
+        (options, events)
+    }
+
+    #[test]
+    fn retries_a_body_failing_to_serialize() {
+        let mut conn = MockConnection::ok();
+        let (options, events) = options(3);
+        let mut attempts = 0;
+        let result = block_on(transaction(&mut conn, &options, |tx| {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                tx.execute("UPDATE \"Post\" SET \"views\" = \"views\" + 1", &[]).await?;
+                if attempt == 1 { Err(serialization_failure()) } else { Ok(attempt) }
+            }.boxed()
+        }));
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(conn.statements, [
+            "BEGIN ISOLATION LEVEL SERIALIZABLE",
+            "UPDATE \"Post\" SET \"views\" = \"views\" + 1",
+            "ROLLBACK",
+            "BEGIN ISOLATION LEVEL SERIALIZABLE",
+            "UPDATE \"Post\" SET \"views\" = \"views\" + 1",
+            "COMMIT"
+        ]);
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [TransactionEvent::Retry { attempt: 1, code, delay }]
+                if *code == SqlState::SERIALIZATION_FAILURE && *delay == Duration::from_secs(0)
+        ));
+    }
+
+    #[test]
+    fn retries_a_deadlocked_commit() {
+        let mut commits = 0;
+        let mut conn = MockConnection::new(move |sql| {
+            if sql == "COMMIT" {
+                commits += 1;
+                if commits == 1 {
+                    return Err(Error::database(Some(SqlState::DEADLOCK_DETECTED), "deadlock detected"));
+                }
+            }
+            Ok(Vec::new())
+        });
+        let (options, events) = options(2);
+        let result = block_on(transaction(&mut conn, &options, |_| async { Ok(()) }.boxed()));
+        assert!(result.is_ok());
+        assert_eq!(conn.statements.iter().filter(|sql| sql.starts_with("BEGIN")).count(), 2);
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [TransactionEvent::Retry { attempt: 1, code, .. }] if *code == SqlState::DEADLOCK_DETECTED
+        ));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut conn = MockConnection::ok();
+        let (options, events) = options(3);
+        let result: Result<()> = block_on(transaction(&mut conn, &options, |_| async { Err(serialization_failure()) }.boxed()));
+        match result {
+            Err(Error::RetriesExhausted { attempts: 3, last }) => assert_eq!(last.code(), Some(&SqlState::SERIALIZATION_FAILURE)),
+            other => panic!("expected RetriesExhausted, got {:?}", other)
+        }
+        assert_eq!(conn.statements.iter().filter(|sql| *sql == "ROLLBACK").count(), 3);
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let mut conn = MockConnection::ok();
+        let (options, events) = options(3);
+        let result: Result<()> = block_on(transaction(&mut conn, &options, |_| {
+            async { Err(Error::database(Some(SqlState::new("23503")), "foreign key violation")) }.boxed()
+        }));
+        assert_eq!(result.unwrap_err().code(), Some(&SqlState::new("23503")));
+        assert_eq!(conn.statements, ["BEGIN ISOLATION LEVEL SERIALIZABLE", "ROLLBACK"]);
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_body_error_when_the_rollback_fails() {
+        let mut conn = MockConnection::new(|sql| match sql {
+            "ROLLBACK" => Err(Error::database(None, "connection closed")),
+            _ => Ok(Vec::new())
+        });
+        let (options, _) = options(1);
+        let result: Result<()> = block_on(transaction(&mut conn, &options, |_| async { Err(serialization_failure()) }.boxed()));
+        match result {
+            Err(Error::Database { code: Some(code), message }) => {
+                assert_eq!(code, SqlState::SERIALIZATION_FAILURE);
+                assert_eq!(message, "could not serialize access");
+            }
+            other => panic!("expected the body's error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn backoff_delays() {
+        assert_eq!(Backoff::None.delay(3), Duration::from_secs(0));
+        assert_eq!(Backoff::Fixed(Duration::from_millis(50)).delay(3), Duration::from_millis(50));
+        let exponential = Backoff::Exponential { initial: Duration::from_millis(10), max: Duration::from_millis(100) };
+        let delays: Vec<Duration> = (1..=6).map(|attempt| exponential.delay(attempt)).collect();
+        assert_eq!(delays, [10, 20, 40, 80, 100, 100].iter().map(|ms| Duration::from_millis(*ms)).collect::<Vec<_>>());
+        assert_eq!(exponential.delay(40), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,1083 @@
+use std::collections::{HashMap, HashSet};
+use crate::schema::{
+    creation_order, element_type, quote_literal, Check, Column, Composite, DbType, EnumType, ForeignKeyConstraint, Index, QualifiedName, References,
+    Schema, Table, Unique
+};
+
+/// A single change between two schemas. Tables, types and indexes are
+/// named by their [`QualifiedName`]s, and the names they are renamed to or
+/// constraints are named after are the bare ones.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    CreateSchema(String),
+    /// Moves a table to another Postgres schema, `None` being `public`.
+    SetTableSchema { table: QualifiedName, schema: Option<String> },
+    /// Moves an enum or composite type to another Postgres schema.
+    SetTypeSchema { name: QualifiedName, schema: Option<String> },
+    RenameEnumLabel { enum_name: QualifiedName, from: String, to: String },
+    RenameComposite { from: QualifiedName, to: String },
+    RenameAttribute { composite: QualifiedName, from: String, to: String },
+    RenameTable { from: QualifiedName, to: String },
+    RenameColumn { table: QualifiedName, from: String, to: String },
+    /// Postgres keeps the names of constraints when what they are named
+    /// after is renamed, so they are renamed along.
+    RenameConstraint { table: QualifiedName, from: String, to: String },
+    RenameIndex { from: QualifiedName, to: String },
+    CreateEnum(EnumType),
+    /// Postgres can not remove labels from an enum, so only added ones are
+    /// tracked.
+    AddEnumLabel { enum_name: QualifiedName, label: String },
+    DropEnum(QualifiedName),
+    CreateComposite(Composite),
+    AddAttribute { composite: QualifiedName, name: String, db_type: DbType },
+    DropAttribute { composite: QualifiedName, name: String },
+    AlterAttributeType { composite: QualifiedName, name: String, db_type: DbType },
+    DropComposite(QualifiedName),
+    CreateTable(Table),
+    DropTable(QualifiedName),
+    AddColumn { table: QualifiedName, column: Column },
+    DropColumn { table: QualifiedName, column: String },
+    AlterColumnType { table: QualifiedName, column: String, db_type: DbType },
+    SetNotNull { table: QualifiedName, column: String },
+    DropNotNull { table: QualifiedName, column: String },
+    SetDefault { table: QualifiedName, column: String, default: String },
+    DropDefault { table: QualifiedName, column: String },
+    AddUnique { table: QualifiedName, column: String },
+    DropUnique { table: QualifiedName, column: String },
+    AddIdentity { table: QualifiedName, column: String },
+    DropIdentity { table: QualifiedName, column: String },
+    AddPrimaryKey { table: QualifiedName, columns: Vec<String> },
+    DropPrimaryKey { table: QualifiedName },
+    AddForeignKey { table: QualifiedName, column: String, references: References },
+    DropForeignKey { table: QualifiedName, column: String },
+    AddForeignKeyConstraint { table: QualifiedName, foreign_key: ForeignKeyConstraint },
+    DropForeignKeyConstraint { table: QualifiedName, name: String },
+    AddUniqueConstraint { table: QualifiedName, unique: Unique },
+    DropUniqueConstraint { table: QualifiedName, name: String },
+    AddCheck { table: QualifiedName, check: Check },
+    DropCheck { table: QualifiedName, name: String },
+    AddIndex { table: QualifiedName, index: Index },
+    DropIndex(QualifiedName),
+    /// Dropped once nothing the schemas hold lives in it. Postgres refuses
+    /// when something else still does.
+    DropSchema(String)
+}
+
+impl Change {
+    /// Where the statement goes in the rendered diff: types are created
+    /// before the tables using them and dropped after, and constraints are
+    /// dropped before and added after the columns they cover change.
+    /// Schemas are created and moved to first and renames come next, as the
+    /// other changes use the new names.
+    fn order(&self) -> u8 {
+        match self {
+            Change::CreateSchema(_) => 0,
+            Change::SetTableSchema { .. } | Change::SetTypeSchema { .. } => 1,
+            Change::RenameEnumLabel { .. } => 2,
+            Change::RenameComposite { .. } => 3,
+            Change::RenameAttribute { .. } => 4,
+            Change::RenameTable { .. } => 5,
+            Change::RenameColumn { .. } | Change::RenameConstraint { .. } | Change::RenameIndex { .. } => 6,
+            Change::CreateEnum(_) => 7,
+            Change::AddEnumLabel { .. } => 8,
+            Change::CreateComposite(_) => 9,
+            Change::AddAttribute { .. } => 10,
+            Change::AlterAttributeType { .. } => 11,
+            Change::DropCheck { .. }
+            | Change::DropIndex(_)
+            | Change::DropForeignKey { .. }
+            | Change::DropForeignKeyConstraint { .. } => 12,
+            Change::DropUnique { .. }
+            | Change::DropUniqueConstraint { .. }
+            | Change::DropPrimaryKey { .. }
+            | Change::DropDefault { .. }
+            | Change::DropIdentity { .. } => 13,
+            Change::CreateTable(_) => 14,
+            Change::AddColumn { .. } => 15,
+            Change::AlterColumnType { .. } => 16,
+            Change::SetNotNull { .. }
+            | Change::DropNotNull { .. }
+            | Change::SetDefault { .. }
+            | Change::AddIdentity { .. } => 17,
+            Change::AddPrimaryKey { .. } | Change::AddUnique { .. } | Change::AddUniqueConstraint { .. } => 18,
+            Change::AddCheck { .. }
+            | Change::AddIndex { .. }
+            | Change::AddForeignKey { .. }
+            | Change::AddForeignKeyConstraint { .. } => 19,
+            Change::DropColumn { .. } => 20,
+            Change::DropTable(_) => 21,
+            Change::DropAttribute { .. } => 22,
+            Change::DropComposite(_) => 23,
+            Change::DropEnum(_) => 24,
+            Change::DropSchema(_) => 25
+        }
+    }
+
+    /// Whether the change drops data, which undoing it can not bring back.
+    pub fn is_irreversible(&self) -> bool {
+        matches!(self, Change::DropTable(_) | Change::DropColumn { .. } | Change::DropAttribute { .. })
+    }
+
+    /// Whether the change has to be committed before the statements after
+    /// it can rely on it, as a label added to an enum can not be used in the
+    /// transaction adding it.
+    pub fn needs_commit(&self) -> bool {
+        matches!(self, Change::AddEnumLabel { .. })
+    }
+
+    pub fn to_sql(&self) -> String {
+        match self {
+            Change::CreateSchema(name) => format!("CREATE SCHEMA IF NOT EXISTS \"{}\"", name),
+            Change::SetTableSchema { table, schema } => {
+                format!("ALTER TABLE {} SET SCHEMA \"{}\"", table, schema.as_deref().unwrap_or("public"))
+            }
+            Change::SetTypeSchema { name, schema } => {
+                format!("ALTER TYPE {} SET SCHEMA \"{}\"", name, schema.as_deref().unwrap_or("public"))
+            }
+            Change::RenameEnumLabel { enum_name, from, to } => {
+                format!("ALTER TYPE {} RENAME VALUE {} TO {}", enum_name, quote_literal(from), quote_literal(to))
+            }
+            Change::RenameComposite { from, to } => format!("ALTER TYPE {} RENAME TO \"{}\"", from, to),
+            Change::RenameAttribute { composite, from, to } => {
+                format!("ALTER TYPE {} RENAME ATTRIBUTE \"{}\" TO \"{}\"", composite, from, to)
+            }
+            Change::RenameTable { from, to } => format!("ALTER TABLE {} RENAME TO \"{}\"", from, to),
+            Change::RenameColumn { table, from, to } => {
+                format!("ALTER TABLE {} RENAME COLUMN \"{}\" TO \"{}\"", table, from, to)
+            }
+            Change::RenameConstraint { table, from, to } => {
+                format!("ALTER TABLE {} RENAME CONSTRAINT \"{}\" TO \"{}\"", table, from, to)
+            }
+            Change::RenameIndex { from, to } => format!("ALTER INDEX {} RENAME TO \"{}\"", from, to),
+            Change::CreateEnum(enum_type) => enum_type.create_sql(),
+            // Run outside a transaction, so it is skipped when a migration
+            // failing after it is applied again.
+            Change::AddEnumLabel { enum_name, label } => {
+                format!("ALTER TYPE {} ADD VALUE IF NOT EXISTS {}", enum_name, quote_literal(label))
+            }
+            Change::DropEnum(name) | Change::DropComposite(name) => format!("DROP TYPE {}", name),
+            Change::CreateComposite(composite) => composite.create_sql(),
+            Change::AddAttribute { composite, name, db_type } => {
+                format!("ALTER TYPE {} ADD ATTRIBUTE \"{}\" {}", composite, name, db_type.db_type_string_simple())
+            }
+            Change::DropAttribute { composite, name } => {
+                format!("ALTER TYPE {} DROP ATTRIBUTE \"{}\"", composite, name)
+            }
+            Change::AlterAttributeType { composite, name, db_type } => {
+                format!("ALTER TYPE {} ALTER ATTRIBUTE \"{}\" TYPE {}", composite, name, db_type.db_type_string_simple())
+            }
+            Change::CreateTable(table) => table.create_sql(),
+            Change::DropTable(name) => format!("DROP TABLE {}", name),
+            // The primary key is added apart, as it may span other columns.
+            Change::AddColumn { table, column } => {
+                let db_type = DbType { primary_key: false, ..column.db_type().clone() };
+                format!("ALTER TABLE {} ADD COLUMN \"{}\" {}", table, column.name(), db_type.db_type_string())
+            }
+            Change::DropColumn { table, column } => format!("ALTER TABLE {} DROP COLUMN \"{}\"", table, column),
+            // The explicit cast also converts between types Postgres has no
+            // assignment cast for, such as from text to a number.
+            Change::AlterColumnType { table, column, db_type } => {
+                let db_type = db_type.db_type_string_simple();
+                format!(
+                    "ALTER TABLE {} ALTER COLUMN \"{}\" TYPE {} USING \"{}\"::{}",
+                    table, column, db_type, column, db_type
+                )
+            }
+            Change::SetNotNull { table, column } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" SET NOT NULL", table, column)
+            }
+            Change::DropNotNull { table, column } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" DROP NOT NULL", table, column)
+            }
+            Change::SetDefault { table, column, default } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" SET DEFAULT {}", table, column, default)
+            }
+            Change::DropDefault { table, column } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" DROP DEFAULT", table, column)
+            }
+            // The names are the ones Postgres gives column constraints.
+            Change::AddUnique { table, column } => {
+                format!("ALTER TABLE {} ADD CONSTRAINT \"{}_{}_key\" UNIQUE (\"{}\")", table, table.name, column, column)
+            }
+            Change::DropUnique { table, column } => {
+                format!("ALTER TABLE {} DROP CONSTRAINT \"{}_{}_key\"", table, table.name, column)
+            }
+            Change::AddIdentity { table, column } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" ADD GENERATED BY DEFAULT AS IDENTITY", table, column)
+            }
+            Change::DropIdentity { table, column } => {
+                format!("ALTER TABLE {} ALTER COLUMN \"{}\" DROP IDENTITY", table, column)
+            }
+            Change::AddPrimaryKey { table, columns } => {
+                let columns: Vec<String> = columns.iter().map(|column| format!("\"{}\"", column)).collect();
+                format!("ALTER TABLE {} ADD CONSTRAINT \"{}_pkey\" PRIMARY KEY ({})", table, table.name, columns.join(", "))
+            }
+            Change::DropPrimaryKey { table } => format!("ALTER TABLE {} DROP CONSTRAINT \"{}_pkey\"", table, table.name),
+            // The name is the one Postgres gives foreign keys.
+            Change::AddForeignKey { table, column, references } => format!(
+                "ALTER TABLE {} ADD CONSTRAINT \"{}_{}_fkey\" FOREIGN KEY (\"{}\") {}",
+                table, table.name, column, column, references.to_sql()
+            ),
+            Change::DropForeignKey { table, column } => {
+                format!("ALTER TABLE {} DROP CONSTRAINT \"{}_{}_fkey\"", table, table.name, column)
+            }
+            Change::AddForeignKeyConstraint { table, foreign_key } => {
+                format!("ALTER TABLE {} ADD {}", table, foreign_key.to_sql())
+            }
+            Change::AddUniqueConstraint { table, unique } => format!("ALTER TABLE {} ADD {}", table, unique.to_sql()),
+            Change::DropUniqueConstraint { table, name }
+            | Change::DropForeignKeyConstraint { table, name }
+            | Change::DropCheck { table, name } => {
+                format!("ALTER TABLE {} DROP CONSTRAINT \"{}\"", table, name)
+            }
+            Change::AddCheck { table, check } => format!("ALTER TABLE {} ADD {}", table, check.to_sql()),
+            Change::AddIndex { table, index } => index.create_sql(table),
+            Change::DropIndex(name) => format!("DROP INDEX {}", name),
+            Change::DropSchema(name) => format!("DROP SCHEMA \"{}\"", name)
+        }
+    }
+}
+
+/// The changes turning one [`Schema`] into another, see [`Schema::diff`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaDiff {
+    changes: Vec<Change>,
+    warnings: Vec<String>
+}
+
+fn by_name<T>(items: &[T], name: impl Fn(&T) -> &str) -> HashMap<&str, &T> {
+    items.iter().map(|item| (name(item), item)).collect()
+}
+
+impl SchemaDiff {
+    /// Types and tables are created after what they depend on and dropped
+    /// before it, see [`Schema::to_sql`].
+    pub(crate) fn between(current: &Schema, target: &Schema) -> Self {
+        let mut differ = Differ::default();
+        let (current_schemas, target_schemas) = (current.schemas(), target.schemas());
+        for schema in target_schemas.iter().filter(|schema| !current_schemas.contains(schema)) {
+            differ.changes.push(Change::CreateSchema(schema.to_string()));
+        }
+        differ.enums(current.enums(), target.enums());
+        let in_order = |schema: &Schema| -> Vec<Composite> { schema.composites_in_order().into_iter().cloned().collect() };
+        differ.composites(&in_order(current), &in_order(target));
+        differ.tables(current.tables(), target.tables());
+        for schema in current_schemas.iter().filter(|schema| !target_schemas.contains(schema)) {
+            differ.changes.push(Change::DropSchema(schema.to_string()));
+        }
+        differ.changes.sort_by_key(Change::order);
+        Self { changes: differ.changes, warnings: differ.warnings }
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Drops paired with adds of the same type, which lose data if they were
+    /// meant as a rename.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// One statement per change, in an order they can be run in.
+    pub fn statements(&self) -> Vec<String> {
+        self.changes.iter().map(Change::to_sql).collect()
+    }
+
+    /// The statements of the changes that drop data.
+    pub fn irreversible_statements(&self) -> Vec<String> {
+        self.changes.iter().filter(|change| change.is_irreversible()).map(Change::to_sql).collect()
+    }
+
+    pub fn to_sql(&self) -> String {
+        self.statements().iter().map(|statement| format!("{};\n", statement)).collect()
+    }
+}
+
+/// Whether two types are the same to Postgres, which ignores the sizes of
+/// array dimensions and the case of unquoted type names.
+fn same_type(a: &DbType, b: &DbType) -> bool {
+    fn normalize(base: &str) -> String {
+        let mut normalized = String::new();
+        let mut in_dimension = false;
+        let mut in_quotes = false;
+        for c in base.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                '[' if !in_quotes => in_dimension = true,
+                ']' if !in_quotes => in_dimension = false,
+                c if in_dimension && c.is_ascii_digit() => continue,
+                _ => ()
+            }
+            normalized.push(if in_quotes { c } else { c.to_ascii_uppercase() });
+        }
+        normalized
+    }
+    a.base == b.base || normalize(&a.base) == normalize(&b.base)
+}
+
+/// Types Postgres spells in several words, which it prints after `::` when
+/// it prints a cast back.
+const MULTI_WORD_TYPES: [&str; 4] = [" varying", " precision", " without time zone", " with time zone"];
+
+/// Whether two `CHECK` or `DEFAULT` expressions are the same, ignoring what
+/// Postgres changes when it prints one back: whitespace, quotes,
+/// parentheses, casts and case.
+fn same_expr(a: &str, b: &str) -> bool {
+    fn normalize(expr: &str) -> String {
+        let mut normalized = String::new();
+        let mut rest = expr;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            if c == ':' && rest.starts_with(':') {
+                rest = rest[1..].trim_start_matches(|c: char| c.is_alphanumeric() || c == '_' || c == '"');
+                if let Some(words) = MULTI_WORD_TYPES.iter().find(|words| rest.starts_with(*words)) {
+                    rest = &rest[words.len()..];
+                }
+                rest = rest.trim_start_matches("[]");
+                continue;
+            }
+            if !c.is_whitespace() && !matches!(c, '(' | ')' | '"' | '\'') {
+                normalized.extend(c.to_lowercase());
+            }
+        }
+        normalized
+    }
+    a == b || normalize(a) == normalize(b)
+}
+
+/// Whether `column` is the whole primary key of `table`, which makes it
+/// unique without a constraint of its own.
+pub(crate) fn is_sole_key(table: &Table, column: &str) -> bool {
+    table.primary_key() == [column]
+}
+
+/// Whether two indexes cover the same keys the same way, whatever their
+/// names.
+fn same_index(a: &Index, b: &Index) -> bool {
+    let method = |index: &Index| index.using.as_deref().unwrap_or("btree").to_lowercase();
+    a.keys.len() == b.keys.len()
+        && a.keys.iter().zip(&b.keys).all(|(a, b)| same_expr(a, b))
+        && a.unique == b.unique
+        && method(a) == method(b)
+        && match (&a.predicate, &b.predicate) {
+            (Some(a), Some(b)) => same_expr(a, b),
+            (a, b) => a == b
+        }
+}
+
+/// The renames between the names in `current` and those in `target`, as a
+/// map from the new name to the old one, given the `(old, new)` renames
+/// recorded on either side. A rename recorded on `target` applies when
+/// `current` still has the old name, and one recorded on `current` applies
+/// backwards when `target` has its old name again, which is how a diff back
+/// renames back.
+fn renames<'a>(
+    current: &[&'a str],
+    target: &[&'a str],
+    target_renames: impl IntoIterator<Item = (&'a str, &'a str)>,
+    current_renames: impl IntoIterator<Item = (&'a str, &'a str)>
+) -> HashMap<&'a str, &'a str> {
+    let current: HashSet<&str> = current.iter().copied().collect();
+    let target: HashSet<&str> = target.iter().copied().collect();
+    let mut renames = HashMap::new();
+    for (old, new) in target_renames {
+        if current.contains(old) && !current.contains(new) && target.contains(new) && !target.contains(old) {
+            renames.insert(new, old);
+        }
+    }
+    for (old, new) in current_renames {
+        if target.contains(old) && !target.contains(new) && current.contains(new) && !current.contains(old) {
+            renames.insert(old, new);
+        }
+    }
+    renames
+}
+
+fn warn_rename(kind: &str, dropped: &str, added: &str, attribute: &str) -> String {
+    format!(
+        "{kind} {dropped} is dropped and {added} is added with the same type; if it was renamed, add \
+        #[platt(renamed_from = \"{attribute}\")] to it instead",
+        kind = kind, dropped = dropped, added = added, attribute = attribute
+    )
+}
+
+/// Collects the changes and warnings of a diff.
+#[derive(Default)]
+struct Differ {
+    changes: Vec<Change>,
+    warnings: Vec<String>,
+    /// The types and tables being renamed or moved to another schema, as
+    /// `(old, new)` quoted names, which types referring to them are read
+    /// with.
+    type_renames: Vec<(String, String)>,
+    /// The tables being renamed or moved, which foreign keys pointing to
+    /// them are read with.
+    table_renames: Vec<(QualifiedName, QualifiedName)>
+}
+
+impl Differ {
+    /// `db_type` with the names of renamed types and tables in it replaced
+    /// with the new ones.
+    fn renamed_type(&self, db_type: &DbType) -> DbType {
+        let mut db_type = db_type.clone();
+        let element = element_type(&db_type.base);
+        if let Some((_, new)) = self.type_renames.iter().find(|(old, _)| old == element) {
+            db_type.base = format!("{}{}", new, &db_type.base[element.len()..]);
+        }
+        db_type.references = db_type.references.map(|references| self.renamed_references(&references));
+        db_type
+    }
+
+    /// `references` pointing to the new name of the table, if it is being
+    /// renamed or moved.
+    fn renamed_references(&self, references: &References) -> References {
+        let mut references = references.clone();
+        let table = QualifiedName::new(references.schema.as_deref(), &references.table);
+        if let Some((_, new)) = self.table_renames.iter().find(|(old, _)| *old == table) {
+            references.schema = new.schema.clone();
+            references.table = new.name.clone();
+        }
+        references
+    }
+
+    fn enums(&mut self, current: &[EnumType], target: &[EnumType]) {
+        let current_by_name = by_name(current, |enum_type| &enum_type.name);
+        let target_by_name = by_name(target, |enum_type| &enum_type.name);
+        for enum_type in target {
+            let old = match current_by_name.get(enum_type.name.as_str()) {
+                Some(old) => old,
+                None => {
+                    self.changes.push(Change::CreateEnum(enum_type.clone()));
+                    continue;
+                }
+            };
+            if old.schema != enum_type.schema {
+                self.changes.push(Change::SetTypeSchema { name: old.qualified_name(), schema: enum_type.schema.clone() });
+                self.type_renames.push((old.qualified_name().to_string(), enum_type.qualified_name().to_string()));
+            }
+            let old_labels: Vec<&str> = old.labels.iter().map(String::as_str).collect();
+            let new_labels: Vec<&str> = enum_type.labels.iter().map(String::as_str).collect();
+            let renames = renames(
+                &old_labels,
+                &new_labels,
+                enum_type.renamed_labels.iter().map(|(old, new)| (old.as_str(), new.as_str())),
+                old.renamed_labels.iter().map(|(old, new)| (old.as_str(), new.as_str()))
+            );
+            let mut added = Vec::new();
+            for label in &new_labels {
+                match renames.get(label) {
+                    _ if old_labels.contains(label) => (),
+                    Some(from) => self.changes.push(Change::RenameEnumLabel {
+                        enum_name: enum_type.qualified_name(),
+                        from: from.to_string(),
+                        to: label.to_string()
+                    }),
+                    None => {
+                        added.push(*label);
+                        self.changes.push(Change::AddEnumLabel {
+                            enum_name: enum_type.qualified_name(),
+                            label: label.to_string()
+                        });
+                    }
+                }
+            }
+            let renamed: HashSet<&str> = renames.values().copied().collect();
+            let unused = old_labels.iter().filter(|label| !new_labels.contains(label) && !renamed.contains(*label));
+            for (unused, added) in unused.zip(added) {
+                self.warnings.push(warn_rename(
+                    "label",
+                    &format!("{}::{}", enum_type.name, unused),
+                    &format!("{}::{}", enum_type.name, added),
+                    unused
+                ));
+            }
+        }
+        for enum_type in current.iter().rev().filter(|enum_type| !target_by_name.contains_key(enum_type.name.as_str())) {
+            self.changes.push(Change::DropEnum(enum_type.qualified_name()));
+        }
+    }
+
+    fn composites(&mut self, current: &[Composite], target: &[Composite]) {
+        let current_by_name = by_name(current, |composite| &composite.name);
+        let old_names: Vec<&str> = current.iter().map(|composite| composite.name.as_str()).collect();
+        let new_names: Vec<&str> = target.iter().map(|composite| composite.name.as_str()).collect();
+        let renames = renames(
+            &old_names,
+            &new_names,
+            target.iter().filter_map(|composite| Some((composite.renamed_from.as_deref()?, composite.name.as_str()))),
+            current.iter().filter_map(|composite| Some((composite.renamed_from.as_deref()?, composite.name.as_str())))
+        );
+        let matched: Vec<(&Composite, Option<&Composite>)> = target.iter().map(|composite| {
+            let old_name = renames.get(composite.name.as_str()).copied().unwrap_or(&composite.name);
+            (composite, current_by_name.get(old_name).copied())
+        }).collect();
+        // Fields may hold the other types, so their new names are known
+        // before any is compared.
+        for (composite, old) in &matched {
+            if let Some(old) = old.filter(|old| old.qualified_name() != composite.qualified_name()) {
+                self.type_renames.push((old.qualified_name().to_string(), composite.qualified_name().to_string()));
+            }
+        }
+
+        let mut created = Vec::new();
+        for (composite, old) in matched {
+            let old = match old {
+                Some(old) => old,
+                None => {
+                    created.push(composite);
+                    self.changes.push(Change::CreateComposite(composite.clone()));
+                    continue;
+                }
+            };
+            if old.schema != composite.schema {
+                self.changes.push(Change::SetTypeSchema { name: old.qualified_name(), schema: composite.schema.clone() });
+            }
+            if old.name != composite.name {
+                self.changes.push(Change::RenameComposite {
+                    from: QualifiedName::new(composite.schema.as_deref(), &old.name),
+                    to: composite.name.clone()
+                });
+            }
+            self.composite(old, composite);
+        }
+        let renamed: HashSet<&str> = renames.values().copied().collect();
+        let dropped: Vec<&Composite> = current.iter().rev()
+            .filter(|composite| !new_names.contains(&composite.name.as_str()) && !renamed.contains(composite.name.as_str()))
+            .collect();
+        for composite in &dropped {
+            self.changes.push(Change::DropComposite(composite.qualified_name()));
+        }
+        let same_fields = |a: &Composite, b: &Composite| {
+            a.fields.len() == b.fields.len()
+                && a.fields.iter().zip(&b.fields).all(|((a_name, a_type), (b_name, b_type))| {
+                    a_name == b_name && same_type(a_type, b_type)
+                })
+        };
+        for old in dropped {
+            if let Some(new) = created.iter().find(|new| same_fields(old, new)) {
+                self.warnings.push(warn_rename("type", &old.name, &new.name, &old.name));
+            }
+        }
+    }
+
+    fn composite(&mut self, old: &Composite, composite: &Composite) {
+        let old_fields = by_name(&old.fields, |(name, _)| name);
+        let old_names: Vec<&str> = old.fields.iter().map(|(name, _)| name.as_str()).collect();
+        let new_names: Vec<&str> = composite.fields.iter().map(|(name, _)| name.as_str()).collect();
+        let renames = renames(
+            &old_names,
+            &new_names,
+            composite.renamed_fields.iter().map(|(old, new)| (old.as_str(), new.as_str())),
+            old.renamed_fields.iter().map(|(old, new)| (old.as_str(), new.as_str()))
+        );
+        let mut added = Vec::new();
+        for (name, db_type) in &composite.fields {
+            let old_name = match renames.get(name.as_str()) {
+                Some(from) => {
+                    self.changes.push(Change::RenameAttribute {
+                        composite: composite.qualified_name(),
+                        from: from.to_string(),
+                        to: name.clone()
+                    });
+                    from
+                }
+                None => name.as_str()
+            };
+            match old_fields.get(old_name) {
+                None => {
+                    added.push((name, db_type));
+                    self.changes.push(Change::AddAttribute {
+                        composite: composite.qualified_name(),
+                        name: name.clone(),
+                        db_type: db_type.clone()
+                    });
+                }
+                Some((_, old_type)) if !same_type(&self.renamed_type(old_type), db_type) => {
+                    self.changes.push(Change::AlterAttributeType {
+                        composite: composite.qualified_name(),
+                        name: name.clone(),
+                        db_type: db_type.clone()
+                    });
+                }
+                Some(_) => ()
+            }
+        }
+        let renamed: HashSet<&str> = renames.values().copied().collect();
+        for (name, old_type) in &old.fields {
+            if new_names.contains(&name.as_str()) || renamed.contains(name.as_str()) {
+                continue;
+            }
+            self.changes.push(Change::DropAttribute { composite: composite.qualified_name(), name: name.clone() });
+            if let Some((added, _)) = added.iter().find(|(_, db_type)| same_type(&self.renamed_type(old_type), db_type)) {
+                self.warnings.push(warn_rename(
+                    "field",
+                    &format!("{}.{}", composite.name, name),
+                    &format!("{}.{}", composite.name, added),
+                    name
+                ));
+            }
+        }
+    }
+
+    fn tables(&mut self, current: &[Table], target: &[Table]) {
+        let current_by_name = by_name(current, Table::name);
+        let old_names: Vec<&str> = current.iter().map(Table::name).collect();
+        let new_names: Vec<&str> = target.iter().map(Table::name).collect();
+        let renames = renames(
+            &old_names,
+            &new_names,
+            target.iter().filter_map(|table| Some((table.renamed_from()?, table.name()))),
+            current.iter().filter_map(|table| Some((table.renamed_from()?, table.name())))
+        );
+        let matched: Vec<(&Table, Option<&Table>)> = target.iter().map(|table| {
+            let old_name = renames.get(table.name()).copied().unwrap_or(table.name());
+            (table, current_by_name.get(old_name).copied())
+        }).collect();
+        for (table, old) in &matched {
+            if let Some(old) = old.filter(|old| old.qualified_name() != table.qualified_name()) {
+                self.type_renames.push((old.qualified_name().to_string(), table.qualified_name().to_string()));
+                self.table_renames.push((old.qualified_name(), table.qualified_name()));
+            }
+        }
+
+        let mut created = Vec::new();
+        for (table, old) in matched {
+            let old = match old {
+                Some(old) => old,
+                None => {
+                    created.push(table);
+                    continue;
+                }
+            };
+            if old.schema() != table.schema() {
+                self.changes.push(Change::SetTableSchema { table: old.qualified_name(), schema: table.schema().map(str::to_string) });
+            }
+            if old.name() != table.name() {
+                self.changes.push(Change::RenameTable {
+                    from: QualifiedName::new(table.schema(), old.name()),
+                    to: table.name().to_string()
+                });
+                self.rename_constraints(old, &table.qualified_name());
+            }
+            self.table(old, table);
+        }
+        // Foreign keys closing a reference cycle between the created tables
+        // are added once all of them exist.
+        for (table, cycle) in creation_order(&created) {
+            let (table, add_foreign_keys) = table.add_foreign_keys(&cycle);
+            self.changes.push(Change::CreateTable(table.clone()));
+            for index in table.all_indexes() {
+                self.changes.push(Change::AddIndex { table: table.qualified_name(), index });
+            }
+            self.changes.extend(add_foreign_keys);
+        }
+        let renamed: HashSet<&str> = renames.values().copied().collect();
+        let dropped: Vec<&Table> = current.iter()
+            .filter(|table| !new_names.contains(&table.name()) && !renamed.contains(table.name()))
+            .collect();
+        // The other way around, tables are dropped before those they
+        // reference, after dropping the foreign keys of a cycle.
+        for (table, cycle) in creation_order(&dropped).into_iter().rev() {
+            let (_, columns, constraints) = table.split_foreign_keys(&cycle);
+            for (column, _) in columns {
+                self.changes.push(Change::DropForeignKey { table: table.qualified_name(), column });
+            }
+            for foreign_key in constraints {
+                self.changes.push(Change::DropForeignKeyConstraint { table: table.qualified_name(), name: foreign_key.name });
+            }
+            self.changes.push(Change::DropTable(table.qualified_name()));
+        }
+        let same_columns = |a: &Table, b: &Table| {
+            a.columns().len() == b.columns().len()
+                && a.columns().iter().zip(b.columns()).all(|(a, b)| {
+                    a.name() == b.name() && same_type(&self.renamed_type(a.db_type()), b.db_type())
+                })
+        };
+        let mut warnings = Vec::new();
+        for old in dropped {
+            if let Some(new) = created.iter().find(|new| same_columns(old, new)) {
+                warnings.push(warn_rename("table", old.name(), new.name(), old.name()));
+            }
+        }
+        self.warnings.extend(warnings);
+    }
+
+    /// Renames the constraints Postgres named after `old` to follow it to
+    /// `table`.
+    fn rename_constraints(&mut self, old: &Table, table: &QualifiedName) {
+        let name = &table.name;
+        let mut renames = Vec::new();
+        if !old.primary_key().is_empty() {
+            renames.push((format!("{}_pkey", old.name()), format!("{}_pkey", name)));
+        }
+        for column in old.columns() {
+            if column.db_type().unique && !is_sole_key(old, column.name()) {
+                renames.push((format!("{}_{}_key", old.name(), column.name()), format!("{}_{}_key", name, column.name())));
+            }
+            if column.db_type().references.is_some() {
+                renames.push((format!("{}_{}_fkey", old.name(), column.name()), format!("{}_{}_fkey", name, column.name())));
+            }
+        }
+        for (from, to) in renames {
+            self.changes.push(Change::RenameConstraint { table: table.clone(), from, to });
+        }
+    }
+
+    fn table(&mut self, current: &Table, target: &Table) {
+        let table = target.qualified_name();
+        let old_columns = by_name(current.columns(), Column::name);
+        let old_names: Vec<&str> = current.columns().iter().map(Column::name).collect();
+        let new_names: Vec<&str> = target.columns().iter().map(Column::name).collect();
+        let renames = renames(
+            &old_names,
+            &new_names,
+            target.columns().iter().filter_map(|column| Some((column.renamed_from()?, column.name()))),
+            current.columns().iter().filter_map(|column| Some((column.renamed_from()?, column.name())))
+        );
+        let mut added = Vec::new();
+        for column in target.columns() {
+            let old_name = match renames.get(column.name()) {
+                Some(from) => {
+                    self.changes.push(Change::RenameColumn {
+                        table: table.clone(),
+                        from: from.to_string(),
+                        to: column.name().to_string()
+                    });
+                    let old_type = old_columns[from].db_type();
+                    let mut suffixes = Vec::new();
+                    if old_type.unique && !is_sole_key(current, from) {
+                        suffixes.push("key");
+                    }
+                    if old_type.references.is_some() {
+                        suffixes.push("fkey");
+                    }
+                    for suffix in suffixes {
+                        self.changes.push(Change::RenameConstraint {
+                            table: table.clone(),
+                            from: format!("{}_{}_{}", table.name, from, suffix),
+                            to: format!("{}_{}_{}", table.name, column.name(), suffix)
+                        });
+                    }
+                    from
+                }
+                None => column.name()
+            };
+            let old = match old_columns.get(old_name) {
+                Some(old) => self.renamed_type(old.db_type()),
+                None => {
+                    added.push(column);
+                    self.changes.push(Change::AddColumn { table: table.clone(), column: column.clone() });
+                    continue;
+                }
+            };
+            let new = column.db_type();
+            let column = column.name().to_string();
+            if !same_type(&old, new) {
+                self.changes.push(Change::AlterColumnType { table: table.clone(), column: column.clone(), db_type: new.clone() });
+            }
+            match (old.nullable, new.nullable) {
+                (true, false) => self.changes.push(Change::SetNotNull { table: table.clone(), column: column.clone() }),
+                (false, true) => self.changes.push(Change::DropNotNull { table: table.clone(), column: column.clone() }),
+                _ => ()
+            }
+            match (&old.references, &new.references) {
+                (Some(old), Some(new)) if old == new => (),
+                (old, new) => {
+                    if old.is_some() {
+                        self.changes.push(Change::DropForeignKey { table: table.clone(), column: column.clone() });
+                    }
+                    if let Some(references) = new {
+                        self.changes.push(Change::AddForeignKey {
+                            table: table.clone(),
+                            column: column.clone(),
+                            references: references.clone()
+                        });
+                    }
+                }
+            }
+            match (&old.default, &new.default) {
+                (Some(old), Some(new)) if same_expr(old, new) => (),
+                (_, Some(default)) => self.changes.push(Change::SetDefault {
+                    table: table.clone(),
+                    column: column.clone(),
+                    default: default.clone()
+                }),
+                (Some(_), None) => self.changes.push(Change::DropDefault { table: table.clone(), column: column.clone() }),
+                (None, None) => ()
+            }
+            match (old.identity, new.identity) {
+                (false, true) => self.changes.push(Change::AddIdentity { table: table.clone(), column: column.clone() }),
+                (true, false) => self.changes.push(Change::DropIdentity { table: table.clone(), column: column.clone() }),
+                _ => ()
+            }
+            match (old.unique && !is_sole_key(current, old_name), new.unique && !is_sole_key(target, &column)) {
+                (false, true) => self.changes.push(Change::AddUnique { table: table.clone(), column }),
+                (true, false) => self.changes.push(Change::DropUnique { table: table.clone(), column }),
+                _ => ()
+            }
+        }
+        let renamed: HashSet<&str> = renames.values().copied().collect();
+        for column in current.columns() {
+            if new_names.contains(&column.name()) || renamed.contains(column.name()) {
+                continue;
+            }
+            self.changes.push(Change::DropColumn { table: table.clone(), column: column.name().to_string() });
+            let old_type = self.renamed_type(column.db_type());
+            if let Some(added) = added.iter().find(|added| *added.db_type() == old_type) {
+                self.warnings.push(warn_rename(
+                    "column",
+                    &format!("{}.{}", table.name, column.name()),
+                    &format!("{}.{}", table.name, added.name()),
+                    column.name()
+                ));
+            }
+        }
+
+        let column_renames: HashMap<&str, &str> = renames.iter().map(|(new, old)| (*old, *new)).collect();
+        let old_key: Vec<&str> = current.primary_key().into_iter()
+            .map(|column| column_renames.get(column).copied().unwrap_or(column))
+            .collect();
+        if old_key != target.primary_key() {
+            if !old_key.is_empty() {
+                self.changes.push(Change::DropPrimaryKey { table: table.clone() });
+            }
+            if !target.primary_key().is_empty() {
+                let columns = target.primary_key().into_iter().map(str::to_string).collect();
+                self.changes.push(Change::AddPrimaryKey { table: table.clone(), columns });
+            }
+        }
+        self.uniques(current, target, &column_renames);
+        self.foreign_keys(current, target, &column_renames);
+
+        let old_checks = by_name(current.checks(), |check| &check.name);
+        let new_checks = by_name(target.checks(), |check| &check.name);
+        for check in target.checks() {
+            match old_checks.get(check.name.as_str()) {
+                Some(old) if same_expr(&old.expr, &check.expr) => (),
+                Some(_) => {
+                    self.changes.push(Change::DropCheck { table: table.clone(), name: check.name.clone() });
+                    self.changes.push(Change::AddCheck { table: table.clone(), check: check.clone() });
+                }
+                None => self.changes.push(Change::AddCheck { table: table.clone(), check: check.clone() })
+            }
+        }
+        for check in current.checks().iter().filter(|check| !new_checks.contains_key(check.name.as_str())) {
+            self.changes.push(Change::DropCheck { table: table.clone(), name: check.name.clone() });
+        }
+
+        self.indexes(&table, &current.all_indexes(), &target.all_indexes());
+    }
+
+    /// Constraints are matched by name once the renames of the table and its
+    /// columns are applied to the current ones, renaming those named after
+    /// what was renamed.
+    fn uniques(&mut self, current: &Table, target: &Table, column_renames: &HashMap<&str, &str>) {
+        let table = target.qualified_name();
+        let mut old_uniques = Vec::new();
+        for old in current.uniques() {
+            let columns: Vec<String> = old.columns.iter()
+                .map(|column| column_renames.get(column.as_str()).copied().unwrap_or(column).to_string())
+                .collect();
+            let name = if old.name == Unique::new(current.name(), old.columns.clone()).name {
+                Unique::new(&table.name, columns.clone()).name
+            } else {
+                old.name.clone()
+            };
+            if name != old.name {
+                self.changes.push(Change::RenameConstraint { table: table.clone(), from: old.name.clone(), to: name.clone() });
+            }
+            old_uniques.push(Unique { name, columns });
+        }
+        let old_by_name = by_name(&old_uniques, |unique| &unique.name);
+        let new_by_name = by_name(target.uniques(), |unique| &unique.name);
+        for unique in target.uniques() {
+            match old_by_name.get(unique.name.as_str()) {
+                Some(old) if old.columns == unique.columns => (),
+                Some(_) => {
+                    self.changes.push(Change::DropUniqueConstraint { table: table.clone(), name: unique.name.clone() });
+                    self.changes.push(Change::AddUniqueConstraint { table: table.clone(), unique: unique.clone() });
+                }
+                None => self.changes.push(Change::AddUniqueConstraint { table: table.clone(), unique: unique.clone() })
+            }
+        }
+        for unique in old_uniques.iter().filter(|unique| !new_by_name.contains_key(unique.name.as_str())) {
+            self.changes.push(Change::DropUniqueConstraint { table: table.clone(), name: unique.name.clone() });
+        }
+    }
+
+    /// Like [`Differ::uniques`], for the foreign keys spanning several
+    /// columns.
+    fn foreign_keys(&mut self, current: &Table, target: &Table, column_renames: &HashMap<&str, &str>) {
+        let table = target.qualified_name();
+        let mut old_foreign_keys = Vec::new();
+        for old in current.foreign_keys() {
+            let columns: Vec<String> = old.columns.iter()
+                .map(|column| column_renames.get(column.as_str()).copied().unwrap_or(column).to_string())
+                .collect();
+            let is_default = old.name == ForeignKeyConstraint::new(current.name(), old.columns.clone(), old.references.clone()).name;
+            let renamed = ForeignKeyConstraint::new(&table.name, columns, self.renamed_references(&old.references));
+            let name = if is_default { renamed.name.clone() } else { old.name.clone() };
+            if name != old.name {
+                self.changes.push(Change::RenameConstraint { table: table.clone(), from: old.name.clone(), to: name.clone() });
+            }
+            old_foreign_keys.push(ForeignKeyConstraint { name, ..renamed });
+        }
+        let old_by_name = by_name(&old_foreign_keys, |foreign_key| &foreign_key.name);
+        let new_by_name = by_name(target.foreign_keys(), |foreign_key| &foreign_key.name);
+        for foreign_key in target.foreign_keys() {
+            let add = Change::AddForeignKeyConstraint { table: table.clone(), foreign_key: foreign_key.clone() };
+            match old_by_name.get(foreign_key.name.as_str()) {
+                Some(old) if *old == foreign_key => (),
+                Some(_) => {
+                    self.changes.push(Change::DropForeignKeyConstraint { table: table.clone(), name: foreign_key.name.clone() });
+                    self.changes.push(add);
+                }
+                None => self.changes.push(add)
+            }
+        }
+        for foreign_key in old_foreign_keys.iter().filter(|foreign_key| !new_by_name.contains_key(foreign_key.name.as_str())) {
+            self.changes.push(Change::DropForeignKeyConstraint { table: table.clone(), name: foreign_key.name.clone() });
+        }
+    }
+
+    /// Indexes are matched by name, and an index that only changed name is
+    /// renamed rather than built again.
+    fn indexes(&mut self, table: &QualifiedName, current: &[Index], target: &[Index]) {
+        let index_name = |index: &Index| QualifiedName::new(table.schema.as_deref(), &index.name);
+        let old_indexes = by_name(current, |index| &index.name);
+        let new_indexes = by_name(target, |index| &index.name);
+        let mut dropped: Vec<&Index> = current.iter().filter(|index| !new_indexes.contains_key(index.name.as_str())).collect();
+        for index in target {
+            match old_indexes.get(index.name.as_str()) {
+                Some(old) if same_index(old, index) => (),
+                Some(_) => {
+                    self.changes.push(Change::DropIndex(index_name(index)));
+                    self.changes.push(Change::AddIndex { table: table.clone(), index: index.clone() });
+                }
+                None => match dropped.iter().position(|old| same_index(old, index)) {
+                    Some(position) => {
+                        let old = dropped.remove(position);
+                        self.changes.push(Change::RenameIndex { from: index_name(old), to: index.name.clone() });
+                    }
+                    None => self.changes.push(Change::AddIndex { table: table.clone(), index: index.clone() })
+                }
+            }
+        }
+        for index in dropped {
+            self.changes.push(Change::DropIndex(index_name(index)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_type(base: &str) -> DbType {
+        DbType { base: base.to_string(), ..DbType::default() }
+    }
+
+    fn table(name: &str, columns: &[(&str, &str)]) -> Table {
+        let mut all = vec![Column::new("id".to_string(), crate::schema::primary_key())];
+        all.extend(columns.iter().map(|(name, base)| Column::new(name.to_string(), db_type(base))));
+        Table::new(name.to_string(), all)
+    }
+
+    fn schema(tables: Vec<Table>, enums: Vec<EnumType>) -> Schema {
+        let mut schema = Schema::empty();
+        schema.add_enums(enums);
+        schema.add_tables(tables);
+        schema
+    }
+
+    fn mood(labels: &[&str]) -> EnumType {
+        EnumType {
+            name: "Mood".to_string(),
+            schema: None,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            renamed_labels: Vec::new()
+        }
+    }
+
+    #[test]
+    fn order_puts_types_before_tables_and_drops_last() {
+        let name = QualifiedName::new(None, "Post");
+        let mut changes = [
+            Change::DropEnum(QualifiedName::new(None, "Mood")),
+            Change::DropColumn { table: name.clone(), column: "old".to_string() },
+            Change::AddColumn { table: name.clone(), column: Column::new("new".to_string(), db_type("TEXT")) },
+            Change::AddCheck { table: name.clone(), check: Check { name: "Post_check".to_string(), expr: "TRUE".to_string() } },
+            Change::DropCheck { table: name.clone(), name: "Post_old_check".to_string() },
+            Change::CreateEnum(mood(&["happy"])),
+            Change::RenameTable { from: QualifiedName::new(None, "Article"), to: "Post".to_string() },
+            Change::CreateSchema("blog".to_string())
+        ];
+        changes.sort_by_key(Change::order);
+        let order: Vec<&str> = changes.iter().map(|change| match change {
+            Change::CreateSchema(_) => "CreateSchema",
+            Change::RenameTable { .. } => "RenameTable",
+            Change::CreateEnum(_) => "CreateEnum",
+            Change::DropCheck { .. } => "DropCheck",
+            Change::AddColumn { .. } => "AddColumn",
+            Change::AddCheck { .. } => "AddCheck",
+            Change::DropColumn { .. } => "DropColumn",
+            Change::DropEnum(_) => "DropEnum",
+            _ => unreachable!()
+        }).collect();
+        assert_eq!(
+            order,
+            ["CreateSchema", "RenameTable", "CreateEnum", "DropCheck", "AddColumn", "AddCheck", "DropColumn", "DropEnum"]
+        );
+    }
+
+    #[test]
+    fn diff_of_equal_schemas_is_empty() {
+        let schema = schema(vec![table("Post", &[("title", "TEXT")])], vec![mood(&["happy", "sad"])]);
+        assert!(schema.diff(&schema.clone()).is_empty());
+    }
+
+    #[test]
+    fn diff_alters_columns_and_types() {
+        let current = schema(vec![table("Post", &[("title", "TEXT"), ("views", "TEXT"), ("old", "TEXT")])], vec![mood(&["happy"])]);
+        let target = schema(
+            vec![table("Post", &[("title", "TEXT"), ("views", "BIGINT"), ("mood", "\"Mood\"")])],
+            vec![mood(&["happy", "sad"])]
+        );
+        let diff = target.diff(&current);
+        assert_eq!(diff.statements(), [
+            "ALTER TYPE \"Mood\" ADD VALUE IF NOT EXISTS 'sad'",
+            "ALTER TABLE \"Post\" ADD COLUMN \"mood\" \"Mood\" NOT NULL",
+            "ALTER TABLE \"Post\" ALTER COLUMN \"views\" TYPE BIGINT USING \"views\"::BIGINT",
+            "ALTER TABLE \"Post\" DROP COLUMN \"old\""
+        ]);
+        assert_eq!(diff.irreversible_statements(), ["ALTER TABLE \"Post\" DROP COLUMN \"old\""]);
+    }
+
+    #[test]
+    fn diff_creates_and_drops_tables() {
+        let current = schema(vec![table("Old", &[])], Vec::new());
+        let target = schema(vec![table("New", &[("name", "TEXT")]).with_schema(Some("blog".to_string()))], Vec::new());
+        assert_eq!(target.diff(&current).statements(), [
+            "CREATE SCHEMA IF NOT EXISTS \"blog\"",
+            "CREATE TABLE \"blog\".\"New\" (\"id\" UUID NOT NULL PRIMARY KEY UNIQUE, \"name\" TEXT NOT NULL)",
+            "DROP TABLE \"Old\""
+        ]);
+    }
+
+    #[test]
+    fn renamed_type_only_replaces_whole_names() {
+        let differ = Differ {
+            type_renames: vec![("\"Point\"".to_string(), "\"Position\"".to_string())],
+            ..Differ::default()
+        };
+        assert_eq!(differ.renamed_type(&db_type("\"Point\"")).base, "\"Position\"");
+        assert_eq!(differ.renamed_type(&db_type("\"Point\"[][3]")).base, "\"Position\"[][3]");
+        assert_eq!(differ.renamed_type(&db_type("\"geo\".\"Point\"")).base, "\"geo\".\"Point\"");
+        assert_eq!(differ.renamed_type(&db_type("\"Point\"\"s\"")).base, "\"Point\"\"s\"");
+    }
+}
@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A five character Postgres error code, as found in `SQLSTATE`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SqlState(Cow<'static, str>);
+
+impl SqlState {
+    pub const SERIALIZATION_FAILURE: SqlState = SqlState(Cow::Borrowed("40001"));
+    pub const DEADLOCK_DETECTED: SqlState = SqlState(Cow::Borrowed("40P01"));
+    pub const UNIQUE_VIOLATION: SqlState = SqlState(Cow::Borrowed("23505"));
+
+    pub fn new(code: impl Into<String>) -> Self {
+        SqlState(Cow::Owned(code.into()))
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SqlState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// An error reported by the database server or the driver talking to it.
+    Database {
+        code: Option<SqlState>,
+        message: String
+    },
+    /// A write was refused as it would have broken the unique constraint
+    /// or unique index called `constraint`, e.g. `User_email_key`.
+    UniqueViolation {
+        constraint: String,
+        message: String
+    },
+    /// A value could not be encoded for the column type it is sent as.
+    Encode(String),
+    /// A value sent by the database could not be decoded into the Rust type
+    /// it was read as.
+    Decode(String),
+    /// A `PlattEnum` value was read whose discriminant matches none of the
+    /// enum's variants.
+    UnknownDiscriminant {
+        type_name: &'static str,
+        discriminant: u32
+    },
+    /// A `PlattEnum` stored as a Postgres `ENUM` was read with a label that
+    /// matches none of its variants.
+    UnknownLabel {
+        type_name: &'static str,
+        label: String
+    },
+    /// A `NULL` was read into a type that is not an `Option`.
+    UnexpectedNull,
+    /// No connection was registered under the alias a router picked.
+    UnknownDatabase(String),
+    /// A query locking rows with `FOR UPDATE` or `FOR SHARE` was run outside a
+    /// transaction, where the locks would be released immediately.
+    LockOutsideTransaction,
+    /// A query's row lock can not be taken as asked, e.g. `nowait()` without
+    /// a lock to modify.
+    InvalidLock(String),
+    /// Results were streamed through a cursor outside a transaction, which
+    /// cursors only live in.
+    CursorOutsideTransaction,
+    /// The transaction was retried `attempts` times and failed every time.
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<Error>
+    },
+    Io(std::io::Error),
+    /// A migration file is missing, misnamed or malformed.
+    Migration(String),
+    /// The file of an applied migration no longer matches the checksum
+    /// recorded when it was applied.
+    MigrationEdited {
+        version: u32,
+        name: String
+    },
+    /// A migration drops data and has not been marked as acknowledged.
+    IrreversibleMigration {
+        version: u32,
+        name: String
+    },
+    /// Reverting a migration drops data and has not been acknowledged with
+    /// `Migrator::with_reverts_acknowledged`.
+    IrreversibleRevert {
+        version: u32,
+        name: String
+    }
+}
+
+impl Error {
+    pub fn database(code: Option<SqlState>, message: impl Into<String>) -> Self {
+        Error::Database { code, message: message.into() }
+    }
+
+    pub fn code(&self) -> Option<&SqlState> {
+        match self {
+            Error::Database { code, .. } => code.as_ref(),
+            Error::UniqueViolation { .. } => Some(&SqlState::UNIQUE_VIOLATION),
+            Error::RetriesExhausted { last, .. } => last.code(),
+            _ => None
+        }
+    }
+
+    /// The name of the constraint a [`Error::UniqueViolation`] broke.
+    pub fn constraint(&self) -> Option<&str> {
+        match self {
+            Error::UniqueViolation { constraint, .. } => Some(constraint),
+            Error::RetriesExhausted { last, .. } => last.constraint(),
+            _ => None
+        }
+    }
+
+    /// Whether re-running the whole transaction may succeed, i.e. the server
+    /// aborted it because of a serialization failure or a deadlock.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.code(), Some(code) if *code == SqlState::SERIALIZATION_FAILURE || *code == SqlState::DEADLOCK_DETECTED)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database { code: Some(code), message } => write!(f, "database error {}: {}", code, message),
+            Error::Database { code: None, message } => write!(f, "database error: {}", message),
+            Error::UniqueViolation { constraint, message } => {
+                write!(f, "unique constraint \"{}\" violated: {}", constraint, message)
+            }
+            Error::Encode(message) => write!(f, "could not encode value: {}", message),
+            Error::Decode(message) => write!(f, "could not decode value: {}", message),
+            Error::UnknownDiscriminant { type_name, discriminant } => {
+                write!(f, "unknown discriminant {} for {}", discriminant, type_name)
+            }
+            Error::UnknownLabel { type_name, label } => write!(f, "unknown label {:?} for {}", label, type_name),
+            Error::UnexpectedNull => write!(f, "unexpected NULL value"),
+            Error::UnknownDatabase(alias) => write!(f, "no connection for database \"{}\"", alias),
+            Error::LockOutsideTransaction => write!(f, "row locks can only be taken inside a transaction"),
+            Error::InvalidLock(message) => write!(f, "invalid row lock: {}", message),
+            Error::CursorOutsideTransaction => write!(f, "results can only be streamed inside a transaction"),
+            Error::RetriesExhausted { attempts, last } => write!(f, "transaction failed after {} attempts: {}", attempts, last),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Migration(message) => write!(f, "invalid migration: {}", message),
+            Error::MigrationEdited { version, name } => {
+                write!(f, "migration {:04}_{} was edited after it was applied", version, name)
+            }
+            Error::IrreversibleMigration { version, name } => write!(
+                f,
+                "migration {:04}_{} drops data and must be acknowledged before it is applied",
+                version, name
+            ),
+            Error::IrreversibleRevert { version, name } => write!(
+                f,
+                "reverting migration {:04}_{} drops data and must be acknowledged",
+                version, name
+            )
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::RetriesExhausted { last, .. } => Some(last.as_ref()),
+            Error::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,446 @@
+use std::marker::PhantomData;
+use crate::schema::{DbModel, ExactString, ForeignKey, Varchar};
+use crate::value::{Nestable, ToSql};
+
+type Param = Box<dyn ToSql + Send + Sync>;
+
+enum Fragment {
+    Sql(String),
+    Param(Param)
+}
+
+/// A boolean SQL expression used to filter a query. Values compared against
+/// are kept aside and sent as parameters.
+pub struct Condition {
+    fragments: Vec<Fragment>
+}
+
+impl Condition {
+    /// A condition written as raw SQL, without parameters.
+    pub fn sql(sql: impl Into<String>) -> Self {
+        Self { fragments: vec![Fragment::Sql(sql.into())] }
+    }
+
+    fn binary(expr: &str, op: &str, value: Param) -> Self {
+        Self {
+            fragments: vec![
+                Fragment::Sql(format!("{} {} ", expr, op)),
+                Fragment::Param(value)
+            ]
+        }
+    }
+
+    fn wrap(mut self, before: &str, after: &str) -> Self {
+        self.fragments.insert(0, Fragment::Sql(before.to_string()));
+        self.fragments.push(Fragment::Sql(after.to_string()));
+        self
+    }
+
+    fn join(self, op: &str, other: Condition) -> Self {
+        let mut fragments = self.wrap("(", ")").fragments;
+        fragments.push(Fragment::Sql(format!(" {} ", op)));
+        fragments.extend(other.wrap("(", ")").fragments);
+        Self { fragments }.wrap("(", ")")
+    }
+
+    pub fn and(self, other: Condition) -> Self {
+        self.join("AND", other)
+    }
+
+    pub fn or(self, other: Condition) -> Self {
+        self.join("OR", other)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        self.wrap("NOT (", ")")
+    }
+
+    /// Appends the condition to `sql`, numbering its parameters after the
+    /// ones already in `params`.
+    pub fn render<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        for fragment in &self.fragments {
+            match fragment {
+                Fragment::Sql(text) => sql.push_str(text),
+                Fragment::Param(value) => {
+                    params.push(&**value);
+                    sql.push_str(&format!("${}", params.len()));
+                }
+            }
+        }
+    }
+}
+
+/// A column, or a field of a composite column, holding values of type `T`.
+pub struct Field<T> {
+    expr: String,
+    _type: PhantomData<fn() -> T>
+}
+
+impl<T> Field<T> {
+    pub fn new(expr: String) -> Self {
+        Self { expr, _type: PhantomData }
+    }
+
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    pub fn is_null(&self) -> Condition {
+        Condition::sql(format!("{} IS NULL", self.expr))
+    }
+
+    pub fn is_not_null(&self) -> Condition {
+        Condition::sql(format!("{} IS NOT NULL", self.expr))
+    }
+}
+
+impl<T: ToSql + Send + Sync + 'static> Field<T> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn eq(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, "=", Box::new(value))
+    }
+
+    pub fn ne(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, "<>", Box::new(value))
+    }
+
+    pub fn lt(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, "<", Box::new(value))
+    }
+
+    pub fn le(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, "<=", Box::new(value))
+    }
+
+    pub fn gt(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, ">", Box::new(value))
+    }
+
+    pub fn ge(&self, value: T) -> Condition {
+        Condition::binary(&self.expr, ">=", Box::new(value))
+    }
+
+    pub fn is_in(&self, values: Vec<T>) -> Condition where T: Nestable {
+        Condition {
+            fragments: vec![
+                Fragment::Sql(format!("{} = ANY(", self.expr)),
+                Fragment::Param(Box::new(values)),
+                Fragment::Sql(")".to_string())
+            ]
+        }
+    }
+}
+
+/// Types compared with `LIKE`.
+pub trait TextLike { }
+impl TextLike for String { }
+impl<const SIZE: usize> TextLike for Varchar<SIZE> { }
+impl<const SIZE: usize> TextLike for ExactString<SIZE> { }
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+impl<T: TextLike> Field<T> {
+    /// Matches `pattern`, in which `%` and `_` are wildcards.
+    pub fn like(&self, pattern: &str) -> Condition {
+        Condition::binary(&self.expr, "LIKE", Box::new(pattern.to_string()))
+    }
+
+    pub fn ilike(&self, pattern: &str) -> Condition {
+        Condition::binary(&self.expr, "ILIKE", Box::new(pattern.to_string()))
+    }
+
+    pub fn contains(&self, value: &str) -> Condition {
+        self.like(&format!("%{}%", escape_like(value)))
+    }
+
+    pub fn starts_with(&self, value: &str) -> Condition {
+        self.like(&format!("{}%", escape_like(value)))
+    }
+
+    pub fn ends_with(&self, value: &str) -> Condition {
+        self.like(&format!("%{}", escape_like(value)))
+    }
+}
+
+/// Gives the handle used to build conditions on a column of this type from
+/// the SQL expression for the column.
+///
+/// Most types are filtered through a [`Field`]; the `PlattEnum` derive
+/// generates handles for conditions on variants and their fields.
+pub trait Filterable {
+    type Filter;
+    fn filter(expr: String) -> Self::Filter;
+
+    /// The handle for a model field called `name`, which most types store in
+    /// the column of that name.
+    fn column(name: &str) -> Self::Filter where Self: Sized {
+        Self::filter(format!("\"{}\"", name))
+    }
+}
+
+macro_rules! field_filterable {
+    ($($rust:ty),+) => {
+        $(
+            impl Filterable for $rust {
+                type Filter = Field<$rust>;
+                fn filter(expr: String) -> Self::Filter {
+                    Field::new(expr)
+                }
+            }
+        )+
+    };
+}
+
+field_filterable!(bool, u8, u16, u32, u64, i8, i16, i32, i64, String, f32, f64);
+field_filterable!(crate::schema::BitString);
+
+pub mod non_zero {
+    use std::num::*;
+    use super::*;
+    field_filterable!(NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64);
+}
+
+impl<const SIZE: usize> Filterable for Varchar<SIZE> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+impl<const SIZE: usize> Filterable for ExactString<SIZE> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+impl<const BEFORE: u16, const AFTER: u16> Filterable for crate::schema::Decimal<BEFORE, AFTER> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+impl<const SIZE: usize> Filterable for crate::schema::BitStringFixed<SIZE> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+impl<Other: DbModel> Filterable for ForeignKey<Other> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+/// A nullable column is filtered like the type it holds, with
+/// [`Field::is_null`] for the missing values.
+impl<T: Filterable> Filterable for Option<T> {
+    type Filter = T::Filter;
+    fn filter(expr: String) -> Self::Filter {
+        T::filter(expr)
+    }
+
+    fn column(name: &str) -> Self::Filter {
+        T::column(name)
+    }
+}
+
+impl<T> Filterable for Vec<T> {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+impl<T, const N: usize> Filterable for [T; N] {
+    type Filter = Field<Self>;
+    fn filter(expr: String) -> Self::Filter {
+        Field::new(expr)
+    }
+}
+
+#[cfg(feature = "chrono_type")]
+pub mod chrono_type {
+    use super::*;
+    field_filterable!(chrono::NaiveDate, chrono::NaiveTime, chrono::NaiveDateTime);
+    impl<Tz: chrono::TimeZone> Filterable for chrono::DateTime<Tz> {
+        type Filter = Field<Self>;
+        fn filter(expr: String) -> Self::Filter {
+            Field::new(expr)
+        }
+    }
+}
+
+#[cfg(feature = "uuid_type")]
+pub mod uuid_type {
+    use super::*;
+    field_filterable!(uuid::Uuid);
+}
+
+#[cfg(feature = "json")]
+pub mod json {
+    use super::*;
+    field_filterable!(crate::schema::json::LenientJson, serde_json::Value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QuerySet;
+    use crate::value::Format;
+    use crate::{PlattEnum, PlattModel};
+
+    #[derive(PlattEnum, Clone)]
+    enum Mood {
+        Happy,
+        Sad
+    }
+
+    #[derive(PlattEnum, Clone)]
+    enum Review {
+        Pending,
+        Done { score: i32, note: String }
+    }
+
+    #[derive(PlattEnum, Clone)]
+    #[platt(repr = "columns")]
+    enum Shape {
+        Circle { radius: i32 },
+        Square(i32)
+    }
+
+    #[derive(PlattModel)]
+    #[platt(table = "Post")]
+    struct Post {
+        title: Varchar<50>,
+        views: i64,
+        subtitle: Option<String>,
+        mood: Mood,
+        review: Review,
+        shape: Shape
+    }
+
+    fn sql(query: &QuerySet<(), Post>) -> (String, Vec<String>) {
+        let (sql, params) = query.to_sql_with_params();
+        let params = params.iter().map(|param| {
+            let mut out = Vec::new();
+            param.to_sql(Format::Text, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }).collect();
+        (sql, params)
+    }
+
+    fn posts() -> QuerySet<(), Post> {
+        QuerySet::new(())
+    }
+
+    #[test]
+    fn comparisons_number_their_params() {
+        let query = posts()
+            .filter(|post| post.views().ge(10).and(post.views().lt(20)))
+            .filter(|post| post.views().eq(1).or(post.views().ne(2).and(post.views().le(3))))
+            .filter(|post| post.views().gt(4))
+            .filter(|post| post.views().is_in(vec![5, 6]));
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE ((\"views\" >= $1) AND (\"views\" < $2)) \
+             AND ((\"views\" = $3) OR (((\"views\" <> $4) AND (\"views\" <= $5)))) \
+             AND \"views\" > $6 AND \"views\" = ANY($7)".to_string(),
+            ["10", "20", "1", "2", "3", "4", "{5,6}"].map(String::from).to_vec()
+        ));
+    }
+
+    #[test]
+    fn render_numbers_after_the_existing_params() {
+        let first = Field::<i32>::new("\"a\"".to_string()).eq(1);
+        let second = Field::<i32>::new("\"b\"".to_string()).eq(2);
+        let (mut sql, mut params) = (String::new(), Vec::new());
+        first.render(&mut sql, &mut params);
+        sql += ", ";
+        second.render(&mut sql, &mut params);
+        assert_eq!(sql, "\"a\" = $1, \"b\" = $2");
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn nullable_columns() {
+        let query = posts()
+            .filter(|post| post.subtitle().is_null())
+            .filter(|post| post.subtitle().is_not_null().or(post.subtitle().eq("a".to_string())));
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE \"subtitle\" IS NULL \
+             AND ((\"subtitle\" IS NOT NULL) OR (\"subtitle\" = $1))".to_string(),
+            vec!["a".to_string()]
+        ));
+    }
+
+    #[test]
+    fn like_escapes_wildcards() {
+        let query = posts()
+            .filter(|post| post.title().contains("50%_off\\"))
+            .filter(|post| post.title().starts_with("a_b"))
+            .filter(|post| post.title().ends_with("%"))
+            .filter(|post| post.subtitle().like("a%_"))
+            .filter(|post| post.subtitle().ilike("A%"));
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE \"title\" LIKE $1 AND \"title\" LIKE $2 AND \"title\" LIKE $3 \
+             AND \"subtitle\" LIKE $4 AND \"subtitle\" ILIKE $5".to_string(),
+            ["%50\\%\\_off\\\\%", "a\\_b%", "%\\%", "a%_", "A%"].map(String::from).to_vec()
+        ));
+    }
+
+    #[test]
+    fn exclude_negates() {
+        let query = posts()
+            .exclude(|post| post.views().eq(0))
+            .filter(|post| post.views().gt(1).not())
+            .exclude(|post| post.subtitle().is_null().or(post.views().lt(2)));
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE NOT (\"views\" = $1) AND NOT (\"views\" > $2) \
+             AND NOT (((\"subtitle\" IS NULL) OR (\"views\" < $3)))".to_string(),
+            ["0", "1", "2"].map(String::from).to_vec()
+        ));
+    }
+
+    #[test]
+    fn native_enum_variants() {
+        let query = posts().filter(|post| post.mood().is_happy()).exclude(|post| post.mood().is_sad());
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE \"mood\" = 'Happy' AND NOT (\"mood\" = 'Sad')".to_string(),
+            vec![]
+        ));
+    }
+
+    #[test]
+    fn composite_enum_variants_and_fields() {
+        let query = posts()
+            .filter(|post| post.review().is_done())
+            .filter(|post| post.review().done().score().ge(3))
+            .filter(|post| post.review().done().note().contains("good"))
+            .exclude(|post| post.review().is_pending());
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE (\"review\").\"Review\" = 1 \
+             AND ((\"review\").\"Done\").\"score\" >= $1 \
+             AND ((\"review\").\"Done\").\"note\" LIKE $2 \
+             AND NOT ((\"review\").\"Review\" = 0)".to_string(),
+            ["3", "%good%"].map(String::from).to_vec()
+        ));
+    }
+
+    #[test]
+    fn flattened_enum_variants_and_fields() {
+        let query = posts()
+            .filter(|post| post.shape().is_circle().and(post.shape().circle().radius().gt(2)))
+            .filter(|post| post.shape().is_square().or(post.shape().square()._0().eq(4)));
+        assert_eq!(sql(&query), (
+            "SELECT * FROM \"Post\" WHERE ((\"shape\" = 'Circle') AND (\"shape_circle_radius\" > $1)) \
+             AND ((\"shape\" = 'Square') OR (\"shape_square_0\" = $2))".to_string(),
+            ["2", "4"].map(String::from).to_vec()
+        ));
+    }
+}
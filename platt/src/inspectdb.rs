@@ -0,0 +1,545 @@
+//! Writes the Rust source of models matching an existing [`Schema`], e.g.
+//! one read with [`Schema::introspect`], to adopt platt on a database it did
+//! not create.
+//!
+//! Whatever has no equivalent in a model comes out with a `TODO` comment
+//! saying so, next to the closest model that can be written.
+
+use heck::CamelCase;
+use crate::schema::{Composite, DbType, EnumType, Index, ReferentialAction, References, Schema, Table, Unique};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become",
+    "box", "do", "final", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield"
+];
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+/// A valid identifier as close to `name` as possible, and whether it had to
+/// change.
+fn ident(name: &str) -> (String, bool) {
+    if is_ident(name) {
+        return (name.to_string(), false);
+    }
+    let mut ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || KEYWORDS.contains(&ident.as_str()) {
+        ident.insert(0, '_');
+    }
+    (ident, true)
+}
+
+/// The name of the Rust type for a type called `name` in the database.
+fn type_ident(name: &str) -> String {
+    ident(&name.to_camel_case()).0
+}
+
+/// Strips the quotes from a type name written as `"name"`, and the schema
+/// from one written as `"schema"."name"`.
+fn unquote(name: &str) -> Option<&str> {
+    let name = name.strip_prefix('"')?.strip_suffix('"')?;
+    name.rsplit("\".\"").next()
+}
+
+/// The `#[platt(schema = "...")]` attribute of a type in `schema`.
+fn schema_attr(schema: Option<&str>) -> String {
+    match schema {
+        Some(schema) => format!("#[platt(schema = {:?})]\n", schema),
+        None => String::new()
+    }
+}
+
+/// Turns database types into Rust ones, collecting what it can not map.
+struct Types<'a> {
+    schema: &'a Schema,
+    enum_composites: &'a [String],
+    todos: Vec<String>
+}
+
+impl Types<'_> {
+    /// The Rust type of a column or attribute of type `base`.
+    fn rust(&mut self, base: &str) -> String {
+        if let Some(element) = base.strip_suffix("[]") {
+            return format!("Vec<{}>", self.rust(element));
+        }
+        if let Some(name) = unquote(base) {
+            if self.schema.enums().iter().any(|enum_type| enum_type.name == name)
+                || self.enum_composites.iter().any(|composite| format!("{}__Composite", composite) == name) {
+                return type_ident(name.trim_end_matches("__Composite"));
+            }
+            self.todos.push(format!("the composite type {} has no Rust equivalent", base));
+            return "String".to_string();
+        }
+        let (name, modifier) = match base.split_once('(') {
+            Some((name, modifier)) => (name, modifier.trim_end_matches(')')),
+            None => (base, "")
+        };
+        let mut modifiers = modifier.split(", ");
+        match (name, modifiers.next(), modifiers.next()) {
+            ("SMALLINT", ..) => "i16".to_string(),
+            ("INT", ..) => "i32".to_string(),
+            ("BIGINT", ..) => "i64".to_string(),
+            ("REAL", ..) => "f32".to_string(),
+            ("DOUBLE PRECISION", ..) => "f64".to_string(),
+            ("BOOLEAN", ..) => "bool".to_string(),
+            ("TEXT", ..) => "String".to_string(),
+            ("VARCHAR", Some(size), None) if !size.is_empty() => format!("platt::schema::Varchar<{}>", size),
+            ("CHAR", Some(size), None) if !size.is_empty() => format!("platt::schema::ExactString<{}>", size),
+            ("DECIMAL", Some(before), Some(after)) => format!("platt::schema::Decimal<{}, {}>", before, after),
+            ("BIT", Some(size), None) if !size.is_empty() => format!("platt::schema::BitStringFixed<{}>", size),
+            ("BIT VARYING", ..) => "platt::schema::BitString".to_string(),
+            ("DATE", ..) => "chrono::NaiveDate".to_string(),
+            ("TIME", ..) => "chrono::NaiveTime".to_string(),
+            ("TIMESTAMP", ..) => "chrono::NaiveDateTime".to_string(),
+            ("TIMESTAMPTZ", ..) => "chrono::DateTime<chrono::Utc>".to_string(),
+            ("UUID", ..) => "uuid::Uuid".to_string(),
+            ("JSONB", ..) => "serde_json::Value".to_string(),
+            ("JSON", ..) => "platt::schema::json::LenientJson".to_string(),
+            _ => {
+                self.todos.push(format!("{} has no Rust equivalent", base));
+                "String".to_string()
+            }
+        }
+    }
+
+    /// What a column of type `db_type` references, if a [`ForeignKey`]
+    /// can: the whole primary key of the other table, when it is a single
+    /// column of the same type.
+    ///
+    /// [`ForeignKey`]: crate::schema::ForeignKey
+    fn foreign_key<'b>(&self, db_type: &'b DbType) -> Option<&'b References> {
+        let references = db_type.references.as_ref()?;
+        let table = self.schema.tables().iter().find(|table| {
+            table.name() == references.table && table.schema() == references.schema.as_deref()
+        })?;
+        let key = table.columns().iter().find(|column| column.db_type().primary_key)?;
+        let is_key = table.primary_key() == references.columns.iter().map(String::as_str).collect::<Vec<_>>()
+            && key.db_type().base == db_type.base;
+        if is_key { Some(references) } else { None }
+    }
+
+    /// The Rust type of a column of type `db_type`.
+    fn field(&mut self, db_type: &DbType) -> String {
+        let rust = match &db_type.references {
+            Some(references) if self.foreign_key(db_type).is_some() => {
+                format!("platt::schema::ForeignKey<{}>", type_ident(&references.table))
+            }
+            Some(references) => {
+                self.todos.push(format!(
+                    "references \"{}\"(\"{}\"), but a ForeignKey<{}> can only reference its primary key",
+                    references.table, references.columns.join("\", \""), type_ident(&references.table)
+                ));
+                self.rust(&db_type.base)
+            }
+            None => self.rust(&db_type.base)
+        };
+        if db_type.nullable {
+            format!("Option<{}>", rust)
+        } else {
+            rust
+        }
+    }
+
+    /// The `TODO` comments for what the last types could not map.
+    fn take_todos(&mut self, indent: &str) -> String {
+        self.todos.drain(..).map(|todo| format!("{}// TODO: {}\n", indent, todo)).collect()
+    }
+}
+
+/// The names of the enums stored as platt's composite layout: a
+/// `<Enum>__Composite` type holding an `INT` discriminant named `<Enum>`,
+/// then a `<Enum>_<Variant>` composite per variant with data.
+fn enum_composites(schema: &Schema) -> Vec<String> {
+    schema.composites().iter().filter_map(|composite| {
+        let name = composite.name.strip_suffix("__Composite")?;
+        let (discriminant, variants) = composite.fields.split_first()?;
+        let is_layout = discriminant.0 == name
+            && discriminant.1.base == "INT"
+            && variants.iter().all(|(variant, db_type)| {
+                unquote(&db_type.base) == Some(format!("{}_{}", name, variant).as_str())
+            });
+        if is_layout { Some(name.to_string()) } else { None }
+    }).collect()
+}
+
+/// Whether the composite type `composite` is part of the layout of the enum
+/// `name`.
+fn is_enum_part(schema: &Schema, name: &str, composite: &str) -> bool {
+    let layout = format!("{}__Composite", name);
+    composite == layout || schema.composites().iter()
+        .filter(|candidate| candidate.name == layout)
+        .flat_map(|layout| &layout.fields[1..])
+        .any(|(variant, _)| composite == format!("{}_{}", name, variant))
+}
+
+fn native_enum(enum_type: &EnumType, out: &mut String) {
+    let name = type_ident(&enum_type.name);
+    if name != enum_type.name {
+        out.push_str(&format!("// TODO: the enum is called \"{}\" in the database\n", enum_type.name));
+    }
+    out.push_str(&format!("#[derive(PlattEnum)]\n{}pub enum {} {{\n", schema_attr(enum_type.schema.as_deref()), name));
+    let variants: Vec<String> = enum_type.labels.iter().map(|label| {
+        let (variant, changed) = ident(label);
+        if changed {
+            format!("    // TODO: the label is {:?} in the database\n    {}", label, variant)
+        } else {
+            format!("    {}", variant)
+        }
+    }).collect();
+    out.push_str(&variants.join(",\n"));
+    out.push_str("\n}\n\n");
+}
+
+fn composite_enum(schema: &Schema, name: &str, types: &mut Types, out: &mut String) {
+    let composite = schema.composites().iter()
+        .find(|composite| composite.name == format!("{}__Composite", name))
+        .expect("enum_composites only returns enums with a layout composite");
+    out.push_str(&format!(
+        "#[derive(PlattEnum)]\n{}pub enum {} {{\n",
+        schema_attr(composite.schema.as_deref()),
+        type_ident(name)
+    ));
+    out.push_str("    // TODO: unit variants are not stored in the database, add them back where they were declared\n");
+    let mut variants = Vec::new();
+    for (variant, _) in &composite.fields[1..] {
+        let fields = schema.composites().iter()
+            .find(|composite| composite.name == format!("{}_{}", name, variant))
+            .map_or(&[][..], |composite| &composite.fields[..]);
+        let variant = ident(variant).0;
+        let tuple = fields.iter().enumerate().all(|(position, (field, _))| *field == format!("_{}", position));
+        if tuple {
+            let fields: Vec<String> = fields.iter().map(|(_, db_type)| types.rust(&db_type.base)).collect();
+            variants.push(format!("{}    {}({})", types.take_todos("    "), variant, fields.join(", ")));
+        } else {
+            let fields: Vec<String> = fields.iter().map(|(field, db_type)| {
+                let rust = types.rust(&db_type.base);
+                format!("{}        {}: {}", types.take_todos("        "), ident(field).0, rust)
+            }).collect();
+            variants.push(format!("    {} {{\n{}\n    }}", variant, fields.join(",\n")));
+        }
+    }
+    out.push_str(&variants.join(",\n"));
+    out.push_str("\n}\n\n");
+}
+
+fn other_composite(composite: &Composite, out: &mut String) {
+    out.push_str(&format!(
+        "// TODO: the composite type \"{}\" has no Rust equivalent, it holds:\n",
+        composite.name
+    ));
+    for (field, db_type) in &composite.fields {
+        out.push_str(&format!("//     {} {}\n", field, db_type.base));
+    }
+    out.push('\n');
+}
+
+fn model(table: &Table, types: &mut Types, out: &mut String) {
+    let name = type_ident(table.name());
+    // A UUID `id` primary key is the one models get when no field is marked
+    // `primary_key`.
+    let has_id = table.primary_key() == ["id"] && table.columns().iter().any(|column| {
+        column.name() == "id" && column.db_type().base == "UUID" && column.db_type().unique && !column.db_type().nullable
+    });
+    if table.primary_key().is_empty() {
+        out.push_str("// TODO: the table has no primary key, the model is given a UUID \"id\" one\n");
+    }
+    for foreign_key in table.foreign_keys() {
+        out.push_str(&format!(
+            "// TODO: \"{}\" references \"{}\"(\"{}\"), a ForeignKey<{}> field stores it as <field>_<key column>\n",
+            foreign_key.columns.join("\", \""),
+            foreign_key.references.table,
+            foreign_key.references.columns.join("\", \""),
+            type_ident(&foreign_key.references.table)
+        ));
+    }
+    out.push_str("#[derive(PlattModel)]\n#[platt(not_clonable)]\n");
+    if name != table.name() {
+        out.push_str(&format!("#[platt(table = {:?})]\n", table.name()));
+    }
+    out.push_str(&schema_attr(table.schema()));
+    let is_column = |key: &str| table.columns().iter().any(|column| column.name() == key);
+    let field = |column: &str| format!("{:?}", ident(column).0);
+    for unique in table.uniques() {
+        let fields: Vec<String> = unique.columns.iter().map(|column| field(column)).collect();
+        let mut options = vec![format!("fields = [{}]", fields.join(", "))];
+        if unique.name != Unique::new(table.name(), unique.columns.clone()).name {
+            options.push(format!("name = {:?}", unique.name));
+        }
+        out.push_str(&format!("#[platt(unique({}))]\n", options.join(", ")));
+    }
+    for check in table.checks() {
+        let expr: Vec<&str> = check.expr.split_whitespace().collect();
+        out.push_str(&format!("#[platt(check(name = {:?}, expr = {:?}))]\n", check.name, expr.join(" ")));
+    }
+    // Plain indexes on a single column become `index` on its field.
+    let (column_indexes, indexes): (Vec<&Index>, Vec<&Index>) = table.indexes().iter().partition(|index| {
+        index.keys.len() == 1
+            && is_column(&index.keys[0])
+            && !index.unique
+            && index.using.is_none()
+            && index.predicate.is_none()
+            && index.name == Index::new(table.name(), index.keys.clone()).name
+    });
+    for index in indexes {
+        let keys: Vec<String> = index.keys.iter()
+            .map(|key| if is_column(key) { field(key) } else { format!("{:?}", key) })
+            .collect();
+        let mut options = vec![format!("fields = [{}]", keys.join(", "))];
+        if index.unique {
+            options.push("unique".to_string());
+        }
+        if let Some(using) = &index.using {
+            options.push(format!("using = {:?}", using));
+        }
+        if let Some(predicate) = &index.predicate {
+            options.push(format!("where = {:?}", predicate));
+        }
+        if index.name != Index::new(table.name(), index.keys.clone()).name {
+            options.push(format!("name = {:?}", index.name));
+        }
+        out.push_str(&format!("#[platt(index({}))]\n", options.join(", ")));
+    }
+    out.push_str(&format!("pub struct {} {{\n", name));
+    let mut fields = Vec::new();
+    for column in table.columns() {
+        let db_type = column.db_type();
+        if column.name() == "id" && has_id {
+            continue;
+        }
+        if column.name() == "id" && table.primary_key().is_empty() {
+            fields.push(format!("    // TODO: \"id\" {} clashes with the id the model is given", db_type.base));
+            continue;
+        }
+        let mut field = String::new();
+        let mut options = Vec::new();
+        let (field_name, changed) = ident(column.name());
+        if changed {
+            options.push(format!("column = {:?}", column.name()));
+        }
+        if db_type.primary_key {
+            options.push("primary_key".to_string());
+        }
+        if db_type.identity {
+            options.push("identity".to_string());
+        }
+        if db_type.unique && !(db_type.primary_key && table.primary_key().len() == 1) {
+            options.push("unique".to_string());
+        }
+        if column_indexes.iter().any(|index| index.keys[0] == column.name()) {
+            options.push("index".to_string());
+        }
+        if let Some(default) = &db_type.default {
+            options.push(format!("server_default = {:?}", default));
+        }
+        if let Some(references) = types.foreign_key(db_type) {
+            let action = |action: ReferentialAction| match action {
+                ReferentialAction::Cascade => "cascade",
+                ReferentialAction::SetNull => "set_null",
+                ReferentialAction::SetDefault => "set_default",
+                ReferentialAction::Restrict => "restrict"
+            };
+            if let Some(on_delete) = references.on_delete {
+                options.push(format!("on_delete = \"{}\"", action(on_delete)));
+            }
+            if let Some(on_update) = references.on_update {
+                options.push(format!("on_update = \"{}\"", action(on_update)));
+            }
+            if references.deferrable {
+                options.push("deferrable".to_string());
+            }
+        }
+        let rust = types.field(db_type);
+        field.push_str(&types.take_todos("    "));
+        if !options.is_empty() {
+            field.push_str(&format!("    #[platt({})]\n", options.join(", ")));
+        }
+        field.push_str(&format!("    {}: {}", field_name, rust));
+        fields.push(field);
+    }
+    out.push_str(&fields.join(",\n"));
+    out.push_str("\n}\n\n");
+}
+
+/// The source of a module declaring a model per table of `schema`, an enum
+/// per enum type and per composite type laid out the way
+/// [`PlattEnum`](crate::PlattEnum) stores enums with data, and a call to
+/// [`activate_models!`](crate::activate_models).
+pub fn generate(schema: &Schema) -> String {
+    let enum_composites = enum_composites(schema);
+    let mut types = Types { schema, enum_composites: &enum_composites, todos: Vec::new() };
+    let mut out = String::from("use platt::{PlattEnum, PlattModel};\n\n");
+    for enum_type in schema.enums() {
+        native_enum(enum_type, &mut out);
+    }
+    for name in &enum_composites {
+        composite_enum(schema, name, &mut types, &mut out);
+    }
+    for composite in schema.composites() {
+        if !enum_composites.iter().any(|name| is_enum_part(schema, name, &composite.name)) {
+            other_composite(composite, &mut out);
+        }
+    }
+    for table in schema.tables() {
+        model(table, &mut types, &mut out);
+    }
+    let models: Vec<String> = schema.tables().iter().map(|table| type_ident(table.name())).collect();
+    if !models.is_empty() {
+        out.push_str(&format!("platt::activate_models!({});\n", models.join(", ")));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{primary_key, Column};
+
+    fn db_type(base: &str) -> DbType {
+        DbType { base: base.to_string(), ..DbType::default() }
+    }
+
+    fn nullable(base: &str) -> DbType {
+        DbType { nullable: true, ..db_type(base) }
+    }
+
+    fn references(base: &str, table: &str, column: &str) -> DbType {
+        DbType { references: Some(References::new(table.to_string(), vec![column.to_string()])), ..db_type(base) }
+    }
+
+    fn table(name: &str, columns: Vec<(&str, DbType)>) -> Table {
+        let mut all = vec![Column::new("id".to_string(), primary_key())];
+        all.extend(columns.into_iter().map(|(name, db_type)| Column::new(name.to_string(), db_type)));
+        Table::new(name.to_string(), all)
+    }
+
+    fn composite(name: &str, fields: Vec<(&str, &str)>) -> Composite {
+        Composite {
+            name: name.to_string(),
+            schema: None,
+            fields: fields.into_iter().map(|(field, base)| (field.to_string(), db_type(base))).collect(),
+            renamed_from: None,
+            renamed_fields: Vec::new()
+        }
+    }
+
+    #[test]
+    fn maps_column_types() {
+        let mut schema = Schema::empty();
+        schema.add_tables(vec![table("Post", vec![
+            ("title", db_type("VARCHAR(80)")),
+            ("price", db_type("DECIMAL(10, 2)")),
+            ("subtitle", nullable("TEXT")),
+            ("tags", db_type("TEXT[]")),
+            ("address", db_type("INET"))
+        ])]);
+        assert_eq!(generate(&schema), r#"use platt::{PlattEnum, PlattModel};
+
+#[derive(PlattModel)]
+#[platt(not_clonable)]
+pub struct Post {
+    title: platt::schema::Varchar<80>,
+    price: platt::schema::Decimal<10, 2>,
+    subtitle: Option<String>,
+    tags: Vec<String>,
+    // TODO: INET has no Rust equivalent
+    address: String
+}
+
+platt::activate_models!(Post);
+"#);
+    }
+
+    #[test]
+    fn maps_references_to_primary_keys() {
+        let mut schema = Schema::empty();
+        schema.add_tables(vec![
+            table("Author", vec![("email", DbType { unique: true, ..db_type("TEXT") })]),
+            table("Post", vec![
+                ("author", references("UUID", "Author", "id")),
+                ("editor", DbType { nullable: true, ..references("UUID", "Author", "id") }),
+                ("author_email", references("TEXT", "Author", "email"))
+            ])
+        ]);
+        assert_eq!(generate(&schema), r#"use platt::{PlattEnum, PlattModel};
+
+#[derive(PlattModel)]
+#[platt(not_clonable)]
+pub struct Author {
+    #[platt(unique)]
+    email: String
+}
+
+#[derive(PlattModel)]
+#[platt(not_clonable)]
+pub struct Post {
+    author: platt::schema::ForeignKey<Author>,
+    editor: Option<platt::schema::ForeignKey<Author>>,
+    // TODO: references "Author"("email"), but a ForeignKey<Author> can only reference its primary key
+    author_email: String
+}
+
+platt::activate_models!(Author, Post);
+"#);
+    }
+
+    #[test]
+    fn turns_enum_types_back_into_enums() {
+        let mut schema = Schema::empty();
+        schema.add_enums(vec![EnumType {
+            name: "mood".to_string(),
+            schema: None,
+            labels: vec!["Happy".to_string(), "not sure".to_string()],
+            renamed_labels: Vec::new()
+        }]);
+        schema.add_composites(vec![
+            composite("Status__Composite", vec![("Status", "INT"), ("Published", "\"Status_Published\""), ("Moved", "\"Status_Moved\"")]),
+            composite("Status_Published", vec![("at", "TIMESTAMPTZ")]),
+            composite("Status_Moved", vec![("_0", "TEXT"), ("_1", "INT")]),
+            composite("Point", vec![("x", "INT")])
+        ]);
+        schema.add_tables(vec![table("Post", vec![
+            ("mood", db_type("\"mood\"")),
+            ("status", db_type("\"Status__Composite\"")),
+            ("at", db_type("\"Point\""))
+        ])]);
+        assert_eq!(generate(&schema), r#"use platt::{PlattEnum, PlattModel};
+
+// TODO: the enum is called "mood" in the database
+#[derive(PlattEnum)]
+pub enum Mood {
+    Happy,
+    // TODO: the label is "not sure" in the database
+    not_sure
+}
+
+#[derive(PlattEnum)]
+pub enum Status {
+    // TODO: unit variants are not stored in the database, add them back where they were declared
+    Published {
+        at: chrono::DateTime<chrono::Utc>
+    },
+    Moved(String, i32)
+}
+
+// TODO: the composite type "Point" has no Rust equivalent, it holds:
+//     x INT
+
+#[derive(PlattModel)]
+#[platt(not_clonable)]
+pub struct Post {
+    mood: Mood,
+    status: Status,
+    // TODO: the composite type "Point" has no Rust equivalent
+    at: String
+}
+
+platt::activate_models!(Post);
+"#);
+    }
+}
@@ -0,0 +1,364 @@
+use crate::db::{Connection, Row};
+use crate::error::Result;
+use crate::migrate::MIGRATIONS_TABLE;
+use crate::schema::{
+    schema_name, Check, Column, Composite, DbType, EnumType, ForeignKeyConstraint, Index, QualifiedName, ReferentialAction,
+    References, Schema, Table, Unique
+};
+use crate::value::FromSql;
+
+const ENUMS: &str = "\
+    SELECT n.nspname::text AS schema, t.typname::text AS name, e.enumlabel::text AS label \
+    FROM pg_type t \
+    JOIN pg_namespace n ON n.oid = t.typnamespace \
+    JOIN pg_enum e ON e.enumtypid = t.oid \
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_%' \
+    ORDER BY t.oid, e.enumsortorder";
+
+const COMPOSITES: &str = "\
+    SELECT n.nspname::text AS schema, t.typname::text AS name, a.attname::text AS field, \
+        format_type(a.atttypid, a.atttypmod) AS type, \
+        CASE WHEN ut.typtype IN ('e', 'c') THEN ut.typname::text END AS user_type, un.nspname::text AS user_schema \
+    FROM pg_type t \
+    JOIN pg_namespace n ON n.oid = t.typnamespace \
+    JOIN pg_class c ON c.oid = t.typrelid \
+    JOIN pg_attribute a ON a.attrelid = c.oid \
+    JOIN pg_type at ON at.oid = a.atttypid \
+    JOIN pg_type ut ON ut.oid = CASE WHEN at.typcategory = 'A' THEN at.typelem ELSE at.oid END \
+    JOIN pg_namespace un ON un.oid = ut.typnamespace \
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_%' AND t.typtype = 'c' AND c.relkind = 'c' AND a.attnum > 0 AND NOT a.attisdropped \
+    ORDER BY t.oid, a.attnum";
+
+const COLUMNS: &str = "\
+    SELECT n.nspname::text AS schema, c.relname::text AS table, a.attname::text AS column, \
+        format_type(a.atttypid, a.atttypmod) AS type, \
+        CASE WHEN ut.typtype IN ('e', 'c') THEN ut.typname::text END AS user_type, un.nspname::text AS user_schema, \
+        a.attnotnull AS not_null, a.attidentity <> '' AS identity, \
+        pg_get_expr(d.adbin, d.adrelid) AS default_expr \
+    FROM pg_class c \
+    JOIN pg_namespace n ON n.oid = c.relnamespace \
+    JOIN pg_attribute a ON a.attrelid = c.oid \
+    LEFT JOIN pg_attrdef d ON d.adrelid = c.oid AND d.adnum = a.attnum \
+    JOIN pg_type at ON at.oid = a.atttypid \
+    JOIN pg_type ut ON ut.oid = CASE WHEN at.typcategory = 'A' THEN at.typelem ELSE at.oid END \
+    JOIN pg_namespace un ON un.oid = ut.typnamespace \
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_%' AND c.relkind IN ('r', 'p') \
+        AND NOT (n.nspname = 'public' AND c.relname = $1) \
+        AND a.attnum > 0 AND NOT a.attisdropped \
+    ORDER BY c.oid, a.attnum";
+
+const CONSTRAINTS: &str = "\
+    SELECT n.nspname::text AS schema, c.relname::text AS table, con.conname::text AS name, con.contype::text AS kind, \
+        pg_get_constraintdef(con.oid) AS definition, \
+        ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY k(attnum, n) \
+            JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum ORDER BY k.n) AS columns, \
+        fn.nspname::text AS foreign_schema, fc.relname::text AS foreign_table, \
+        ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY k(attnum, n) \
+            JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum ORDER BY k.n) AS foreign_columns, \
+        con.confdeltype::text AS on_delete, con.confupdtype::text AS on_update, con.condeferred AS deferred \
+    FROM pg_constraint con \
+    JOIN pg_class c ON c.oid = con.conrelid \
+    JOIN pg_namespace n ON n.oid = c.relnamespace \
+    LEFT JOIN pg_class fc ON fc.oid = con.confrelid \
+    LEFT JOIN pg_namespace fn ON fn.oid = fc.relnamespace \
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_%' AND NOT (n.nspname = 'public' AND c.relname = $1) \
+    ORDER BY con.oid";
+
+const INDEXES: &str = "\
+    SELECT n.nspname::text AS schema, c.relname::text AS table, ic.relname::text AS name, i.indisunique AS unique, am.amname::text AS using, \
+        ARRAY(SELECT pg_get_indexdef(i.indexrelid, k, true) FROM generate_series(1, i.indnkeyatts) k) AS keys, \
+        pg_get_expr(i.indpred, i.indrelid, true) AS predicate \
+    FROM pg_index i \
+    JOIN pg_class c ON c.oid = i.indrelid \
+    JOIN pg_namespace n ON n.oid = c.relnamespace \
+    JOIN pg_class ic ON ic.oid = i.indexrelid \
+    JOIN pg_am am ON am.oid = ic.relam \
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_%' AND NOT (n.nspname = 'public' AND c.relname = $1) \
+        AND NOT EXISTS (SELECT 1 FROM pg_constraint con WHERE con.conindid = i.indexrelid) \
+    ORDER BY i.indexrelid";
+
+/// The spelling [`HasDbType`](crate::schema::HasDbType) uses for a type
+/// `format_type` printed as `format`, where `user_type` names the enum or
+/// composite type it is, or is an array of, and `user_schema` the schema
+/// that type lives in.
+///
+/// Built-in types are spelled as a model would write them, so that a column
+/// matching its model is not reported as drift. Those not listed are kept as
+/// Postgres prints them, in uppercase.
+fn base_type(format: &str, user_type: Option<String>, user_schema: Option<String>) -> String {
+    let element = format.trim_end_matches("[]");
+    let dimensions = &format[element.len()..];
+    if let Some(user_type) = user_type {
+        return format!("{}{}", QualifiedName::new(schema_name(user_schema).as_deref(), &user_type), dimensions);
+    }
+    // The modifier comes before the time zone of times, as in
+    // `timestamp(3) with time zone`, and after the fields of intervals, as
+    // in `interval second(3)`.
+    let (name, modifier) = match element.split_once('(') {
+        Some((before, rest)) => match rest.split_once(')') {
+            Some((modifier, after)) => (format!("{}{}", before, after), Some(modifier)),
+            None => (element.to_string(), None)
+        },
+        None => (element.to_string(), None)
+    };
+    let name = match name.as_str() {
+        "smallint" => "SMALLINT".to_string(),
+        "integer" => "INT".to_string(),
+        "bigint" => "BIGINT".to_string(),
+        "real" => "REAL".to_string(),
+        "double precision" => "DOUBLE PRECISION".to_string(),
+        "boolean" => "BOOLEAN".to_string(),
+        "text" => "TEXT".to_string(),
+        "character varying" => "VARCHAR".to_string(),
+        "character" => "CHAR".to_string(),
+        "numeric" => "DECIMAL".to_string(),
+        "bit" => "BIT".to_string(),
+        "bit varying" => "BIT VARYING".to_string(),
+        "bytea" => "BYTEA".to_string(),
+        "date" => "DATE".to_string(),
+        "time without time zone" => "TIME".to_string(),
+        "time with time zone" => "TIMETZ".to_string(),
+        "timestamp without time zone" => "TIMESTAMP".to_string(),
+        "timestamp with time zone" => "TIMESTAMPTZ".to_string(),
+        "uuid" => "UUID".to_string(),
+        "json" => "JSON".to_string(),
+        "jsonb" => "JSONB".to_string(),
+        // Such as `interval`, `interval year to month` or `inet`, which
+        // are spelled the same in SQL.
+        name => name.to_uppercase()
+    };
+    match modifier {
+        Some(modifier) => format!("{}({}){}", name, modifier.replace(',', ", "), dimensions),
+        None => format!("{}{}", name, dimensions)
+    }
+}
+
+/// The action `pg_constraint` spells as `code`, where `a` is `NO ACTION`.
+fn action(code: &str) -> Option<ReferentialAction> {
+    match code {
+        "c" => Some(ReferentialAction::Cascade),
+        "n" => Some(ReferentialAction::SetNull),
+        "d" => Some(ReferentialAction::SetDefault),
+        "r" => Some(ReferentialAction::Restrict),
+        _ => None
+    }
+}
+
+fn get<T: FromSql>(row: &Row, column: &str) -> Result<T> {
+    T::from_row(row, column)
+}
+
+/// What the foreign key constraint in `row` references.
+fn references(row: &Row) -> Result<References> {
+    Ok(References {
+        schema: schema_name(get(row, "foreign_schema")?),
+        table: get(row, "foreign_table")?,
+        columns: get(row, "foreign_columns")?,
+        on_delete: action(&get::<String>(row, "on_delete")?),
+        on_update: action(&get::<String>(row, "on_update")?),
+        deferrable: get(row, "deferred")?
+    })
+}
+
+/// The table or type named in the `name` column of `row`, in the schema
+/// named in its `schema` column.
+fn qualified(row: &Row, schema: &str, name: &str) -> Result<QualifiedName> {
+    Ok(QualifiedName { schema: schema_name(get(row, schema)?), name: get(row, name)? })
+}
+
+impl Schema {
+    /// Reads the enums, composite types and tables of the database `conn` is
+    /// connected to, in every schema but those Postgres keeps its catalogs
+    /// in, leaving out [`MIGRATIONS_TABLE`].
+    ///
+    /// Types are spelled the way the models spell them, so diffing a model
+    /// schema against this one lists the changes the database is missing.
+    /// Postgres does not enforce `NOT NULL` on the attributes of composite
+    /// types, so they are all read as nullable.
+    pub async fn introspect<C: Connection + ?Sized>(conn: &mut C) -> Result<Schema> {
+        let mut enums: Vec<EnumType> = Vec::new();
+        for row in conn.query(ENUMS, &[]).await? {
+            let name = qualified(&row, "schema", "name")?;
+            let label = get(&row, "label")?;
+            match enums.last_mut() {
+                Some(enum_type) if enum_type.qualified_name() == name => enum_type.labels.push(label),
+                _ => enums.push(EnumType {
+                    name: name.name,
+                    schema: name.schema,
+                    labels: vec![label],
+                    renamed_labels: Vec::new()
+                })
+            }
+        }
+
+        let mut composites: Vec<Composite> = Vec::new();
+        for row in conn.query(COMPOSITES, &[]).await? {
+            let name = qualified(&row, "schema", "name")?;
+            let field = get(&row, "field")?;
+            let db_type = DbType {
+                base: base_type(&get::<String>(&row, "type")?, get(&row, "user_type")?, get(&row, "user_schema")?),
+                nullable: true,
+                ..DbType::default()
+            };
+            match composites.last_mut() {
+                Some(composite) if composite.qualified_name() == name => composite.fields.push((field, db_type)),
+                _ => composites.push(Composite {
+                    name: name.name,
+                    schema: name.schema,
+                    fields: vec![(field, db_type)],
+                    renamed_from: None,
+                    renamed_fields: Vec::new()
+                })
+            }
+        }
+
+        let migrations_table = MIGRATIONS_TABLE.to_string();
+        let mut columns: Vec<(QualifiedName, String, DbType)> = Vec::new();
+        for row in conn.query(COLUMNS, &[&migrations_table]).await? {
+            let db_type = DbType {
+                base: base_type(&get::<String>(&row, "type")?, get(&row, "user_type")?, get(&row, "user_schema")?),
+                nullable: !get::<bool>(&row, "not_null")?,
+                default: get(&row, "default_expr")?,
+                identity: get(&row, "identity")?,
+                ..DbType::default()
+            };
+            columns.push((qualified(&row, "schema", "table")?, get(&row, "column")?, db_type));
+        }
+
+        let mut checks: Vec<(QualifiedName, Check)> = Vec::new();
+        let mut uniques: Vec<(QualifiedName, Unique)> = Vec::new();
+        let mut foreign_keys: Vec<(QualifiedName, ForeignKeyConstraint)> = Vec::new();
+        for row in conn.query(CONSTRAINTS, &[&migrations_table]).await? {
+            let table = qualified(&row, "schema", "table")?;
+            let kind: String = get(&row, "kind")?;
+            if kind == "c" {
+                let definition: String = get(&row, "definition")?;
+                let definition = definition.trim_end_matches(" NOT VALID");
+                let expr = definition.strip_prefix("CHECK (").and_then(|expr| expr.strip_suffix(')')).unwrap_or(definition).trim();
+                checks.push((table, Check { name: get(&row, "name")?, expr: expr.to_string() }));
+                continue;
+            }
+            let constraint_columns: Vec<String> = get(&row, "columns")?;
+            let name: String = get(&row, "name")?;
+            if kind == "u" && (constraint_columns.len() != 1 || name != Unique::new(&table.name, constraint_columns.clone()).name) {
+                uniques.push((table, Unique { name, columns: constraint_columns }));
+                continue;
+            }
+            if kind == "p" {
+                // A primary key on a single column makes it unique, which
+                // Postgres folds into the primary key.
+                for (_, _, db_type) in columns.iter_mut().filter(|(t, c, _)| *t == table && constraint_columns.contains(c)) {
+                    db_type.primary_key = true;
+                    db_type.unique |= constraint_columns.len() == 1;
+                }
+                continue;
+            }
+            if kind == "f" && constraint_columns.len() != 1 {
+                foreign_keys.push((table, ForeignKeyConstraint { name, columns: constraint_columns, references: references(&row)? }));
+                continue;
+            }
+            let column = match constraint_columns.as_slice() {
+                [column] => column,
+                _ => continue
+            };
+            let db_type = match columns.iter_mut().find(|(t, c, _)| *t == table && c == column) {
+                Some((_, _, db_type)) => db_type,
+                None => continue
+            };
+            match kind.as_str() {
+                "u" => db_type.unique = true,
+                "f" => db_type.references = Some(references(&row)?),
+                _ => ()
+            }
+        }
+
+        let mut table_columns: Vec<(QualifiedName, Vec<Column>)> = Vec::new();
+        for (table, column, db_type) in columns {
+            match table_columns.last_mut() {
+                Some((name, columns)) if *name == table => columns.push(Column::new(column, db_type)),
+                _ => table_columns.push((table, vec![Column::new(column, db_type)]))
+            }
+        }
+        let mut indexes: Vec<(QualifiedName, Index)> = Vec::new();
+        for row in conn.query(INDEXES, &[&migrations_table]).await? {
+            let keys: Vec<String> = get(&row, "keys")?;
+            let using: String = get(&row, "using")?;
+            indexes.push((qualified(&row, "schema", "table")?, Index {
+                name: get(&row, "name")?,
+                // Columns come back quoted when their names need it.
+                keys: keys.into_iter()
+                    .map(|key| match key.strip_prefix('"').and_then(|key| key.strip_suffix('"')) {
+                        Some(column) if !column.contains('"') => column.to_string(),
+                        _ => key
+                    })
+                    .collect(),
+                unique: get(&row, "unique")?,
+                using: if using == "btree" { None } else { Some(using) },
+                predicate: get(&row, "predicate")?
+            }));
+        }
+
+        let tables = table_columns.into_iter().map(|(name, columns)| {
+            let table_checks = checks.iter()
+                .filter(|(table, _)| *table == name)
+                .map(|(_, check)| check.clone())
+                .collect();
+            let table_uniques = uniques.iter()
+                .filter(|(table, _)| *table == name)
+                .map(|(_, unique)| unique.clone())
+                .collect();
+            let table_indexes = indexes.iter()
+                .filter(|(table, _)| *table == name)
+                .map(|(_, index)| index.clone())
+                .collect();
+            let table_foreign_keys = foreign_keys.iter()
+                .filter(|(table, _)| *table == name)
+                .map(|(_, foreign_key)| foreign_key.clone())
+                .collect();
+            Table::new(name.name, columns)
+                .with_schema(name.schema)
+                .with_checks(table_checks)
+                .with_indexes(table_indexes)
+                .with_uniques(table_uniques)
+                .with_foreign_keys(table_foreign_keys)
+        }).collect();
+
+        let mut schema = Schema::empty();
+        schema.add_enums(enums);
+        schema.add_composites(composites);
+        schema.add_tables(tables);
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_type_spells_types_as_models_do() {
+        let cases = [
+            ("integer", "INT"),
+            ("character varying(20)[]", "VARCHAR(20)[]"),
+            ("numeric(10,2)", "DECIMAL(10, 2)"),
+            ("time with time zone", "TIMETZ"),
+            ("time(3) without time zone", "TIME(3)"),
+            ("timestamp(6) with time zone[]", "TIMESTAMPTZ(6)[]"),
+            ("bit(4)", "BIT(4)"),
+            ("bit varying", "BIT VARYING"),
+            ("interval(3)", "INTERVAL(3)"),
+            ("interval second(2)", "INTERVAL SECOND(2)"),
+            ("interval year to month", "INTERVAL YEAR TO MONTH"),
+            ("inet", "INET")
+        ];
+        for (format, expected) in cases {
+            assert_eq!(base_type(format, None, None), expected, "{}", format);
+        }
+    }
+
+    #[test]
+    fn base_type_qualifies_user_types() {
+        assert_eq!(base_type("\"Mood\"[]", Some("Mood".to_string()), Some("public".to_string())), "\"Mood\"[]");
+        assert_eq!(base_type("geo.point", Some("point".to_string()), Some("geo".to_string())), "\"geo\".\"point\"");
+    }
+}
@@ -1,15 +1,36 @@
+pub mod db;
+pub mod diff;
+pub mod error;
+pub mod filter;
+pub mod inspectdb;
+mod introspect;
+pub mod migrate;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod query;
+pub mod router;
 pub mod schema;
+pub mod validate;
+pub mod value;
 pub use platt_macros::*;
 pub use derive_builder::Builder;
+pub use uuid;
+
+// Lets the tests derive models and enums, whose code names the crate
+// `::platt`.
+#[cfg(test)]
+extern crate self as platt;
 
 pub trait HasBuilder {
     type Builder;
 }
 
+/// Generates `get_schema()`, the schema of the given models, and
+/// `get_data_migrations()`, the data migrations listed after them as
+/// `; data_migrations { "name" => path::to::function }`.
 #[macro_export]
 macro_rules! activate_models {
-    ( $($x:ident),+ ) => {
+    ( $($x:ident),+ $(; data_migrations { $($name:literal => $f:path),* $(,)? })? ) => {
         pub fn get_schema() -> ::platt::schema::Schema{
             let mut schema = ::platt::schema::Schema::empty();
             $(
@@ -17,5 +38,14 @@ macro_rules! activate_models {
             )+
             schema
         }
+
+        #[allow(unused_mut)]
+        pub fn get_data_migrations() -> ::platt::migrate::DataMigrations {
+            let mut data_migrations = ::platt::migrate::DataMigrations::new();
+            $($(
+                data_migrations.register($name, $f);
+            )*)?
+            data_migrations
+        }
     };
 }
\ No newline at end of file
@@ -0,0 +1,612 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use crate::db::{transaction, Connection, TransactionOptions};
+use crate::error::{Error, Result};
+use crate::diff::SchemaDiff;
+use crate::schema::Schema;
+use crate::value::FromSql;
+
+/// The table applied migrations are recorded in.
+pub const MIGRATIONS_TABLE: &str = "platt_migrations";
+
+/// A step of a migration: either a SQL statement, written in the file as a
+/// string, or a data migration registered under a name, written as
+/// `{"run": "<name>"}`.
+///
+/// Statements whose changes the steps after them can only use once they are
+/// committed, such as `ALTER TYPE ... ADD VALUE`, are written as
+/// `{"outside_transaction": "<statement>"}`. They are run on their own,
+/// committing the steps before them in one transaction and running the steps
+/// after them in another.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    Sql(String),
+    Run { run: String },
+    OutsideTransaction { outside_transaction: String }
+}
+
+impl Step {
+    /// The steps running the changes of `diff`.
+    fn from_diff(diff: &SchemaDiff) -> Vec<Step> {
+        diff.changes().iter().map(|change| match change.needs_commit() {
+            true => Step::OutsideTransaction { outside_transaction: change.to_sql() },
+            false => Step::Sql(change.to_sql())
+        }).collect()
+    }
+}
+
+/// The schemas either side of a migration, handed to the data migrations it
+/// runs. `from` is the schema before the migration and `to` the one after
+/// it, so a data migration run between the generated statements sees the
+/// tables of both: columns `to` adds have been created and columns it drops
+/// are still there.
+#[derive(Debug, Clone)]
+pub struct HistoricalSchema {
+    pub from: Schema,
+    pub to: Schema
+}
+
+/// A data migration, given the migration's transaction and the schemas
+/// either side of it.
+pub type DataMigrationFn = Arc<
+    dyn for<'t> Fn(&'t mut dyn Connection, &'t HistoricalSchema) -> BoxFuture<'t, Result<()>> + Send + Sync
+>;
+
+/// The data migrations migration files can run, by name. Generated as
+/// `get_data_migrations()` by [`activate_models!`](crate::activate_models).
+#[derive(Clone, Default)]
+pub struct DataMigrations {
+    steps: HashMap<String, DataMigrationFn>
+}
+
+impl DataMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F>(&mut self, name: impl Into<String>, f: F)
+    where
+        F: for<'t> Fn(&'t mut dyn Connection, &'t HistoricalSchema) -> BoxFuture<'t, Result<()>> + Send + Sync + 'static
+    {
+        self.steps.insert(name.into(), Arc::new(f));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DataMigrationFn> {
+        self.steps.get(name)
+    }
+
+    fn check(&self, migration: &Migration, steps: &[Step]) -> Result<()> {
+        for step in steps {
+            if let Step::Run { run } = step {
+                if self.get(run).is_none() {
+                    return Err(Error::Migration(format!(
+                        "migration {} runs \"{}\", which is not a registered data migration",
+                        Migration::file_name(migration.version, &migration.name),
+                        run
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run<C: Connection + ?Sized>(&self, mut conn: &mut C, steps: &[Step], history: &HistoricalSchema) -> Result<()> {
+        for step in steps {
+            match step {
+                Step::Sql(statement) | Step::OutsideTransaction { outside_transaction: statement } => {
+                    conn.batch_execute(statement).await?
+                }
+                Step::Run { run } => {
+                    let f = self.get(run).expect("steps are checked before running").clone();
+                    f(&mut conn, history).await?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The contents of a migration file.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MigrationFile {
+    statements: Vec<Step>,
+    #[serde(default)]
+    down: Vec<Step>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    irreversible: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    acknowledged: bool,
+    schema: Schema
+}
+
+/// A migration read from a `<version>_<name>.json` file, e.g.
+/// `0001_initial.json`.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub name: String,
+    pub statements: Vec<Step>,
+    /// The steps undoing the migration.
+    pub down: Vec<Step>,
+    /// The statements that drop data. A migration with any is only applied
+    /// once `acknowledged` has been set in its file.
+    pub irreversible: Vec<String>,
+    pub acknowledged: bool,
+    /// The schema once the migration has been applied.
+    pub schema: Schema,
+    /// The SHA-256 of the file, in hex.
+    pub checksum: String
+}
+
+impl Migration {
+    fn file_name(version: u32, name: &str) -> String {
+        format!("{:04}_{}.json", version, name)
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>> {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) if file_name.ends_with(".json") => file_name.trim_end_matches(".json"),
+            _ => return Ok(None)
+        };
+        let (version, name) = match file_name.split_once('_') {
+            Some((version, name)) => (version, name),
+            None => return Err(Error::Migration(format!("{} is not named <version>_<name>.json", path.display())))
+        };
+        let version = version.parse()
+            .map_err(|_| Error::Migration(format!("{} does not start with a version number", path.display())))?;
+        let contents = std::fs::read(path)?;
+        let file: MigrationFile = serde_json::from_slice(&contents)
+            .map_err(|error| Error::Migration(format!("{}: {}", path.display(), error)))?;
+        Ok(Some(Self {
+            version,
+            name: name.to_string(),
+            statements: file.statements,
+            down: file.down,
+            irreversible: file.irreversible,
+            acknowledged: file.acknowledged,
+            schema: file.schema,
+            checksum: checksum(&contents)
+        }))
+    }
+}
+
+fn checksum(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The schema of the migration before `version`.
+fn previous_schema(migrations: &[Migration], version: u32) -> Schema {
+    migrations.iter()
+        .take_while(|migration| migration.version < version)
+        .last()
+        .map(|migration| migration.schema.clone())
+        .unwrap_or_default()
+}
+
+/// Whether reverting `migration` drops data, going by the schemas either
+/// side of it.
+fn drops_on_revert(migrations: &[Migration], migration: &Migration) -> bool {
+    !previous_schema(migrations, migration.version).diff(&migration.schema).irreversible_statements().is_empty()
+}
+
+/// What [`Migrator::migrate_to`] did to a migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStep {
+    Applied(u32),
+    Reverted(u32)
+}
+
+/// A row of [`MIGRATIONS_TABLE`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String
+}
+
+/// Writes migrations into a directory and applies them to a database.
+///
+/// Every migration holds the statements turning the schema of the previous
+/// one into its own and the statements undoing them, computed from the
+/// inverse diff, along with a snapshot of that schema to diff the next
+/// migration against. Applied migrations are recorded in
+/// [`MIGRATIONS_TABLE`] with the checksum of their file, and a migration
+/// edited after being applied stops any further migration.
+///
+/// Besides SQL, a migration can run the [`DataMigrations`] it is given with
+/// [`Migrator::with_data_migrations`], in the same transaction as its
+/// statements.
+pub struct Migrator {
+    dir: PathBuf,
+    data_migrations: DataMigrations,
+    reverts_acknowledged: bool
+}
+
+impl Migrator {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), data_migrations: DataMigrations::new(), reverts_acknowledged: false }
+    }
+
+    pub fn with_data_migrations(mut self, data_migrations: DataMigrations) -> Self {
+        self.data_migrations = data_migrations;
+        self
+    }
+
+    /// Lets [`Migrator::migrate_to`] revert migrations whose down steps drop
+    /// data. The files of applied migrations can not be edited to
+    /// acknowledge it, as that changes their checksum.
+    pub fn with_reverts_acknowledged(mut self, acknowledged: bool) -> Self {
+        self.reverts_acknowledged = acknowledged;
+        self
+    }
+
+    /// The migrations in the directory, ordered by version.
+    pub fn load(&self) -> Result<Vec<Migration>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut migrations = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            if let Some(migration) = Migration::read(&entry?.path())? {
+                migrations.push(migration);
+            }
+        }
+        migrations.sort_by_key(|migration| migration.version);
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                return Err(Error::Migration(format!("two migrations are numbered {:04}", pair[0].version)));
+            }
+        }
+        Ok(migrations)
+    }
+
+    /// The schema the migrations in the directory lead to.
+    pub fn current_schema(&self) -> Result<Schema> {
+        Ok(self.load()?.pop().map(|migration| migration.schema).unwrap_or_default())
+    }
+
+    /// Writes a migration from the latest migration's schema to `schema`,
+    /// returning its path, or `None` if there is nothing to change.
+    ///
+    /// If the migration drops data, the statements doing so are listed under
+    /// `irreversible` and it is not applied until `"acknowledged": true` is
+    /// added to the file.
+    pub fn make(&self, schema: &Schema, name: &str) -> Result<Option<PathBuf>> {
+        self.make_with_data(schema, name, &[])
+    }
+
+    /// Like [`Migrator::make`], but runs the named data migrations once the
+    /// statements adding to the schema are applied and before those dropping
+    /// from it, which is where a backfill goes. The migration is written even
+    /// if the schema is unchanged.
+    pub fn make_with_data(&self, schema: &Schema, name: &str, data_migrations: &[&str]) -> Result<Option<PathBuf>> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::Migration(format!("\"{}\" is not a valid migration name", name)));
+        }
+        let migrations = self.load()?;
+        let current = migrations.last().map(|migration| migration.schema.clone()).unwrap_or_default();
+        let diff = schema.diff(&current);
+        if diff.is_empty() && data_migrations.is_empty() {
+            return Ok(None);
+        }
+        for data_migration in data_migrations {
+            if self.data_migrations.get(data_migration).is_none() {
+                return Err(Error::Migration(format!("\"{}\" is not a registered data migration", data_migration)));
+            }
+        }
+        let version = migrations.last().map_or(1, |migration| migration.version + 1);
+        let first_drop = diff.changes().iter().position(|change| change.is_irreversible()).unwrap_or(diff.changes().len());
+        let mut statements = Step::from_diff(&diff);
+        statements.splice(
+            first_drop..first_drop,
+            data_migrations.iter().map(|data_migration| Step::Run { run: data_migration.to_string() })
+        );
+        let file = MigrationFile {
+            statements,
+            down: Step::from_diff(&current.diff(schema)),
+            irreversible: diff.irreversible_statements(),
+            acknowledged: false,
+            schema: schema.clone()
+        };
+        let contents = serde_json::to_string_pretty(&file).map_err(|error| Error::Migration(error.to_string()))?;
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(Migration::file_name(version, name));
+        std::fs::write(&path, contents + "\n")?;
+        Ok(Some(path))
+    }
+
+    async fn create_table<C: Connection + ?Sized>(conn: &mut C) -> Result<()> {
+        conn.batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (\
+                \"version\" INT PRIMARY KEY, \
+                \"name\" TEXT NOT NULL, \
+                \"checksum\" TEXT NOT NULL, \
+                \"applied_at\" TIMESTAMPTZ NOT NULL DEFAULT now()\
+            )",
+            MIGRATIONS_TABLE
+        )).await
+    }
+
+    /// The migrations recorded as applied, ordered by version.
+    pub async fn applied<C: Connection + ?Sized>(&self, conn: &mut C) -> Result<Vec<AppliedMigration>> {
+        Self::create_table(conn).await?;
+        let sql = format!("SELECT \"version\", \"name\", \"checksum\" FROM \"{}\" ORDER BY \"version\"", MIGRATIONS_TABLE);
+        conn.query(&sql, &[]).await?.iter().map(|row| {
+            Ok(AppliedMigration {
+                version: i32::from_row(row, "version")? as u32,
+                name: String::from_row(row, "name")?,
+                checksum: String::from_row(row, "checksum")?
+            })
+        }).collect()
+    }
+
+    /// The migrations still to apply, after checking that every applied one
+    /// still has its file, unchanged.
+    pub async fn pending<C: Connection + ?Sized>(&self, conn: &mut C) -> Result<Vec<Migration>> {
+        let applied = self.applied(conn).await?;
+        let mut migrations = self.load()?;
+        for applied in &applied {
+            match migrations.iter().find(|migration| migration.version == applied.version) {
+                None => return Err(Error::Migration(format!(
+                    "migration {} was applied but its file is missing",
+                    Migration::file_name(applied.version, &applied.name)
+                ))),
+                Some(migration) if migration.checksum != applied.checksum => return Err(Error::MigrationEdited {
+                    version: applied.version,
+                    name: applied.name.clone()
+                }),
+                Some(_) => ()
+            }
+        }
+        migrations.retain(|migration| applied.iter().all(|applied| applied.version != migration.version));
+        Ok(migrations)
+    }
+
+    /// Applies the pending migrations in order, each in its own transaction,
+    /// and returns their versions.
+    pub async fn apply<C: Connection + ?Sized>(&self, conn: &mut C) -> Result<Vec<u32>> {
+        let migrations = self.load()?;
+        let mut versions = Vec::new();
+        for migration in self.pending(conn).await? {
+            self.apply_one(conn, migration.clone(), previous_schema(&migrations, migration.version)).await?;
+            versions.push(migration.version);
+        }
+        Ok(versions)
+    }
+
+    /// Applies or reverts migrations until `version` is the latest applied
+    /// one, where `0` reverts every migration.
+    ///
+    /// Reverting a migration whose down steps drop tables, columns or
+    /// attributes fails with [`Error::IrreversibleRevert`] unless
+    /// [`Migrator::with_reverts_acknowledged`] was set, and nothing is
+    /// reverted then.
+    pub async fn migrate_to<C: Connection + ?Sized>(&self, conn: &mut C, version: u32) -> Result<Vec<MigrationStep>> {
+        let pending = self.pending(conn).await?;
+        let migrations = self.load()?;
+        if version != 0 && migrations.iter().all(|migration| migration.version != version) {
+            return Err(Error::Migration(format!("there is no migration numbered {:04}", version)));
+        }
+
+        let applied = self.applied(conn).await?;
+        let reverted: Vec<&Migration> = applied.iter().rev()
+            .filter(|applied| applied.version > version)
+            .map(|applied| migrations.iter()
+                .find(|migration| migration.version == applied.version)
+                .expect("pending checks every applied migration has a file"))
+            .collect();
+        if !self.reverts_acknowledged {
+            if let Some(migration) = reverted.iter().find(|migration| drops_on_revert(&migrations, migration)) {
+                return Err(Error::IrreversibleRevert { version: migration.version, name: migration.name.clone() });
+            }
+        }
+
+        let mut steps = Vec::new();
+        for migration in reverted {
+            self.revert_one(conn, migration.clone(), previous_schema(&migrations, migration.version)).await?;
+            steps.push(MigrationStep::Reverted(migration.version));
+        }
+        for migration in pending.into_iter().filter(|migration| migration.version <= version) {
+            self.apply_one(conn, migration.clone(), previous_schema(&migrations, migration.version)).await?;
+            steps.push(MigrationStep::Applied(migration.version));
+        }
+        Ok(steps)
+    }
+
+    async fn apply_one<C: Connection + ?Sized>(&self, conn: &mut C, migration: Migration, previous: Schema) -> Result<()> {
+        if !migration.irreversible.is_empty() && !migration.acknowledged {
+            return Err(Error::IrreversibleMigration { version: migration.version, name: migration.name });
+        }
+        self.data_migrations.check(&migration, &migration.statements)?;
+        let history = HistoricalSchema { from: previous, to: migration.schema.clone() };
+        let steps = migration.statements.clone();
+        self.run_steps(conn, &steps, &history, Record::Applied(migration)).await
+    }
+
+    async fn revert_one<C: Connection + ?Sized>(&self, conn: &mut C, migration: Migration, previous: Schema) -> Result<()> {
+        // Data migrations are not undone unless the file lists a step that
+        // does, so a migration running nothing else reverts to a no-op.
+        if migration.down.is_empty() && migration.statements.iter().any(|step| !matches!(step, Step::Run { .. })) {
+            return Err(Error::Migration(format!(
+                "migration {} has no down statements",
+                Migration::file_name(migration.version, &migration.name)
+            )));
+        }
+        self.data_migrations.check(&migration, &migration.down)?;
+        let history = HistoricalSchema { from: migration.schema.clone(), to: previous };
+        let steps = migration.down.clone();
+        self.run_steps(conn, &steps, &history, Record::Reverted(migration.version)).await
+    }
+
+    /// Runs `steps` in a transaction, recording the migration in it, or in
+    /// one transaction per run of steps between those that have to be run
+    /// outside of one. The migration is then recorded in the last.
+    async fn run_steps<C: Connection + ?Sized>(
+        &self,
+        conn: &mut C,
+        steps: &[Step],
+        history: &HistoricalSchema,
+        record: Record
+    ) -> Result<()> {
+        let mut start = 0;
+        for (index, step) in steps.iter().enumerate() {
+            if let Step::OutsideTransaction { outside_transaction } = step {
+                if index > start {
+                    self.run_in_transaction(conn, &steps[start..index], history, None).await?;
+                }
+                conn.batch_execute(outside_transaction).await?;
+                start = index + 1;
+            }
+        }
+        self.run_in_transaction(conn, &steps[start..], history, Some(record)).await
+    }
+
+    async fn run_in_transaction<C: Connection + ?Sized>(
+        &self,
+        conn: &mut C,
+        steps: &[Step],
+        history: &HistoricalSchema,
+        record: Option<Record>
+    ) -> Result<()> {
+        transaction(conn, &TransactionOptions::default(), |tx| {
+            let steps = steps.to_vec();
+            let history = history.clone();
+            let record = record.clone();
+            let data_migrations = self.data_migrations.clone();
+            async move {
+                data_migrations.run(tx, &steps, &history).await?;
+                if let Some(record) = record {
+                    record.write(tx).await?;
+                }
+                Ok(())
+            }.boxed()
+        }).await
+    }
+}
+
+/// How a migration that was run is recorded in [`MIGRATIONS_TABLE`].
+#[derive(Clone)]
+enum Record {
+    Applied(Migration),
+    Reverted(u32)
+}
+
+impl Record {
+    async fn write<C: Connection + ?Sized>(&self, conn: &mut C) -> Result<()> {
+        match self {
+            Record::Applied(migration) => {
+                let sql = format!(
+                    "INSERT INTO \"{}\" (\"version\", \"name\", \"checksum\") VALUES ($1, $2, $3)",
+                    MIGRATIONS_TABLE
+                );
+                let version = migration.version as i32;
+                conn.execute(&sql, &[&version, &migration.name, &migration.checksum]).await?;
+            }
+            Record::Reverted(version) => {
+                let sql = format!("DELETE FROM \"{}\" WHERE \"version\" = $1", MIGRATIONS_TABLE);
+                let version = *version as i32;
+                conn.execute(&sql, &[&version]).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{primary_key, Column, DbType, EnumType, Table};
+
+    /// A migrations directory of its own for every test.
+    fn migrator(test: &str) -> Migrator {
+        let dir = std::env::temp_dir().join(format!("platt_migrate_{}_{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        Migrator::new(dir)
+    }
+
+    fn schema(columns: &[&str], labels: &[&str]) -> Schema {
+        let mut schema = Schema::empty();
+        schema.add_enums(vec![EnumType {
+            name: "Mood".to_string(),
+            schema: None,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            renamed_labels: Vec::new()
+        }]);
+        let mut all = vec![Column::new("id".to_string(), primary_key())];
+        all.extend(columns.iter().map(|name| {
+            Column::new(name.to_string(), DbType { base: "TEXT".to_string(), ..DbType::default() })
+        }));
+        schema.add_tables(vec![Table::new("Post".to_string(), all)]);
+        schema
+    }
+
+    #[test]
+    fn checksum_is_sha256_in_hex() {
+        assert_eq!(checksum(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn load_checksums_the_file() {
+        let migrator = migrator("checksum");
+        let path = migrator.make(&schema(&["title"], &["happy"]), "initial").unwrap().unwrap();
+        let loaded = migrator.load().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].checksum, checksum(&std::fs::read(&path).unwrap()));
+
+        let edited = std::fs::read_to_string(&path).unwrap().replace("\"title\"", "\"heading\"");
+        std::fs::write(&path, edited).unwrap();
+        assert_ne!(migrator.load().unwrap()[0].checksum, loaded[0].checksum);
+        std::fs::remove_dir_all(&migrator.dir).unwrap();
+    }
+
+    #[test]
+    fn make_lists_irreversible_statements() {
+        let migrator = migrator("irreversible");
+        migrator.make(&schema(&["title", "body"], &["happy"]), "initial").unwrap();
+        assert!(migrator.make(&schema(&["title", "body"], &["happy"]), "unchanged").unwrap().is_none());
+        migrator.make(&schema(&["title"], &["happy", "sad"]), "drop_body").unwrap();
+
+        let migrations = migrator.load().unwrap();
+        let (initial, drop_body) = (&migrations[0], &migrations[1]);
+        assert!(initial.irreversible.is_empty());
+        assert_eq!(drop_body.irreversible, ["ALTER TABLE \"Post\" DROP COLUMN \"body\""]);
+        assert!(!drop_body.acknowledged);
+        assert_eq!(drop_body.statements, [
+            Step::OutsideTransaction { outside_transaction: "ALTER TYPE \"Mood\" ADD VALUE IF NOT EXISTS 'sad'".to_string() },
+            Step::Sql("ALTER TABLE \"Post\" DROP COLUMN \"body\"".to_string())
+        ]);
+        assert_eq!(drop_body.down, [
+            Step::Sql("ALTER TABLE \"Post\" ADD COLUMN \"body\" TEXT NOT NULL".to_string())
+        ]);
+
+        // Reverting the initial migration drops its table, and reverting the
+        // second adds the column back.
+        assert!(drops_on_revert(&migrations, initial));
+        assert!(!drops_on_revert(&migrations, drop_body));
+        std::fs::remove_dir_all(&migrator.dir).unwrap();
+    }
+
+    #[test]
+    fn make_refuses_invalid_names() {
+        let migrator = migrator("names");
+        assert!(matches!(migrator.make(&schema(&[], &[]), "add-posts"), Err(Error::Migration(_))));
+        assert!(matches!(migrator.make(&schema(&[], &[]), ""), Err(Error::Migration(_))));
+    }
+
+    #[test]
+    fn steps_read_back_from_json() {
+        let steps: Vec<Step> = serde_json::from_str(
+            r#"["SELECT 1", {"run": "backfill"}, {"outside_transaction": "SELECT 2"}]"#
+        ).unwrap();
+        assert_eq!(steps, [
+            Step::Sql("SELECT 1".to_string()),
+            Step::Run { run: "backfill".to_string() },
+            Step::OutsideTransaction { outside_transaction: "SELECT 2".to_string() }
+        ]);
+    }
+}
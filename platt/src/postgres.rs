@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use bytes::BytesMut;
+use futures::future::BoxFuture;
+use futures::{FutureExt, TryStreamExt};
+use tokio_postgres::types::{self as pg, Type};
+use crate::db::{Connection, Params, Row};
+use crate::error::{Error, Result, SqlState};
+use crate::value::{Format, IsNull, ToSql};
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(error: tokio_postgres::Error) -> Self {
+        let code = error.code().map(|code| SqlState::new(code.code()));
+        if let Some(db_error) = error.as_db_error() {
+            if code == Some(SqlState::UNIQUE_VIOLATION) {
+                if let Some(constraint) = db_error.constraint() {
+                    return Error::UniqueViolation {
+                        constraint: constraint.to_string(),
+                        message: db_error.message().to_string()
+                    };
+                }
+            }
+        }
+        let message = match error.as_db_error() {
+            Some(db_error) => db_error.message().to_string(),
+            None => error.to_string()
+        };
+        Error::database(code, message)
+    }
+}
+
+/// Hands a [`ToSql`] value to the driver. Values are sent in binary when
+/// their type is exactly the one the server expects and in text otherwise,
+/// letting the server do any conversion.
+struct Param<'a>(&'a (dyn ToSql + Sync));
+
+impl Param<'_> {
+    fn format(&self, ty: &Type) -> Format {
+        if self.0.oid() == Some(ty.oid()) { Format::Binary } else { Format::Text }
+    }
+}
+
+impl std::fmt::Debug for Param<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Param")
+    }
+}
+
+impl pg::ToSql for Param<'_> {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> std::result::Result<pg::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        let mut value = Vec::new();
+        match self.0.to_sql(self.format(ty), &mut value)? {
+            IsNull::Yes => Ok(pg::IsNull::Yes),
+            IsNull::No => {
+                out.extend_from_slice(&value);
+                Ok(pg::IsNull::No)
+            }
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, ty: &Type) -> pg::Format {
+        match self.format(ty) {
+            Format::Text => pg::Format::Text,
+            Format::Binary => pg::Format::Binary
+        }
+    }
+
+    pg::to_sql_checked!();
+}
+
+/// The raw bytes of a result column, which are decoded by [`crate::value::FromSql`].
+struct RawValue(Option<Vec<u8>>);
+
+impl<'a> pg::FromSql<'a> for RawValue {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(RawValue(Some(raw.to_vec())))
+    }
+
+    fn from_sql_null(_ty: &Type) -> std::result::Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(RawValue(None))
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+impl Connection for tokio_postgres::Client {
+    fn batch_execute<'a>(&'a mut self, sql: &'a str) -> BoxFuture<'a, Result<()>> {
+        tokio_postgres::Client::batch_execute(self, sql)
+            .map(|result| result.map_err(Error::from))
+            .boxed()
+    }
+
+    fn execute<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<u64>> {
+        async move {
+            Ok(self.execute_raw(sql, params.iter().map(|param| Param(*param))).await?)
+        }.boxed()
+    }
+
+    fn query<'a>(&'a mut self, sql: &'a str, params: Params<'a>) -> BoxFuture<'a, Result<Vec<Row>>> {
+        async move {
+            let stream = self.query_raw(sql, params.iter().map(|param| Param(*param))).await?;
+            let pg_rows: Vec<tokio_postgres::Row> = stream.try_collect().await?;
+            let mut columns: Option<Arc<[String]>> = None;
+            let mut rows = Vec::with_capacity(pg_rows.len());
+            for row in pg_rows {
+                let columns = columns.get_or_insert_with(|| {
+                    row.columns().iter().map(|column| column.name().to_string()).collect()
+                });
+                let values = (0..row.len())
+                    .map(|index| row.try_get::<_, RawValue>(index).map(|raw| raw.0))
+                    .collect::<std::result::Result<_, _>>()?;
+                rows.push(Row::new(columns.clone(), Format::Binary, values));
+            }
+            Ok(rows)
+        }.boxed()
+    }
+}
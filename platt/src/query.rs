@@ -1,19 +1,38 @@
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures::stream::{self, Stream, TryStreamExt};
+use crate::db::{Connection, Row};
+use crate::error::{Error, Result};
+use crate::filter::Condition;
+use crate::schema::{DbModel, QualifiedName};
+use crate::value::ToSql;
 
 pub trait Queryable {
     type Data;
     type Insertable;
-    type Filters;
+    /// Hands out a filter handle for every column, see [`QuerySet::filter`].
+    type Filters: Default;
     type Update;
 }
 
+/// Builds a value out of a row returned by the database.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+#[deprecated(note = "build conditions from the handles of `Queryable::Filters` instead, see `QuerySet::filter`")]
 pub trait GetFilterState<FieldType> {
+    #[allow(deprecated)]
     fn get(&mut self) -> &mut FilterState<FieldType>;
 }
+
+#[deprecated(note = "build conditions from the handles of `Queryable::Filters` instead, see `QuerySet::filter`")]
+#[allow(deprecated)]
 pub trait Filters<FieldType>: GetFilterState<FieldType> { }
 
+#[deprecated(note = "build conditions from the handles of `Queryable::Filters` instead, see `QuerySet::filter`")]
 #[derive(Debug, Default, Clone)]
-pub struct FilterState<FieldType>(Vec<FieldType>);
+pub struct FilterState<FieldType>(pub Vec<FieldType>);
 
 pub struct DatabaseResult<Model: Queryable>(Model::Data);
 
@@ -23,6 +42,16 @@ pub struct TrackingMut<FieldType> {
     edited: bool
 }
 
+impl<FieldType> TrackingMut<FieldType> {
+    pub fn new(data: FieldType) -> Self {
+        Self { data, edited: false }
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.edited
+    }
+}
+
 impl<FieldType> Deref for TrackingMut<FieldType> {
     type Target = FieldType;
     fn deref(&self) -> &FieldType {
@@ -36,12 +65,76 @@ impl<FieldType> DerefMut for TrackingMut<FieldType> {
     }
 }
 
+/// Number of rows fetched from a server-side cursor at a time by
+/// [`QuerySet::stream`], unless changed with [`QuerySet::fetch_size`].
+pub const DEFAULT_FETCH_SIZE: usize = 1000;
+
+static NEXT_CURSOR: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockStrength {
+    #[default]
+    Update,
+    NoKeyUpdate,
+    Share,
+    KeyShare
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockWait {
+    #[default]
+    Wait,
+    NoWait,
+    SkipLocked
+}
+
+/// A `FOR UPDATE`-style locking clause. Row locks are only held until the
+/// end of the transaction, so a locking query must be run inside one.
+///
+/// `wait` only modifies a lock, so a query fails with
+/// [`Error::InvalidLock`] when it is set without a `strength`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RowLock {
+    pub strength: Option<LockStrength>,
+    pub wait: LockWait
+}
+
+impl RowLock {
+    fn to_sql(&self) -> Option<String> {
+        let mut sql = match self.strength? {
+            LockStrength::Update => "FOR UPDATE",
+            LockStrength::NoKeyUpdate => "FOR NO KEY UPDATE",
+            LockStrength::Share => "FOR SHARE",
+            LockStrength::KeyShare => "FOR KEY SHARE"
+        }.to_string();
+        match self.wait {
+            LockWait::Wait => (),
+            LockWait::NoWait => sql += " NOWAIT",
+            LockWait::SkipLocked => sql += " SKIP LOCKED"
+        }
+        Some(sql)
+    }
+}
+
 pub struct QuerySet<DB, Model> {
     db: DB,
+    fetch_size: usize,
+    lock: Option<RowLock>,
+    conditions: Vec<Condition>,
     _model: std::marker::PhantomData<Model>
 }
 
 impl<DB, Model: Queryable> QuerySet<DB, Model> {
+    pub fn new(db: DB) -> Self {
+        Self {
+            db,
+            fetch_size: DEFAULT_FETCH_SIZE,
+            lock: None,
+            conditions: Vec::new(),
+            _model: std::marker::PhantomData
+        }
+    }
+
     pub fn all() -> Self { todo!() }
     pub fn none() -> Self { todo!() }
     pub fn get_one() -> Model::Data { todo!()  } 
@@ -50,12 +143,20 @@ impl<DB, Model: Queryable> QuerySet<DB, Model> {
     pub fn update_or_create() -> Model::Data { todo!() }
     pub fn bulk_create() -> Model::Data { todo!() }
 
-    pub fn filter(self, filters: Model::Filters) -> Self {
-        todo!()
+    /// Keeps the rows matching the condition built from the model's filters,
+    /// e.g. `.filter(|post| post.name().eq("Hello".to_string()))`.
+    ///
+    /// This replaces the never implemented `filter(Model::Filters)`, whose
+    /// [`FilterState`] types are only kept so that code naming them still
+    /// builds.
+    pub fn filter(mut self, filters: impl FnOnce(&Model::Filters) -> Condition) -> Self {
+        self.conditions.push(filters(&Model::Filters::default()));
+        self
     }
 
-    pub fn exclude(self, filters: Model::Filters) -> Self {
-        todo!()
+    pub fn exclude(mut self, filters: impl FnOnce(&Model::Filters) -> Condition) -> Self {
+        self.conditions.push(filters(&Model::Filters::default()).not());
+        self
     }
 
     pub fn order_by(self) -> Self {
@@ -66,15 +167,15 @@ impl<DB, Model: Queryable> QuerySet<DB, Model> {
         todo!()
     }
 
-    pub fn sum(self, other: Self) -> Self { 
+    pub fn sum(self, _other: Self) -> Self {
         todo!()
     }
 
-    pub fn intersection(self, other: Self) -> Self { 
+    pub fn intersection(self, _other: Self) -> Self {
         todo!()
     }
 
-    pub fn difference(self, other: Self) -> Self { 
+    pub fn difference(self, _other: Self) -> Self {
         todo!()
     }
 
@@ -93,4 +194,333 @@ impl<DB, Model: Queryable> QuerySet<DB, Model> {
     pub fn exists(self) -> Self {
         todo!()
     }
-}
\ No newline at end of file
+
+    pub fn fetch_size(mut self, fetch_size: usize) -> Self {
+        self.fetch_size = fetch_size.max(1);
+        self
+    }
+
+    fn with_lock(mut self, update: impl FnOnce(&mut RowLock)) -> Self {
+        update(self.lock.get_or_insert_with(RowLock::default));
+        self
+    }
+
+    /// Locks the selected rows with `FOR UPDATE`. Running the query outside
+    /// a transaction fails with [`Error::LockOutsideTransaction`].
+    pub fn select_for_update(self) -> Self {
+        self.with_lock(|lock| lock.strength = Some(LockStrength::Update))
+    }
+
+    pub fn select_for_no_key_update(self) -> Self {
+        self.with_lock(|lock| lock.strength = Some(LockStrength::NoKeyUpdate))
+    }
+
+    pub fn select_for_share(self) -> Self {
+        self.with_lock(|lock| lock.strength = Some(LockStrength::Share))
+    }
+
+    pub fn select_for_key_share(self) -> Self {
+        self.with_lock(|lock| lock.strength = Some(LockStrength::KeyShare))
+    }
+
+    /// Fail with `55P03` instead of waiting for rows locked by someone else.
+    /// Only valid together with one of the `select_for_*` locks.
+    pub fn nowait(self) -> Self {
+        self.with_lock(|lock| lock.wait = LockWait::NoWait)
+    }
+
+    /// Leave out rows locked by someone else instead of waiting for them.
+    /// Only valid together with one of the `select_for_*` locks.
+    pub fn skip_locked(self) -> Self {
+        self.with_lock(|lock| lock.wait = LockWait::SkipLocked)
+    }
+}
+
+impl<DB, Model: Queryable + DbModel> QuerySet<DB, Model> {
+    pub fn to_sql(&self) -> String {
+        self.to_sql_with_params().0
+    }
+
+    pub fn to_sql_with_params(&self) -> (String, Vec<&(dyn ToSql + Sync)>) {
+        let mut sql = format!("SELECT * FROM {}", QualifiedName::new(Model::schema().as_deref(), &Model::table_name()));
+        let mut params = Vec::new();
+        for (index, condition) in self.conditions.iter().enumerate() {
+            sql += if index == 0 { " WHERE " } else { " AND " };
+            condition.render(&mut sql, &mut params);
+        }
+        if let Some(lock) = self.lock.as_ref().and_then(RowLock::to_sql) {
+            sql += " ";
+            sql += &lock;
+        }
+        (sql, params)
+    }
+}
+
+/// The parameters of `conditions`, numbered as in [`QuerySet::to_sql_with_params`].
+fn condition_params(conditions: &[Condition]) -> Vec<&(dyn ToSql + Sync)> {
+    let mut sql = String::new();
+    let mut params = Vec::new();
+    for condition in conditions {
+        condition.render(&mut sql, &mut params);
+    }
+    params
+}
+
+enum CursorState<DB> {
+    Start(DB, String, Vec<Condition>),
+    Open { db: DB, cursor: String },
+    Failed(Error),
+    Done
+}
+
+impl<DB: Connection, Model: Queryable + DbModel> QuerySet<DB, Model>
+where
+    Model::Data: FromRow
+{
+    fn check_lock(&self) -> Result<()> {
+        let lock = match &self.lock {
+            Some(lock) => lock,
+            None => return Ok(())
+        };
+        if lock.strength.is_none() {
+            return Err(Error::InvalidLock(
+                "nowait() and skip_locked() need one of the select_for_* locks".to_string()
+            ));
+        }
+        if !self.db.in_transaction() {
+            return Err(Error::LockOutsideTransaction);
+        }
+        Ok(())
+    }
+
+    pub async fn fetch(mut self) -> Result<Vec<Model::Data>> {
+        self.check_lock()?;
+        let sql = self.to_sql();
+        let rows = self.db.query(&sql, &condition_params(&self.conditions)).await?;
+        rows.iter().map(Model::Data::from_row).collect()
+    }
+
+    /// Streams the results through a server-side cursor, fetching
+    /// [`QuerySet::fetch_size`] rows at a time.
+    ///
+    /// Cursors only live inside a transaction, so `DB` has to be one, such as
+    /// the [`Transaction`](crate::db::Transaction) of
+    /// [`transaction`](crate::db::transaction). A stream dropped before its
+    /// end leaves the cursor open until the transaction ends.
+    pub fn stream(self) -> impl Stream<Item = Result<Model::Data>> {
+        let fetch_size = self.fetch_size;
+        self.iter_chunks(fetch_size)
+            .map_ok(|chunk| stream::iter(chunk.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Like [`QuerySet::stream`], but yields the rows in batches of (at most)
+    /// `size`, which is also used as the fetch size.
+    pub fn iter_chunks(self, size: usize) -> impl Stream<Item = Result<Vec<Model::Data>>> {
+        let size = size.max(1);
+        let sql = self.to_sql();
+        let state = match self.check_lock() {
+            Ok(()) if !self.db.in_transaction() => CursorState::Failed(Error::CursorOutsideTransaction),
+            Ok(()) => CursorState::Start(self.db, sql, self.conditions),
+            Err(error) => CursorState::Failed(error)
+        };
+        stream::try_unfold(state, move |state| async move {
+            let (mut db, cursor) = match state {
+                CursorState::Start(mut db, sql, conditions) => {
+                    let cursor = format!("platt_cursor_{}", NEXT_CURSOR.fetch_add(1, Ordering::Relaxed));
+                    let declare = format!("DECLARE \"{}\" NO SCROLL CURSOR FOR {}", cursor, sql);
+                    db.execute(&declare, &condition_params(&conditions)).await?;
+                    (db, cursor)
+                }
+                CursorState::Open { db, cursor } => (db, cursor),
+                CursorState::Failed(error) => return Err(error),
+                CursorState::Done => return Ok(None)
+            };
+
+            let rows = db.query(&format!("FETCH {} FROM \"{}\"", size, cursor), &[]).await?;
+            let chunk = rows.iter().map(Model::Data::from_row).collect::<Result<Vec<_>>>()?;
+            if rows.len() < size {
+                db.batch_execute(&format!("CLOSE \"{}\"", cursor)).await?;
+                if chunk.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some((chunk, CursorState::Done)));
+            }
+            Ok(Some((chunk, CursorState::Open { db, cursor })))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use super::*;
+    use crate::db::tests::MockConnection;
+    use crate::value::Format;
+    use crate::PlattModel;
+
+    #[derive(PlattModel)]
+    #[platt(table = "Account")]
+    struct Account {
+        balance: i64
+    }
+
+    type Lock = fn(QuerySet<(), Account>) -> QuerySet<(), Account>;
+
+    fn accounts(conn: &mut MockConnection) -> QuerySet<&mut MockConnection, Account> {
+        QuerySet::new(conn)
+    }
+
+    #[test]
+    fn lock_clauses() {
+        let cases: [(Lock, &str); 7] = [
+            (|query| query.select_for_update(), "FOR UPDATE"),
+            (|query| query.select_for_no_key_update(), "FOR NO KEY UPDATE"),
+            (|query| query.select_for_share(), "FOR SHARE"),
+            (|query| query.select_for_key_share(), "FOR KEY SHARE"),
+            (|query| query.select_for_update().nowait(), "FOR UPDATE NOWAIT"),
+            (|query| query.skip_locked().select_for_share(), "FOR SHARE SKIP LOCKED"),
+            (|query| query.select_for_update().nowait().skip_locked(), "FOR UPDATE SKIP LOCKED")
+        ];
+        for (lock, clause) in cases {
+            assert_eq!(lock(QuerySet::new(())).to_sql(), format!("SELECT * FROM \"Account\" {}", clause));
+        }
+        assert_eq!(QuerySet::<(), Account>::new(()).to_sql(), "SELECT * FROM \"Account\"");
+    }
+
+    #[test]
+    fn lock_comes_after_the_conditions() {
+        let query = QuerySet::<(), Account>::new(()).filter(|account| account.balance().gt(0)).select_for_update();
+        assert_eq!(query.to_sql(), "SELECT * FROM \"Account\" WHERE \"balance\" > $1 FOR UPDATE");
+    }
+
+    #[test]
+    fn lock_modifiers_need_a_strength() {
+        let mut conn = MockConnection::ok();
+        conn.in_transaction = true;
+        let result = block_on(accounts(&mut conn).nowait().fetch());
+        assert!(matches!(result, Err(Error::InvalidLock(_))));
+        let result = block_on(accounts(&mut conn).skip_locked().fetch());
+        assert!(matches!(result, Err(Error::InvalidLock(_))));
+        assert!(conn.statements.is_empty());
+    }
+
+    #[test]
+    fn locks_need_a_transaction() {
+        let mut conn = MockConnection::ok();
+        let result = block_on(accounts(&mut conn).select_for_update().fetch());
+        assert!(matches!(result, Err(Error::LockOutsideTransaction)));
+        assert!(conn.statements.is_empty());
+
+        conn.in_transaction = true;
+        block_on(accounts(&mut conn).select_for_update().skip_locked().fetch()).unwrap();
+        assert_eq!(conn.statements, ["SELECT * FROM \"Account\" FOR UPDATE SKIP LOCKED"]);
+    }
+
+    /// A connection inside a transaction whose cursors hold accounts with the
+    /// balances `0..total`.
+    fn cursor_over(total: i64) -> MockConnection {
+        let columns: Arc<[String]> = Arc::from(vec!["balance".to_string()]);
+        let mut next = 0;
+        let mut conn = MockConnection::new(move |sql| {
+            if !sql.starts_with("FETCH ") {
+                return Ok(Vec::new());
+            }
+            let size: i64 = sql.split(' ').nth(1).unwrap().parse().unwrap();
+            let rows = (next..total.min(next + size))
+                .map(|balance| Row::new(columns.clone(), Format::Text, vec![Some(balance.to_string().into_bytes())]))
+                .collect();
+            next = total.min(next + size);
+            Ok(rows)
+        });
+        conn.in_transaction = true;
+        conn
+    }
+
+    /// The name of the cursor `statements` declare.
+    fn cursor_name(statements: &[String]) -> String {
+        let declare = statements[0].strip_prefix("DECLARE \"").expect("the cursor is declared first");
+        declare[..declare.find('"').unwrap()].to_string()
+    }
+
+    fn balances(chunk: &[<Account as Queryable>::Data]) -> Vec<i64> {
+        chunk.iter().map(|account| *account.balance).collect()
+    }
+
+    #[test]
+    fn iter_chunks_fetches_until_a_short_chunk() {
+        let mut conn = cursor_over(5);
+        let chunks: Vec<Vec<i64>> = block_on(
+            accounts(&mut conn).filter(|account| account.balance().ge(0)).iter_chunks(2)
+                .map(|chunk| balances(&chunk.unwrap()))
+                .collect()
+        );
+        assert_eq!(chunks, [vec![0, 1], vec![2, 3], vec![4]]);
+
+        let cursor = cursor_name(&conn.statements);
+        assert_eq!(conn.statements, [
+            format!("DECLARE \"{}\" NO SCROLL CURSOR FOR SELECT * FROM \"Account\" WHERE \"balance\" >= $1", cursor),
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("CLOSE \"{}\"", cursor)
+        ]);
+    }
+
+    #[test]
+    fn iter_chunks_ends_on_an_empty_fetch() {
+        let mut conn = cursor_over(4);
+        let chunks: Vec<Vec<i64>> = block_on(
+            accounts(&mut conn).iter_chunks(2).map(|chunk| balances(&chunk.unwrap())).collect()
+        );
+        assert_eq!(chunks, [vec![0, 1], vec![2, 3]]);
+
+        let cursor = cursor_name(&conn.statements);
+        assert_eq!(&conn.statements[1..], [
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("FETCH 2 FROM \"{}\"", cursor),
+            format!("CLOSE \"{}\"", cursor)
+        ]);
+    }
+
+    #[test]
+    fn stream_fetches_fetch_size_rows_at_a_time() {
+        let mut conn = cursor_over(7);
+        let rows: Vec<i64> = block_on(
+            accounts(&mut conn).fetch_size(3).stream().map(|account| *account.unwrap().balance).collect()
+        );
+        assert_eq!(rows, (0..7).collect::<Vec<_>>());
+        let fetches = conn.statements.iter().filter(|sql| sql.starts_with("FETCH 3 FROM ")).count();
+        assert_eq!(fetches, 3);
+
+        let mut conn = cursor_over(2);
+        let rows: Vec<_> = block_on(accounts(&mut conn).fetch_size(0).stream().collect());
+        assert_eq!(rows.len(), 2);
+        assert!(conn.statements[1].starts_with("FETCH 1 FROM "));
+    }
+
+    #[test]
+    fn cursors_need_a_transaction() {
+        let mut conn = cursor_over(3);
+        conn.in_transaction = false;
+        let chunks: Vec<_> = block_on(accounts(&mut conn).iter_chunks(2).collect());
+        assert!(matches!(chunks.as_slice(), [Err(Error::CursorOutsideTransaction)]));
+        let rows: Vec<_> = block_on(accounts(&mut conn).stream().collect());
+        assert!(matches!(rows.as_slice(), [Err(Error::CursorOutsideTransaction)]));
+        assert!(conn.statements.is_empty());
+    }
+
+    #[test]
+    fn cursors_check_the_lock() {
+        let mut conn = cursor_over(3);
+        let chunks: Vec<_> = block_on(accounts(&mut conn).nowait().iter_chunks(2).collect());
+        assert!(matches!(chunks.as_slice(), [Err(Error::InvalidLock(_))]));
+        assert!(conn.statements.is_empty());
+
+        block_on(accounts(&mut conn).select_for_update().iter_chunks(5).collect::<Vec<_>>());
+        assert!(conn.statements[0].ends_with("FROM \"Account\" FOR UPDATE"));
+    }
+}
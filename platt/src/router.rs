@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::error::{Error, Result};
+use crate::schema::DbModel;
+
+/// The alias of the database models without a `#[platt(database = "...")]`
+/// attribute are stored in.
+pub const DEFAULT_DATABASE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Read,
+    Write
+}
+
+/// What a [`Router`] knows about the model an operation is for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    pub table_name: String,
+    pub database: Option<String>
+}
+
+impl ModelInfo {
+    pub fn of<Model: DbModel>() -> Self {
+        Self {
+            table_name: Model::table_name(),
+            database: Model::database()
+        }
+    }
+}
+
+/// Decides which database alias an operation on a model is sent to.
+pub trait Router: Send + Sync {
+    fn route(&self, operation: Operation, model: &ModelInfo) -> String;
+}
+
+/// Sends writes to the primary and spreads reads over the replicas in turn.
+///
+/// Models pinned to another database with `#[platt(database = "...")]` always
+/// go to that database.
+pub struct PrimaryReplicaRouter {
+    primary: String,
+    replicas: Vec<String>,
+    next_replica: AtomicUsize
+}
+
+impl PrimaryReplicaRouter {
+    pub fn new(primary: impl Into<String>, replicas: Vec<String>) -> Self {
+        Self {
+            primary: primary.into(),
+            replicas,
+            next_replica: AtomicUsize::new(0)
+        }
+    }
+}
+
+impl Default for PrimaryReplicaRouter {
+    fn default() -> Self {
+        Self::new(DEFAULT_DATABASE, Vec::new())
+    }
+}
+
+impl Router for PrimaryReplicaRouter {
+    fn route(&self, operation: Operation, model: &ModelInfo) -> String {
+        if let Some(database) = &model.database {
+            return database.clone();
+        }
+        match operation {
+            Operation::Read if !self.replicas.is_empty() => {
+                let next = self.next_replica.fetch_add(1, Ordering::Relaxed);
+                self.replicas[next % self.replicas.len()].clone()
+            }
+            _ => self.primary.clone()
+        }
+    }
+}
+
+/// The connections available to a single request, keyed by database alias.
+///
+/// With [`Databases::stick_to_primary`] enabled, once anything has been
+/// written every later read in the same request is routed like a write, so
+/// the request sees its own changes even if the replicas lag.
+pub struct Databases<C> {
+    connections: HashMap<String, C>,
+    router: Arc<dyn Router>,
+    sticky: bool,
+    written: bool
+}
+
+impl<C> Databases<C> {
+    pub fn new(router: Arc<dyn Router>) -> Self {
+        Self {
+            connections: HashMap::new(),
+            router,
+            sticky: false,
+            written: false
+        }
+    }
+
+    pub fn add(mut self, alias: impl Into<String>, connection: C) -> Self {
+        self.connections.insert(alias.into(), connection);
+        self
+    }
+
+    pub fn stick_to_primary(mut self) -> Self {
+        self.sticky = true;
+        self
+    }
+
+    pub fn alias_for(&mut self, operation: Operation, model: &ModelInfo) -> String {
+        let operation = match operation {
+            Operation::Read if self.sticky && self.written => Operation::Write,
+            operation => operation
+        };
+        if operation == Operation::Write {
+            self.written = true;
+        }
+        self.router.route(operation, model)
+    }
+
+    pub fn connection<Model: DbModel>(&mut self, operation: Operation) -> Result<&mut C> {
+        let alias = self.alias_for(operation, &ModelInfo::of::<Model>());
+        self.connections.get_mut(&alias).ok_or(Error::UnknownDatabase(alias))
+    }
+
+    pub fn for_read<Model: DbModel>(&mut self) -> Result<&mut C> {
+        self.connection::<Model>(Operation::Read)
+    }
+
+    pub fn for_write<Model: DbModel>(&mut self) -> Result<&mut C> {
+        self.connection::<Model>(Operation::Write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlattModel;
+
+    #[derive(PlattModel)]
+    #[platt(table = "Post")]
+    struct Post {
+        title: String
+    }
+
+    #[derive(PlattModel)]
+    #[platt(table = "Event", database = "analytics")]
+    struct Event {
+        name: String
+    }
+
+    fn replicated() -> PrimaryReplicaRouter {
+        PrimaryReplicaRouter::new("primary", vec!["replica_1".to_string(), "replica_2".to_string()])
+    }
+
+    /// Databases whose connections are stubbed by their alias.
+    fn databases(router: impl Router + 'static) -> Databases<&'static str> {
+        Databases::new(Arc::new(router))
+            .add("primary", "primary")
+            .add("replica_1", "replica_1")
+            .add("replica_2", "replica_2")
+            .add("analytics", "analytics")
+    }
+
+    #[test]
+    fn reads_go_round_robin_over_the_replicas() {
+        let router = replicated();
+        let post = ModelInfo::of::<Post>();
+        let reads: Vec<String> = (0..5).map(|_| router.route(Operation::Read, &post)).collect();
+        assert_eq!(reads, ["replica_1", "replica_2", "replica_1", "replica_2", "replica_1"]);
+    }
+
+    #[test]
+    fn writes_go_to_the_primary() {
+        let router = replicated();
+        let post = ModelInfo::of::<Post>();
+        assert_eq!(router.route(Operation::Write, &post), "primary");
+        assert_eq!(router.route(Operation::Write, &post), "primary");
+
+        let router = PrimaryReplicaRouter::default();
+        assert_eq!(router.route(Operation::Read, &post), DEFAULT_DATABASE);
+        assert_eq!(router.route(Operation::Write, &post), DEFAULT_DATABASE);
+    }
+
+    #[test]
+    fn models_pinned_to_a_database_ignore_the_replicas() {
+        let router = replicated();
+        let event = ModelInfo::of::<Event>();
+        assert_eq!(event.database.as_deref(), Some("analytics"));
+        assert_eq!(router.route(Operation::Read, &event), "analytics");
+        assert_eq!(router.route(Operation::Write, &event), "analytics");
+
+        let mut databases = databases(replicated());
+        assert_eq!(*databases.for_read::<Event>().unwrap(), "analytics");
+        assert_eq!(*databases.for_write::<Event>().unwrap(), "analytics");
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "replica_1");
+    }
+
+    #[test]
+    fn reads_stick_to_the_primary_after_a_write() {
+        let mut databases = databases(replicated()).stick_to_primary();
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "replica_1");
+        assert_eq!(*databases.for_write::<Post>().unwrap(), "primary");
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "primary");
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "primary");
+        assert_eq!(*databases.for_read::<Event>().unwrap(), "analytics");
+    }
+
+    #[test]
+    fn reads_do_not_stick_by_default() {
+        let mut databases = databases(replicated());
+        assert_eq!(*databases.for_write::<Post>().unwrap(), "primary");
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "replica_1");
+        assert_eq!(*databases.for_read::<Post>().unwrap(), "replica_2");
+    }
+
+    #[test]
+    fn unknown_aliases_are_an_error() {
+        let mut databases = Databases::new(Arc::new(replicated())).add("primary", "primary");
+        assert_eq!(*databases.for_write::<Post>().unwrap(), "primary");
+        match databases.for_read::<Post>() {
+            Err(Error::UnknownDatabase(alias)) => assert_eq!(alias, "replica_1"),
+            other => panic!("expected UnknownDatabase, got {:?}", other)
+        }
+        assert!(matches!(databases.for_read::<Event>(), Err(Error::UnknownDatabase(alias)) if alias == "analytics"));
+    }
+}
@@ -1,55 +1,329 @@
+use std::fmt;
+use crate::diff::{Change, SchemaDiff};
+
+/// The name of a table, type or index, and the Postgres schema it lives in,
+/// where `None` is `public`. It displays as the quoted identifier
+/// statements use, e.g. `"content"."Post"`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QualifiedName {
+    pub schema: Option<String>,
+    pub name: String
+}
+
+impl QualifiedName {
+    pub fn new(schema: Option<&str>, name: &str) -> Self {
+        Self { schema: schema.map(str::to_string), name: name.to_string() }
+    }
+}
+
+impl fmt::Display for QualifiedName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(schema) = &self.schema {
+            write!(f, "\"{}\".", schema)?;
+        }
+        write!(f, "\"{}\"", self.name)
+    }
+}
+
+/// The schema named `schema`, where `public` is left out as it is the one
+/// unqualified names are looked up in.
+pub fn schema_name(schema: Option<String>) -> Option<String> {
+    schema.filter(|schema| schema != "public")
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(from = "SavedDbType")]
 pub struct DbType {
     pub base: String,
     pub nullable: bool,
     pub indexed: bool,
     pub primary_key: bool,
-    pub unique: bool
+    pub unique: bool,
+    /// The SQL expression the column is set to when a row leaves it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Whether the column is `GENERATED BY DEFAULT AS IDENTITY`, numbering
+    /// the rows that leave it out the way `SERIAL` and `BIGSERIAL` do.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub identity: bool,
+    /// The row the column points to, if it is a foreign key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<References>
+}
+
+/// How a [`DbType`] is read back. Schemas saved before foreign keys had a
+/// field of their own kept the `REFERENCES` clause at the end of `base`.
+#[derive(serde::Deserialize)]
+struct SavedDbType {
+    base: String,
+    nullable: bool,
+    indexed: bool,
+    primary_key: bool,
+    unique: bool,
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    identity: bool,
+    #[serde(default)]
+    references: Option<References>
+}
+
+impl From<SavedDbType> for DbType {
+    fn from(saved: SavedDbType) -> Self {
+        let mut db_type = DbType {
+            base: saved.base,
+            nullable: saved.nullable,
+            indexed: saved.indexed,
+            primary_key: saved.primary_key,
+            unique: saved.unique,
+            default: saved.default,
+            identity: saved.identity,
+            references: saved.references
+        };
+        if db_type.references.is_none() {
+            let parsed = db_type.base.split_once(" REFERENCES ").and_then(|(base, references)| {
+                let (table, column) = references.strip_suffix(')')?.split_once('(')?;
+                let unquote = |name: &str| name.trim_matches('"').to_string();
+                Some((base.to_string(), References::new(unquote(table), vec![unquote(column)])))
+            });
+            if let Some((base, references)) = parsed {
+                db_type.base = base;
+                db_type.references = Some(references);
+            }
+        }
+        db_type
+    }
+}
+
+/// What happens to a row when the row its foreign key points to is deleted
+/// or has its key changed. Leaving it out is `NO ACTION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ReferentialAction {
+    Cascade,
+    SetNull,
+    SetDefault,
+    Restrict
+}
+
+impl ReferentialAction {
+    fn sql(self) -> &'static str {
+        match self {
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+            ReferentialAction::SetDefault => "SET DEFAULT",
+            ReferentialAction::Restrict => "RESTRICT"
+        }
+    }
+}
+
+/// The `REFERENCES` clause of a foreign key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct References {
+    /// The Postgres schema `table` lives in, `None` being `public`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub table: String,
+    /// The columns of `table` pointed to, its primary key.
+    pub columns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<ReferentialAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_update: Option<ReferentialAction>,
+    /// Whether the constraint is `DEFERRABLE INITIALLY DEFERRED`, i.e.
+    /// checked when the transaction commits rather than after each
+    /// statement.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deferrable: bool
+}
+
+impl References {
+    pub fn new(table: String, columns: Vec<String>) -> Self {
+        Self { schema: None, table, columns, on_delete: None, on_update: None, deferrable: false }
+    }
+
+    pub fn qualified_table(&self) -> QualifiedName {
+        QualifiedName::new(self.schema.as_deref(), &self.table)
+    }
+
+    pub(crate) fn to_sql(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|column| format!("\"{}\"", column)).collect();
+        let table = QualifiedName::new(self.schema.as_deref(), &self.table);
+        let mut sql = format!("REFERENCES {}({})", table, columns.join(", "));
+        if let Some(action) = self.on_delete {
+            sql += &format!(" ON DELETE {}", action.sql());
+        }
+        if let Some(action) = self.on_update {
+            sql += &format!(" ON UPDATE {}", action.sql());
+        }
+        if self.deferrable {
+            sql += " DEFERRABLE INITIALLY DEFERRED";
+        }
+        sql
+    }
 }
 
 impl DbType {
     pub(crate) fn db_type_string(&self) -> String {
         let mut base = self.base.clone();
+        if let Some(default) = &self.default {
+            base += &format!(" DEFAULT {}", default);
+        }
+        if self.identity {
+            base += " GENERATED BY DEFAULT AS IDENTITY";
+        }
         if !self.nullable {
             base += " NOT NULL";
         }
-        if self.indexed {
-            base += " INDEXED";
-        }
         if self.primary_key {
             base += " PRIMARY KEY";
         }
         if self.unique {
             base += " UNIQUE";
         }
+        if let Some(references) = &self.references {
+            base += " ";
+            base += &references.to_sql();
+        }
         base
     }
 
     pub(crate) fn db_type_string_simple(&self) -> String {
         self.base.clone()
     }
+
+    /// Turns a `TEXT` or `VARCHAR` type, or an array of either, into a
+    /// `VARCHAR(max_length)` one.
+    ///
+    /// # Panics
+    ///
+    /// On any other type, which the `PlattModel` derive already refuses for
+    /// fields with a `max_length`.
+    pub fn limit_length(&mut self, max_length: usize) {
+        let element = self.base.trim_end_matches(|c: char| c == '[' || c == ']' || c.is_ascii_digit());
+        let dimensions = self.base[element.len()..].to_string();
+        if element != "TEXT" && !(element.starts_with("VARCHAR(") && element.ends_with(')')) {
+            panic!("max_length only applies to text columns, not {}.", self.base);
+        }
+        self.base = format!("VARCHAR({}){}", max_length, dimensions);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Composite {
     pub name: String,
-    pub fields: Vec<(String, DbType)>
+    /// The Postgres schema the type lives in, `None` being `public`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub fields: Vec<(String, DbType)>,
+    /// The name the type had before, see [`Table::with_renamed_from`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    /// The fields that were renamed, as `(old, new)` pairs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_fields: Vec<(String, String)>
+}
+
+impl Composite {
+    pub fn qualified_name(&self) -> QualifiedName {
+        QualifiedName::new(self.schema.as_deref(), &self.name)
+    }
+
+    pub(crate) fn create_sql(&self) -> String {
+        let fields: Vec<String> = self.fields.iter()
+            .map(|(name, db_type)| format!("\"{}\" {}", name, db_type.db_type_string_simple()))
+            .collect();
+        format!("CREATE TYPE {} AS ({})", self.qualified_name(), fields.join(", "))
+    }
+}
+
+/// A type created with `CREATE TYPE ... AS ENUM`, holding one of `labels`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct EnumType {
+    pub name: String,
+    /// The Postgres schema the type lives in, `None` being `public`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    pub labels: Vec<String>,
+    /// The labels that were renamed, as `(old, new)` pairs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_labels: Vec<(String, String)>
+}
+
+impl EnumType {
+    pub fn qualified_name(&self) -> QualifiedName {
+        QualifiedName::new(self.schema.as_deref(), &self.name)
+    }
+
+    pub(crate) fn create_sql(&self) -> String {
+        let labels: Vec<String> = self.labels.iter().map(|label| quote_literal(label)).collect();
+        format!("CREATE TYPE {} AS ENUM ({})", self.qualified_name(), labels.join(", "))
+    }
+}
+
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
 }
 
 pub trait HasDbType {
     fn composites() -> Vec<Composite> {
         Vec::default()
     }
+    fn enums() -> Vec<EnumType> {
+        Vec::default()
+    }
     fn tables() -> Vec<Table> { 
         Vec::default()
     }
     fn db_type() -> DbType;
+    /// The columns a model field called `name` is stored in. Most types take
+    /// a single column of their [`HasDbType::db_type`].
+    fn columns(name: &str) -> Vec<Column> {
+        vec![Column::new(name.to_string(), Self::db_type())]
+    }
+    /// A `CHECK` expression the table needs for a field called `name`.
+    fn check(_name: &str) -> Option<String> {
+        None
+    }
+    /// The foreign keys over several of the columns of a field called
+    /// `name`, as the columns and what they reference. Those over one column
+    /// are kept on it, see [`DbType::references`].
+    fn foreign_keys(_name: &str) -> Vec<(Vec<String>, References)> {
+        Vec::new()
+    }
+    /// Why the column may not hold every value of the type, which
+    /// [`Schema::validate`] reports for the fields of it.
+    fn problems() -> Vec<String> {
+        Vec::new()
+    }
 }
 
 macro_rules! basic_db_type {
     ($rust:ty, $db:expr) => {
         basic_db_type!($rust, $db, false);
     };
+    // An unsigned type stored as the signed one of the same size.
+    ($rust:ty, $db:expr, overflows $signed:ty) => {
+        impl HasDbType for $rust {
+            fn db_type() -> DbType {
+                DbType {
+                    base: $db.to_string(),
+                    nullable: false,
+                    indexed: false,
+                    primary_key: false,
+                    unique: false,
+                    default: None,
+                    identity: false,
+                    references: None
+                }
+            }
+            fn problems() -> Vec<String> {
+                vec![format!(
+                    "{} values above {} overflow the {} it is stored as",
+                    stringify!($rust), <$signed>::MAX, $db
+                )]
+            }
+        }
+    };
     ($rust:ty, $db:expr, $null:expr) => {
         impl HasDbType for $rust {
             fn db_type() -> DbType {
@@ -58,7 +332,10 @@ macro_rules! basic_db_type {
                     nullable: $null,
                     indexed: false,
                     primary_key: false,
-                    unique: false
+                    unique: false,
+                    default: None,
+                    identity: false,
+                    references: None
                 }
             }
         }
@@ -67,9 +344,9 @@ macro_rules! basic_db_type {
 
 basic_db_type!(bool, "BOOLEAN");
 basic_db_type!(u8, "SMALLINT");
-basic_db_type!(u16, "SMALLINT");
-basic_db_type!(u32, "INT");
-basic_db_type!(u64, "BIGINT");
+basic_db_type!(u16, "SMALLINT", overflows i16);
+basic_db_type!(u32, "INT", overflows i32);
+basic_db_type!(u64, "BIGINT", overflows i64);
 basic_db_type!(i8, "SMALLINT");
 basic_db_type!(i16, "SMALLINT");
 basic_db_type!(i32, "INT");
@@ -86,12 +363,40 @@ pub mod non_zero {
     basic_db_type!(NonZeroI32, "INT");
     basic_db_type!(NonZeroI64, "BIGINT");
     basic_db_type!(NonZeroU8, "SMALLINT");
-    basic_db_type!(NonZeroU16, "SMALLINT");
-    basic_db_type!(NonZeroU32, "INT");
-    basic_db_type!(NonZeroU64, "BIGINT");
+    basic_db_type!(NonZeroU16, "SMALLINT", overflows i16);
+    basic_db_type!(NonZeroU32, "INT", overflows i32);
+    basic_db_type!(NonZeroU64, "BIGINT", overflows i64);
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Varchar<const SIZE: usize>(String);
+
+impl<const SIZE: usize> Varchar<SIZE> {
+    /// Returns `None` if `value` is longer than `SIZE` characters.
+    pub fn new(value: impl Into<String>) -> Option<Self> {
+        let value = value.into();
+        if value.chars().count() > SIZE {
+            return None;
+        }
+        Some(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl<const SIZE: usize> std::ops::Deref for Varchar<SIZE> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
 impl<const SIZE: usize> HasDbType for Varchar<SIZE> {
     fn db_type() -> DbType {
         DbType {
@@ -99,12 +404,56 @@ impl<const SIZE: usize> HasDbType for Varchar<SIZE> {
             nullable: false,
             indexed: false,
             primary_key: false,
-            unique: false
+            unique: false,
+            default: None,
+            identity: false,
+            references: None
         }
     }
 }
 
+/// The types stored in text columns, the only ones
+/// `#[platt(max_length = N)]` can turn into `VARCHAR(N)`.
+#[diagnostic::on_unimplemented(message = "`max_length` only applies to text fields, not `{Self}`")]
+pub trait TextType { }
+
+impl TextType for String { }
+impl<const SIZE: usize> TextType for Varchar<SIZE> { }
+impl<T: TextType> TextType for Option<T> { }
+impl<T: TextType> TextType for Vec<T> { }
+impl<T: TextType, const N: usize> TextType for [T; N] { }
+
+/// A `CHAR(SIZE)` value. Postgres pads shorter values with spaces, so values
+/// read back are always `SIZE` characters long.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExactString<const SIZE: usize>(String);
+
+impl<const SIZE: usize> ExactString<SIZE> {
+    /// Returns `None` if `value` is longer than `SIZE` characters.
+    pub fn new(value: impl Into<String>) -> Option<Self> {
+        let value = value.into();
+        if value.chars().count() > SIZE {
+            return None;
+        }
+        Some(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl<const SIZE: usize> std::ops::Deref for ExactString<SIZE> {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
 impl<const SIZE: usize> HasDbType for ExactString<SIZE> {
     fn db_type() -> DbType {
         DbType {
@@ -112,12 +461,74 @@ impl<const SIZE: usize> HasDbType for ExactString<SIZE> {
             nullable: false,
             indexed: false,
             primary_key: false,
-            unique: false
+            unique: false,
+            default: None,
+            identity: false,
+            references: None
+        }
+    }
+}
+
+/// A `DECIMAL(BEFORE, AFTER)` value, stored as an integer number of
+/// `10^-AFTER` units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Decimal<const BEFORE: u16, const AFTER: u16>(i128);
+
+impl<const BEFORE: u16, const AFTER: u16> Decimal<BEFORE, AFTER> {
+    /// The decimal `unscaled * 10^-AFTER`, or `None` if that has more than
+    /// `BEFORE` digits.
+    pub fn from_unscaled(unscaled: i128) -> Option<Self> {
+        if unscaled.unsigned_abs().to_string().len() > BEFORE as usize {
+            return None;
+        }
+        Some(Self(unscaled))
+    }
+
+    pub fn unscaled(&self) -> i128 {
+        self.0
+    }
+}
+
+impl<const BEFORE: u16, const AFTER: u16> std::fmt::Display for Decimal<BEFORE, AFTER> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scale = AFTER as usize;
+        let digits = format!("{:0>width$}", self.0.unsigned_abs(), width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        f.write_str(integer)?;
+        if scale > 0 {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const BEFORE: u16, const AFTER: u16> std::str::FromStr for Decimal<BEFORE, AFTER> {
+    type Err = crate::error::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::error::Error::Decode(format!("invalid DECIMAL({}, {}) {:?}", BEFORE, AFTER, value));
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, value.strip_prefix('+').unwrap_or(value))
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let scale = AFTER as usize;
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+            || fraction[scale.min(fraction.len())..].chars().any(|c| c != '0') {
+            return Err(invalid());
         }
+        let fraction = &fraction[..scale.min(fraction.len())];
+        let unscaled = format!("{}{}{}", integer, fraction, "0".repeat(scale - fraction.len()));
+        let unscaled = unscaled.trim_start_matches('0');
+        let unscaled: i128 = if unscaled.is_empty() { 0 } else { unscaled.parse().map_err(|_| invalid())? };
+        Self::from_unscaled(if negative { -unscaled } else { unscaled }).ok_or_else(invalid)
     }
 }
 
-pub struct Decimal<const BEFORE: u16, const AFTER: u16>();
 impl<const BEFORE: u16, const AFTER: u16> HasDbType for Decimal<BEFORE, AFTER> {
     fn db_type() -> DbType {
         DbType {
@@ -125,12 +536,16 @@ impl<const BEFORE: u16, const AFTER: u16> HasDbType for Decimal<BEFORE, AFTER> {
             nullable: false,
             indexed: false,
             primary_key: false,
-            unique: false
+            unique: false,
+            default: None,
+            identity: false,
+            references: None
         }
     }
 }
 
-pub struct BitStringFixed<const SIZE: usize>([bool; SIZE]);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitStringFixed<const SIZE: usize>(pub [bool; SIZE]);
 impl<const SIZE: usize> HasDbType for BitStringFixed<SIZE> {
     fn db_type() -> DbType {
         DbType {
@@ -138,20 +553,27 @@ impl<const SIZE: usize> HasDbType for BitStringFixed<SIZE> {
             nullable: false,
             indexed: false,
             primary_key: false,
-            unique: false
+            unique: false,
+            default: None,
+            identity: false,
+            references: None
         }
     }
 }
 
-pub struct BitString(Vec<bool>);
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BitString(pub Vec<bool>);
 impl HasDbType for BitString {
     fn db_type() -> DbType {
         DbType {
-            base: format!("BIT VARYING"),
+            base: "BIT VARYING".to_string(),
             nullable: false,
             indexed: false,
             primary_key: false,
-            unique: false
+            unique: false,
+            default: None,
+            identity: false,
+            references: None
         }
     }
 }
@@ -169,7 +591,10 @@ pub mod chrono_type {
                 nullable: false,
                 indexed: false,
                 primary_key: false,
-                unique: false
+                unique: false,
+                default: None,
+                identity: false,
+                references: None
             }
         }
     }
@@ -177,13 +602,15 @@ pub mod chrono_type {
 #[cfg(feature = "uuid_type")]
 pub mod uuid_type {
     use super::*;
-    basic_db_type!(uuid::Uuid, "Uuid");
+    basic_db_type!(uuid::Uuid, "UUID");
 }
 
 #[cfg(feature = "json")]
 pub mod json {
     use super::*;
-    pub struct LenientJson(serde_json::Value);
+    /// Stored as `JSON` rather than `JSONB`, keeping the text as it was sent.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct LenientJson(pub serde_json::Value);
     basic_db_type!(LenientJson, "JSON");
     basic_db_type!(serde_json::Value, "JSONB");
 }
@@ -192,87 +619,596 @@ impl<T: HasDbType> HasDbType for Option<T> {
     fn composites() -> Vec<Composite> {
         T::composites()
     }
+    fn enums() -> Vec<EnumType> {
+        T::enums()
+    }
     fn db_type() -> DbType {
         let mut raw = T::db_type();
         raw.nullable = true;
         raw
     }
+    fn columns(name: &str) -> Vec<Column> {
+        let mut columns = T::columns(name);
+        for column in &mut columns {
+            column.db_type.nullable = true;
+        }
+        columns
+    }
+    fn check(name: &str) -> Option<String> {
+        T::check(name)
+    }
+    fn foreign_keys(name: &str) -> Vec<(Vec<String>, References)> {
+        T::foreign_keys(name)
+    }
+    fn problems() -> Vec<String> {
+        T::problems()
+    }
 }
 
 impl<T: HasDbType> HasDbType for Vec<T> {
     fn composites() -> Vec<Composite> {
         T::composites()
     }
+    fn enums() -> Vec<EnumType> {
+        T::enums()
+    }
     fn db_type() -> DbType {
         let mut raw = T::db_type();
         raw.base = format!("{}[]", raw.base);
         raw
     }
+    fn problems() -> Vec<String> {
+        T::problems()
+    }
 }
 
 impl<T: HasDbType, const N: usize> HasDbType for [T; N] {
     fn composites() -> Vec<Composite> {
         T::composites()
     }
+    fn enums() -> Vec<EnumType> {
+        T::enums()
+    }
     fn db_type() -> DbType {
         let mut raw = T::db_type();
         raw.base = format!("{}[{}]", raw.base, N);
         raw
     }
+    fn problems() -> Vec<String> {
+        T::problems()
+    }
+}
+
+/// A reference to the row of `Other` with the given primary key.
+///
+/// It is stored in columns of the types of those of the key: one named
+/// after the field for a key of one column, and one per key column, named
+/// `<field>_<column>`, for a composite key.
+pub struct ForeignKey<Other: DbModel>(Other::PrimaryKey, std::marker::PhantomData<Other>);
+
+impl<Other: DbModel> ForeignKey<Other> {
+    pub fn new(key: Other::PrimaryKey) -> Self {
+        Self(key, std::marker::PhantomData)
+    }
+
+    pub fn key(&self) -> &Other::PrimaryKey {
+        &self.0
+    }
+}
+
+impl<Other: DbModel<PrimaryKey = uuid::Uuid>> ForeignKey<Other> {
+    pub fn id(&self) -> uuid::Uuid {
+        self.0
+    }
+}
+
+impl<Other: DbModel> Clone for ForeignKey<Other> where Other::PrimaryKey: Clone {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<Other: DbModel> std::fmt::Debug for ForeignKey<Other> where Other::PrimaryKey: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ForeignKey").field(&self.0).finish()
+    }
+}
+
+/// The type of a column holding the values of `key`, a column of a primary
+/// key.
+fn referencing(key: &DbType) -> DbType {
+    DbType { base: key.base.clone(), ..DbType::default() }
 }
 
-pub struct ForeignKey<Other: DbModel>(std::marker::PhantomData<Other>);
 impl<Other: DbModel> HasDbType for ForeignKey<Other> {
+    /// The type of the column referencing a key of one column, or of the
+    /// first of the columns referencing a composite key.
     fn db_type() -> DbType {
-        DbType {
-            base: format!("UUID REFERENCES {}(\"id\")", Other::table_name()),
-            nullable: false,
-            indexed: false,
-            primary_key: false,
-            unique: false
+        let key = Other::key_columns();
+        let mut db_type = referencing(key[0].db_type());
+        if key.len() == 1 {
+            db_type.references = Some(References {
+                schema: Other::schema(),
+                ..References::new(Other::table_name(), vec![key[0].name.clone()])
+            });
+        }
+        db_type
+    }
+
+    fn columns(name: &str) -> Vec<Column> {
+        let key = Other::key_columns();
+        if key.len() == 1 {
+            return vec![Column::new(name.to_string(), Self::db_type())];
         }
+        key.iter()
+            .map(|column| Column::new(format!("{}_{}", name, column.name), referencing(&column.db_type)))
+            .collect()
+    }
+
+    fn foreign_keys(name: &str) -> Vec<(Vec<String>, References)> {
+        let key = Other::key_columns();
+        if key.len() == 1 {
+            return Vec::new();
+        }
+        let columns = key.iter().map(|column| format!("{}_{}", name, column.name)).collect();
+        let references = References {
+            schema: Other::schema(),
+            ..References::new(Other::table_name(), key.into_iter().map(|column| column.name).collect())
+        };
+        vec![(columns, references)]
     }
 }
 
+/// The types of the fields `#[platt(reverse, on_delete, on_update,
+/// deferrable)]` apply to: a `ForeignKey` and an optional one.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a `ForeignKey`, so it takes no `reverse`, `on_delete`, `on_update` or `deferrable`"
+)]
+pub trait ForeignKeyType { }
+
+impl<Other: DbModel> ForeignKeyType for ForeignKey<Other> { }
+impl<Other: DbModel> ForeignKeyType for Option<ForeignKey<Other>> { }
+
+/// The foreign keys that can be set to NULL, the only ones
+/// `on_delete = "set_null"` and `on_update = "set_null"` apply to.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can not be NULL, so it can not be set to NULL",
+    label = "use an `Option<ForeignKey<_>>`"
+)]
+pub trait NullableForeignKey: ForeignKeyType { }
+
+impl<Other: DbModel> NullableForeignKey for Option<ForeignKey<Other>> { }
+
 pub struct ManyToManyRelationship<Other: DbModel>(std::marker::PhantomData<Other>);
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Column {
     name: String,
-    db_type: DbType
+    db_type: DbType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    renamed_from: Option<String>,
+    /// The model field the column stores, when it comes from a model.
+    #[serde(skip)]
+    field: Option<String>,
+    #[serde(skip)]
+    problems: Vec<String>
 }
 
 impl Column {
     pub fn new(name: String, db_type: DbType) -> Self {
         Self { 
             name, 
-            db_type
+            db_type,
+            renamed_from: None,
+            field: None,
+            problems: Vec::new()
+        }
+    }
+
+    pub fn with_field(mut self, field: Option<String>) -> Self {
+        self.field = field;
+        self
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    /// Records why the column may not hold every value of its field, see
+    /// [`HasDbType::problems`].
+    pub fn with_problems(mut self, problems: Vec<String>) -> Self {
+        self.problems = problems;
+        self
+    }
+
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    /// Records the name the column had before, see
+    /// [`Table::with_renamed_from`].
+    pub fn with_renamed_from(mut self, renamed_from: Option<String>) -> Self {
+        self.renamed_from = renamed_from;
+        self
+    }
+
+    pub fn renamed_from(&self) -> Option<&str> {
+        self.renamed_from.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn db_type(&self) -> &DbType {
+        &self.db_type
+    }
+
+    pub fn db_type_mut(&mut self) -> &mut DbType {
+        &mut self.db_type
+    }
+}
+
+/// A named `CHECK` constraint on a table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Check {
+    pub name: String,
+    pub expr: String
+}
+
+impl Check {
+    pub(crate) fn to_sql(&self) -> String {
+        format!("CONSTRAINT \"{}\" CHECK ({})", self.name, self.expr)
+    }
+}
+
+/// A named `UNIQUE` constraint on a table, usually over several columns.
+/// Constraints on a single column are kept on the column, see
+/// [`DbType::unique`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Unique {
+    pub name: String,
+    pub columns: Vec<String>
+}
+
+impl Unique {
+    /// A constraint on `columns` of `table`, named the way Postgres names
+    /// them, e.g. `Post_author_slug_key`.
+    pub fn new(table: &str, columns: Vec<String>) -> Self {
+        Self { name: format!("{}_{}_key", table, columns.join("_")), columns }
+    }
+
+    pub(crate) fn to_sql(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|column| format!("\"{}\"", column)).collect();
+        format!("CONSTRAINT \"{}\" UNIQUE ({})", self.name, columns.join(", "))
+    }
+}
+
+/// A foreign key over several columns of a table, pointing to a composite
+/// primary key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct ForeignKeyConstraint {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub references: References
+}
+
+impl ForeignKeyConstraint {
+    /// A foreign key on `columns` of `table`, named the way Postgres names
+    /// them, e.g. `Seat_booking_flight_booking_number_fkey`.
+    pub fn new(table: &str, columns: Vec<String>, references: References) -> Self {
+        Self { name: format!("{}_{}_fkey", table, columns.join("_")), columns, references }
+    }
+
+    pub(crate) fn to_sql(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|column| format!("\"{}\"", column)).collect();
+        format!("CONSTRAINT \"{}\" FOREIGN KEY ({}) {}", self.name, columns.join(", "), self.references.to_sql())
+    }
+}
+
+/// An index on a table, created apart from it with `CREATE INDEX`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct Index {
+    pub name: String,
+    /// What the index covers, in order: column names, or expressions such
+    /// as `lower("email")`.
+    pub keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unique: bool,
+    /// The index method, e.g. `gin` or `gist`, or `None` for a B-tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
+    /// The `WHERE` condition of a partial index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predicate: Option<String>
+}
+
+impl Index {
+    /// A B-tree index on `keys` of `table`, named the way Postgres names
+    /// indexes, e.g. `User_email_idx`.
+    pub fn new(table: &str, keys: Vec<String>) -> Self {
+        let mut name = table.to_string();
+        for key in &keys {
+            let key: String = key.chars().filter(|c| c.is_alphanumeric() || *c == '_' || *c == ' ').collect();
+            name += "_";
+            name += &key.split_whitespace().collect::<Vec<_>>().join("_");
+        }
+        Self { name: name + "_idx", keys, unique: false, using: None, predicate: None }
+    }
+
+    pub(crate) fn create_sql(&self, table: &QualifiedName) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|key| {
+            if key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                format!("\"{}\"", key)
+            } else {
+                format!("({})", key)
+            }
+        }).collect();
+        let mut sql = format!(
+            "CREATE {}INDEX \"{}\" ON {}",
+            if self.unique { "UNIQUE " } else { "" },
+            self.name,
+            table
+        );
+        if let Some(using) = &self.using {
+            sql += &format!(" USING {}", using);
+        }
+        sql += &format!(" ({})", keys.join(", "));
+        if let Some(predicate) = &self.predicate {
+            sql += &format!(" WHERE {}", predicate);
         }
+        sql
     }
 }
 
 pub trait DbModel {
+    /// The Rust type of the primary key: `uuid::Uuid` for the `id` column
+    /// models get unless fields are marked `primary_key`, the type of the
+    /// field if one is, or a tuple of the types of those that are.
+    type PrimaryKey;
+    /// The name of the table, the name of the model unless it says
+    /// otherwise with `#[platt(table = "...")]` or the crate follows a
+    /// naming convention.
     fn table_name() -> String;
+    /// The Postgres schema the table lives in, `None` being `public`.
+    fn schema() -> Option<String> {
+        None
+    }
+    /// The columns of the primary key, in order.
+    fn key_columns() -> Vec<Column>;
+    /// The database alias this model lives in, or `None` for the default one.
+    fn database() -> Option<String> {
+        None
+    }
     fn activate(schema: &mut Schema);
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Table {
     name: String,
-    columns: Vec<Column>
+    columns: Vec<Column>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    database: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<Check>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    renamed_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    indexes: Vec<Index>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    uniques: Vec<Unique>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    foreign_keys: Vec<ForeignKeyConstraint>,
+    /// The model the table comes from, which saved schemas leave out.
+    #[serde(skip)]
+    model: Option<String>
 }
 
 impl Table {
     pub fn new(name: String, columns: Vec<Column>) -> Self {
         Self { 
             name, 
-            columns
+            columns,
+            schema: None,
+            database: None,
+            checks: Vec::new(),
+            renamed_from: None,
+            indexes: Vec::new(),
+            uniques: Vec::new(),
+            foreign_keys: Vec::new(),
+            model: None
+        }
+    }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn with_foreign_keys(mut self, foreign_keys: Vec<ForeignKeyConstraint>) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    pub fn foreign_keys(&self) -> &[ForeignKeyConstraint] {
+        &self.foreign_keys
+    }
+
+    /// The tables the foreign keys of the table reference, itself included
+    /// when it references itself.
+    pub fn referenced_tables(&self) -> Vec<QualifiedName> {
+        let mut tables = Vec::new();
+        let references = self.columns.iter().filter_map(|column| column.db_type.references.as_ref())
+            .chain(self.foreign_keys.iter().map(|foreign_key| &foreign_key.references));
+        for table in references.map(References::qualified_table) {
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+        tables
+    }
+
+    /// The table without its foreign keys to `tables`, and those foreign
+    /// keys: the columns referencing one, and the constraints over several
+    /// columns doing so.
+    pub(crate) fn split_foreign_keys(
+        &self,
+        tables: &[QualifiedName]
+    ) -> (Table, Vec<(String, References)>, Vec<ForeignKeyConstraint>) {
+        let mut table = self.clone();
+        let mut columns = Vec::new();
+        for column in &mut table.columns {
+            if let Some(references) = column.db_type.references.take() {
+                if tables.contains(&references.qualified_table()) {
+                    columns.push((column.name.clone(), references));
+                } else {
+                    column.db_type.references = Some(references);
+                }
+            }
         }
+        let (constraints, kept) = table.foreign_keys.into_iter()
+            .partition(|foreign_key| tables.contains(&foreign_key.references.qualified_table()));
+        table.foreign_keys = kept;
+        (table, columns, constraints)
+    }
+
+    /// The changes adding back the foreign keys of the table to `tables`,
+    /// for when it is created without them.
+    pub(crate) fn add_foreign_keys(&self, tables: &[QualifiedName]) -> (Table, Vec<Change>) {
+        let (table, columns, constraints) = self.split_foreign_keys(tables);
+        let name = self.qualified_name();
+        let mut changes: Vec<Change> = columns.into_iter()
+            .map(|(column, references)| Change::AddForeignKey { table: name.clone(), column, references })
+            .collect();
+        changes.extend(constraints.into_iter()
+            .map(|foreign_key| Change::AddForeignKeyConstraint { table: name.clone(), foreign_key }));
+        (table, changes)
+    }
+
+    /// The names of the columns of the primary key, in order.
+    pub fn primary_key(&self) -> Vec<&str> {
+        self.columns.iter().filter(|column| column.db_type.primary_key).map(|column| column.name.as_str()).collect()
+    }
+
+    pub fn with_uniques(mut self, uniques: Vec<Unique>) -> Self {
+        self.uniques = uniques;
+        self
+    }
+
+    pub fn uniques(&self) -> &[Unique] {
+        &self.uniques
+    }
+
+    pub fn with_indexes(mut self, indexes: Vec<Index>) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    /// The indexes declared on the table, leaving out those of its
+    /// `indexed` columns.
+    pub fn indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+
+    /// Every index of the table, including one per `indexed` column.
+    pub fn all_indexes(&self) -> Vec<Index> {
+        let mut indexes: Vec<Index> = self.columns.iter()
+            .filter(|column| column.db_type.indexed)
+            .map(|column| Index::new(&self.name, vec![column.name.clone()]))
+            .collect();
+        indexes.extend(self.indexes.iter().cloned());
+        indexes
+    }
+
+    /// Records the name the table had before. A diff from a schema with a
+    /// table of that name, and none of this one, renames it rather than
+    /// dropping it and creating this one, and the diff back renames it back.
+    pub fn with_renamed_from(mut self, renamed_from: Option<String>) -> Self {
+        self.renamed_from = renamed_from;
+        self
+    }
+
+    pub fn renamed_from(&self) -> Option<&str> {
+        self.renamed_from.as_deref()
+    }
+
+    pub fn with_checks(mut self, checks: Vec<Check>) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Puts the table in the Postgres schema `schema` rather than `public`.
+    pub fn with_schema(mut self, schema: Option<String>) -> Self {
+        self.schema = schema_name(schema);
+        self
+    }
+
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    pub fn qualified_name(&self) -> QualifiedName {
+        QualifiedName::new(self.schema(), &self.name)
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn database(&self) -> Option<&str> {
+        self.database.as_deref()
+    }
+
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    pub(crate) fn create_sql(&self) -> String {
+        // A key of several columns can not be declared on each of them.
+        let primary_key = self.primary_key();
+        let mut definitions: Vec<String> = self.columns.iter()
+            .map(|column| {
+                let mut db_type = column.db_type.clone();
+                db_type.primary_key &= primary_key.len() == 1;
+                format!("\"{}\" {}", column.name, db_type.db_type_string())
+            })
+            .collect();
+        if primary_key.len() > 1 {
+            let columns: Vec<String> = primary_key.iter().map(|column| format!("\"{}\"", column)).collect();
+            definitions.push(format!("CONSTRAINT \"{}_pkey\" PRIMARY KEY ({})", self.name, columns.join(", ")));
+        }
+        for unique in &self.uniques {
+            definitions.push(unique.to_sql());
+        }
+        for foreign_key in &self.foreign_keys {
+            definitions.push(foreign_key.to_sql());
+        }
+        for check in &self.checks {
+            definitions.push(check.to_sql());
+        }
+        format!("CREATE TABLE {} ({})", self.qualified_name(), definitions.join(", "))
+    }
+
+    pub fn with_database(mut self, database: Option<String>) -> Self {
+        self.database = database;
+        self
     }
 }
 
-#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Schema {
+    #[serde(default)]
+    enums: Vec<EnumType>,
     composites: Vec<Composite>,
     tables: Vec<Table>
 }
@@ -286,54 +1222,192 @@ impl Schema {
         self.tables.extend(table);
     }
 
+    /// Adds the composite types not already in the schema, as every model
+    /// and type holding one adds it again.
     pub fn add_composites(&mut self, composites: Vec<Composite>) {
-        self.composites.extend(composites);
+        for composite in composites {
+            if !self.composites.iter().any(|other| other.qualified_name() == composite.qualified_name()) {
+                self.composites.push(composite);
+            }
+        }
     }
 
-    pub fn to_sql(&self) -> String {
-        let mut sql = String::new();
-        for composite in &self.composites {
-            let mut composite_sql = format!("CREATE TYPE \"{}\" AS (", composite.name);
-            for (name, db_type) in &composite.fields {
-                composite_sql += &format!("\"{}\" {}, ", name, db_type.db_type_string_simple());
+    /// Adds the enums not already in the schema, see
+    /// [`add_composites`](Self::add_composites).
+    pub fn add_enums(&mut self, enums: Vec<EnumType>) {
+        for enum_type in enums {
+            if !self.enums.iter().any(|other| other.qualified_name() == enum_type.qualified_name()) {
+                self.enums.push(enum_type);
+            }
+        }
+    }
+
+    /// The part of the schema that lives in `database`, where `None` is the
+    /// default database. Types are kept in every database.
+    pub fn for_database(&self, database: Option<&str>) -> Self {
+        Self {
+            enums: self.enums.clone(),
+            composites: self.composites.clone(),
+            tables: self.tables.iter()
+                .filter(|table| table.database.as_deref() == database)
+                .cloned()
+                .collect()
+        }
+    }
+
+    pub fn enums(&self) -> &[EnumType] {
+        &self.enums
+    }
+
+    pub fn composites(&self) -> &[Composite] {
+        &self.composites
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    /// The Postgres schemas other than `public` that tables and types live
+    /// in, in the order they first appear.
+    pub fn schemas(&self) -> Vec<&str> {
+        let mut schemas = Vec::new();
+        let used = self.enums.iter().map(|enum_type| enum_type.schema.as_deref())
+            .chain(self.composites.iter().map(|composite| composite.schema.as_deref()))
+            .chain(self.tables.iter().map(Table::schema));
+        for schema in used.flatten() {
+            if !schemas.contains(&schema) {
+                schemas.push(schema);
             }
-            composite_sql.pop();
-            composite_sql.pop();
-            composite_sql += ");\n";
-            sql += &composite_sql;
         }
+        schemas
+    }
 
+    /// The composite types in an order they can be created in, each after
+    /// the ones it holds.
+    pub fn composites_in_order(&self) -> Vec<&Composite> {
+        let names: Vec<String> = self.composites.iter().map(|composite| composite.qualified_name().to_string()).collect();
+        let (order, _) = dependency_order(self.composites.len(), |composite, other| {
+            self.composites[composite].fields.iter().any(|(_, db_type)| element_type(&db_type.base) == names[other])
+        });
+        order.into_iter().map(|composite| &self.composites[composite]).collect()
+    }
+
+    /// The statements creating the schema from scratch. Types and tables
+    /// come after the ones they depend on, and foreign keys closing a
+    /// reference cycle are added after the tables.
+    pub fn to_sql(&self) -> String {
+        let mut sql = String::new();
+        for schema in self.schemas() {
+            sql += &format!("CREATE SCHEMA IF NOT EXISTS \"{}\";\n", schema);
+        }
+        // Enums come first as composites may hold them.
+        for enum_type in &self.enums {
+            sql += &enum_type.create_sql();
+            sql += ";\n";
+        }
+        for composite in self.composites_in_order() {
+            sql += &composite.create_sql();
+            sql += ";\n";
+        }
+        let tables: Vec<&Table> = self.tables.iter().collect();
+        let mut deferred = Vec::new();
+        for (table, cycle) in creation_order(&tables) {
+            let (table, add_foreign_keys) = table.add_foreign_keys(&cycle);
+            sql += &table.create_sql();
+            sql += ";\n";
+            deferred.extend(add_foreign_keys);
+        }
+        for change in deferred {
+            sql += &change.to_sql();
+            sql += ";\n";
+        }
         for table in &self.tables {
-            let mut table_sql = format!("CREATE TABLE \"{}\" (", table.name);
-            for column in &table.columns {
-                table_sql += &format!("\"{}\" {}, ", column.name, column.db_type.db_type_string());
+            for index in table.all_indexes() {
+                sql += &index.create_sql(&table.qualified_name());
+                sql += ";\n";
             }
-            table_sql.pop();
-            table_sql.pop();
-            table_sql += ");\n";
-            sql += &table_sql;
         }
         sql
     }
 
-    pub fn diff(&self) -> Result<(), Box<dyn std::error::Error>>
-    {
-        use std::fs::File;
-        use std::io::Read;
-        let current_schema: Self = {
-            if let Ok(mut file) = File::open("current_schema.json") {
-                let mut current_schema = String::new();
-                file.read_to_string(&mut current_schema)?;
-                serde_json::from_str(&current_schema)?
-            } else {
-                Self::empty()
+    /// The changes turning `current`, e.g. the schema a database was last
+    /// migrated to, into this one.
+    pub fn diff(&self, current: &Schema) -> SchemaDiff {
+        SchemaDiff::between(current, self)
+    }
+}
+
+/// The type an array type `base` holds, or `base` itself.
+pub(crate) fn element_type(base: &str) -> &str {
+    let mut element = base;
+    while let Some(rest) = element.strip_suffix(']') {
+        match rest.rfind('[') {
+            Some(start) => element = &rest[..start],
+            None => break
+        }
+    }
+    element
+}
+
+/// The items `0..count` ordered so that each comes after those it
+/// `depends_on`, and in the order they are numbered otherwise. A dependency
+/// closing a cycle can not be honoured, and is returned as a
+/// `(dependent, dependency)` pair instead.
+fn dependency_order(count: usize, depends_on: impl Fn(usize, usize) -> bool) -> (Vec<usize>, Vec<(usize, usize)>) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Visited
+    }
+
+    fn visit(
+        item: usize,
+        depends_on: &dyn Fn(usize, usize) -> bool,
+        states: &mut [State],
+        order: &mut Vec<usize>,
+        cycles: &mut Vec<(usize, usize)>
+    ) {
+        states[item] = State::Visiting;
+        for other in 0..states.len() {
+            if other == item || !depends_on(item, other) {
+                continue;
             }
-        };
+            match states[other] {
+                State::Unvisited => visit(other, depends_on, states, order, cycles),
+                State::Visiting => cycles.push((item, other)),
+                State::Visited => ()
+            }
+        }
+        states[item] = State::Visited;
+        order.push(item);
+    }
 
-        dbg!(current_schema);
-        dbg!(self);
-        Ok(())
+    let mut states = vec![State::Unvisited; count];
+    let (mut order, mut cycles) = (Vec::new(), Vec::new());
+    for item in 0..count {
+        if states[item] == State::Unvisited {
+            visit(item, &depends_on, &mut states, &mut order, &mut cycles);
+        }
     }
+    (order, cycles)
+}
+
+/// `tables` in an order they can be created in, each after the tables it
+/// references, along with the tables it references that come after it as
+/// they close a reference cycle. Their foreign keys have to be added once
+/// both tables exist. Tables referencing themselves need no such care.
+pub(crate) fn creation_order<'a>(tables: &[&'a Table]) -> Vec<(&'a Table, Vec<QualifiedName>)> {
+    let names: Vec<QualifiedName> = tables.iter().map(|table| table.qualified_name()).collect();
+    let referenced: Vec<Vec<QualifiedName>> = tables.iter().map(|table| table.referenced_tables()).collect();
+    let (order, cycles) = dependency_order(tables.len(), |table, other| referenced[table].contains(&names[other]));
+    order.into_iter().map(|table| {
+        let cycle = cycles.iter()
+            .filter(|(dependent, _)| *dependent == table)
+            .map(|(_, dependency)| names[*dependency].clone())
+            .collect();
+        (tables[table], cycle)
+    }).collect()
 }
 
 pub fn primary_key() -> DbType {
@@ -342,6 +1416,95 @@ pub fn primary_key() -> DbType {
         nullable: false,
         indexed: false,
         primary_key: true,
-        unique: true
+        unique: true,
+        default: None,
+        identity: false,
+        references: None
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlattModel;
+
+    fn table(name: &str, references: &[&str]) -> Table {
+        let mut columns = vec![Column::new("id".to_string(), primary_key())];
+        columns.extend(references.iter().map(|table| {
+            let references = References::new(table.to_string(), vec!["id".to_string()]);
+            Column::new(format!("{}_id", table), DbType { base: "UUID".to_string(), references: Some(references), ..DbType::default() })
+        }));
+        Table::new(name.to_string(), columns)
     }
-}
\ No newline at end of file
+
+    fn order(tables: &[Table]) -> Vec<(String, Vec<String>)> {
+        let tables: Vec<&Table> = tables.iter().collect();
+        creation_order(&tables).into_iter()
+            .map(|(table, cycle)| (table.name().to_string(), cycle.iter().map(|name| name.name.clone()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn creation_order_puts_referenced_tables_first() {
+        let tables = [table("Comment", &["Post"]), table("Post", &["Author"]), table("Author", &[])];
+        assert_eq!(order(&tables), vec![
+            ("Author".to_string(), vec![]),
+            ("Post".to_string(), vec![]),
+            ("Comment".to_string(), vec![])
+        ]);
+    }
+
+    #[test]
+    fn creation_order_defers_the_reference_closing_a_cycle() {
+        let tables = [table("Author", &["Post"]), table("Post", &["Author"]), table("Node", &["Node"])];
+        assert_eq!(order(&tables), vec![
+            ("Post".to_string(), vec!["Author".to_string()]),
+            ("Author".to_string(), vec![]),
+            ("Node".to_string(), vec![])
+        ]);
+    }
+
+    #[derive(PlattModel)]
+    #[platt(table = "Author")]
+    struct Author {
+        name: String
+    }
+
+    #[derive(PlattModel)]
+    #[platt(table = "Book")]
+    struct Book {
+        #[platt(on_delete = "cascade", deferrable)]
+        author: ForeignKey<Author>,
+        #[platt(on_delete = "set_null", on_update = "restrict")]
+        editor: Option<ForeignKey<Author>>,
+        #[platt(on_delete = "no_action")]
+        translator: Option<ForeignKey<Author>>
+    }
+
+    #[test]
+    fn references_sql_has_actions_and_deferral() {
+        let mut references = References::new("Author".to_string(), vec!["id".to_string()]);
+        assert_eq!(references.to_sql(), "REFERENCES \"Author\"(\"id\")");
+        references.schema = Some("blog".to_string());
+        references.on_delete = Some(ReferentialAction::SetNull);
+        references.on_update = Some(ReferentialAction::Cascade);
+        references.deferrable = true;
+        assert_eq!(
+            references.to_sql(),
+            "REFERENCES \"blog\".\"Author\"(\"id\") ON DELETE SET NULL ON UPDATE CASCADE DEFERRABLE INITIALLY DEFERRED"
+        );
+    }
+
+    #[test]
+    fn derived_foreign_keys_take_their_options() {
+        let mut schema = Schema::empty();
+        <Book as DbModel>::activate(&mut schema);
+        let book = schema.tables().iter().find(|table| table.name() == "Book").unwrap();
+        assert_eq!(book.create_sql(), concat!(
+            "CREATE TABLE \"Book\" (",
+            "\"id\" UUID NOT NULL PRIMARY KEY UNIQUE, ",
+            "\"author\" UUID NOT NULL REFERENCES \"Author\"(\"id\") ON DELETE CASCADE DEFERRABLE INITIALLY DEFERRED, ",
+            "\"editor\" UUID REFERENCES \"Author\"(\"id\") ON DELETE SET NULL ON UPDATE RESTRICT, ",
+            "\"translator\" UUID REFERENCES \"Author\"(\"id\"))"
+        ));
+    }
+}
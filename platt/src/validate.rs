@@ -0,0 +1,281 @@
+//! Checks a [`Schema`] for mistakes that Postgres would only catch once the
+//! statements run, or would not catch at all.
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::diff::is_sole_key;
+use crate::schema::{element_type, QualifiedName, Schema, Table};
+
+/// The longest identifier Postgres keeps, in bytes. It cuts longer ones
+/// short without an error, so two of them can end up with the same name and
+/// the diff no longer finds them by theirs.
+pub const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// The key words Postgres reserves, including those it allows as function
+/// or type names.
+const RESERVED_WORDS: &[&str] = &[
+    "ALL", "ANALYSE", "ANALYZE", "AND", "ANY", "ARRAY", "AS", "ASC", "ASYMMETRIC", "AUTHORIZATION", "BINARY", "BOTH",
+    "CASE", "CAST", "CHECK", "COLLATE", "COLLATION", "COLUMN", "CONCURRENTLY", "CONSTRAINT", "CREATE", "CROSS",
+    "CURRENT_CATALOG", "CURRENT_DATE", "CURRENT_ROLE", "CURRENT_SCHEMA", "CURRENT_TIME", "CURRENT_TIMESTAMP",
+    "CURRENT_USER", "DEFAULT", "DEFERRABLE", "DESC", "DISTINCT", "DO", "ELSE", "END", "EXCEPT", "FALSE", "FETCH",
+    "FOR", "FOREIGN", "FREEZE", "FROM", "FULL", "GRANT", "GROUP", "HAVING", "ILIKE", "IN", "INITIALLY", "INNER",
+    "INTERSECT", "INTO", "IS", "ISNULL", "JOIN", "LATERAL", "LEADING", "LEFT", "LIKE", "LIMIT", "LOCALTIME",
+    "LOCALTIMESTAMP", "NATURAL", "NOT", "NOTNULL", "NULL", "OFFSET", "ON", "ONLY", "OR", "ORDER", "OUTER",
+    "OVERLAPS", "PLACING", "PRIMARY", "REFERENCES", "RETURNING", "RIGHT", "SELECT", "SESSION_USER", "SIMILAR",
+    "SOME", "SYMMETRIC", "SYSTEM_USER", "TABLE", "TABLESAMPLE", "THEN", "TO", "TRAILING", "TRUE", "UNION",
+    "UNIQUE", "USER", "USING", "VARIADIC", "VERBOSE", "WHEN", "WHERE", "WINDOW", "WITH"
+];
+
+/// A mistake found by [`Schema::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The model it comes from, or the table or type when the schema was
+    /// not built from models.
+    pub model: String,
+    /// The field it comes from, or the column, attribute or label.
+    pub field: Option<String>,
+    pub message: String
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}.{}: {}", self.model, field, self.message),
+            None => write!(f, "{}: {}", self.model, self.message)
+        }
+    }
+}
+
+fn too_long(what: &str, name: &str) -> Option<String> {
+    if name.len() <= MAX_IDENTIFIER_LENGTH {
+        return None;
+    }
+    Some(format!(
+        "the {} \"{}\" is {} bytes long, and Postgres cuts it to {}",
+        what, name, name.len(), MAX_IDENTIFIER_LENGTH
+    ))
+}
+
+/// Names with capitals have to be quoted in SQL anyway, as Postgres folds
+/// unquoted ones to lowercase, so only lowercase ones are caught out by being
+/// a reserved word.
+fn reserved(what: &str, name: &str) -> Option<String> {
+    if name != name.to_lowercase() || !RESERVED_WORDS.contains(&name.to_uppercase().as_str()) {
+        return None;
+    }
+    Some(format!("the {} \"{}\" is a reserved word, which SQL written by hand has to quote", what, name))
+}
+
+fn zero_length(base: &str) -> Option<String> {
+    if element_type(base) != "VARCHAR(0)" {
+        return None;
+    }
+    Some("a Varchar<0> or max_length = 0 holds no characters, and Postgres refuses VARCHAR(0)".to_string())
+}
+
+/// Collects problems, leaving out repeats, as a field stored in several
+/// columns has the problems of its type once per column.
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn push(&mut self, model: &str, field: Option<&str>, message: Option<String>) {
+        let problem = match message {
+            Some(message) => Problem { model: model.to_string(), field: field.map(str::to_string), message },
+            None => return
+        };
+        if !self.0.contains(&problem) {
+            self.0.push(problem);
+        }
+    }
+
+    fn type_name(&mut self, name: &str, schema: Option<&str>) {
+        if let Some(schema) = schema {
+            self.push(name, None, too_long("schema name", schema));
+        }
+        self.push(name, None, too_long("type name", name));
+        self.push(name, None, reserved("type name", name));
+    }
+
+    fn table(&mut self, schema: &Schema, table: &Table) {
+        let model = table.model().unwrap_or_else(|| table.name());
+        let exists = |table: &QualifiedName| schema.tables().iter().any(|other| other.qualified_name() == *table);
+
+        if let Some(schema) = table.schema() {
+            self.push(model, None, too_long("schema name", schema));
+        }
+        self.push(model, None, too_long("table name", table.name()));
+        self.push(model, None, reserved("table name", table.name()));
+        if !table.primary_key().is_empty() {
+            self.push(model, None, too_long("primary key name", &format!("{}_pkey", table.name())));
+        }
+        for column in table.columns() {
+            let (name, db_type) = (column.name(), column.db_type());
+            let field = Some(column.field().unwrap_or(name));
+            self.push(model, field, too_long("column name", name));
+            self.push(model, field, reserved("column name", name));
+            if db_type.unique && !is_sole_key(table, name) {
+                self.push(model, field, too_long("unique constraint name", &format!("{}_{}_key", table.name(), name)));
+            }
+            if let Some(references) = &db_type.references {
+                self.push(model, field, too_long("foreign key name", &format!("{}_{}_fkey", table.name(), name)));
+                if !exists(&references.qualified_table()) {
+                    self.push(model, field, Some(format!(
+                        "it references {}, which no model in activate_models! creates",
+                        references.qualified_table()
+                    )));
+                }
+            }
+            self.push(model, field, zero_length(&db_type.base));
+            for problem in column.problems() {
+                self.push(model, field, Some(problem.clone()));
+            }
+        }
+        for foreign_key in table.foreign_keys() {
+            // The columns of the foreign key all store the same field.
+            let field = foreign_key.columns.first()
+                .and_then(|first| table.columns().iter().find(|column| column.name() == first))
+                .map(|column| column.field().unwrap_or_else(|| column.name()));
+            self.push(model, field, too_long("foreign key name", &foreign_key.name));
+            if !exists(&foreign_key.references.qualified_table()) {
+                self.push(model, field, Some(format!(
+                    "it references {}, which no model in activate_models! creates",
+                    foreign_key.references.qualified_table()
+                )));
+            }
+        }
+        for unique in table.uniques() {
+            self.push(model, None, too_long("unique constraint name", &unique.name));
+        }
+        for check in table.checks() {
+            self.push(model, None, too_long("check constraint name", &check.name));
+        }
+        for index in table.all_indexes() {
+            self.push(model, None, too_long("index name", &index.name));
+        }
+    }
+}
+
+impl Schema {
+    /// Lists the mistakes in the schema that creating it would run into, or
+    /// that would go unnoticed until later:
+    ///
+    /// - identifiers longer than [`MAX_IDENTIFIER_LENGTH`], including the
+    ///   names Postgres gives constraints
+    /// - tables, columns and types named after a reserved word in lowercase
+    /// - foreign keys to tables no model creates, as when the model a
+    ///   `ForeignKey` points to is missing from `activate_models!`
+    /// - several models creating the same table
+    /// - columns that can not hold every value of their field, e.g. the
+    ///   `BIGINT` of a `u64`
+    /// - `VARCHAR(0)` columns and attributes
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems = Problems::default();
+        for enum_type in self.enums() {
+            let name = &enum_type.name;
+            problems.type_name(name, enum_type.schema.as_deref());
+            for label in &enum_type.labels {
+                problems.push(name, Some(label), too_long("label", label));
+            }
+        }
+        for composite in self.composites() {
+            let name = &composite.name;
+            problems.type_name(name, composite.schema.as_deref());
+            for (attribute, db_type) in &composite.fields {
+                problems.push(name, Some(attribute), too_long("attribute name", attribute));
+                problems.push(name, Some(attribute), reserved("attribute name", attribute));
+                problems.push(name, Some(attribute), zero_length(&db_type.base));
+            }
+        }
+        let mut models: HashMap<QualifiedName, &str> = HashMap::new();
+        for table in self.tables() {
+            let model = table.model().unwrap_or_else(|| table.name());
+            if let Some(other) = models.insert(table.qualified_name(), model) {
+                problems.push(model, None, Some(format!(
+                    "the table {} is also created by {}",
+                    table.qualified_name(), other
+                )));
+            }
+            problems.table(self, table);
+        }
+        problems.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{primary_key, Column, DbType, EnumType, References};
+
+    fn column(name: &str, base: &str) -> Column {
+        Column::new(name.to_string(), DbType { base: base.to_string(), ..DbType::default() })
+    }
+
+    fn table(name: &str, columns: Vec<Column>) -> Table {
+        let mut all = vec![Column::new("id".to_string(), primary_key())];
+        all.extend(columns);
+        Table::new(name.to_string(), all)
+    }
+
+    fn messages(tables: Vec<Table>, enums: Vec<EnumType>) -> Vec<String> {
+        let mut schema = Schema::empty();
+        schema.add_enums(enums);
+        schema.add_tables(tables);
+        schema.validate().iter().map(Problem::to_string).collect()
+    }
+
+    #[test]
+    fn valid_schema_has_no_problems() {
+        let tables = vec![table("Post", vec![column("title", "VARCHAR(80)")])];
+        assert_eq!(messages(tables, Vec::new()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn finds_long_and_reserved_names() {
+        let long = "a".repeat(MAX_IDENTIFIER_LENGTH + 1);
+        let tables = vec![
+            table("user", vec![column(&long, "TEXT")]),
+            table("Order", vec![column("order", "TEXT"), column("Group", "TEXT")])
+        ];
+        let enums = vec![EnumType {
+            name: "select".to_string(),
+            schema: None,
+            labels: vec!["ok".to_string()],
+            renamed_labels: Vec::new()
+        }];
+        assert_eq!(messages(tables, enums), vec![
+            "select: the type name \"select\" is a reserved word, which SQL written by hand has to quote".to_string(),
+            "user: the table name \"user\" is a reserved word, which SQL written by hand has to quote".to_string(),
+            format!("user.{}: the column name \"{}\" is 64 bytes long, and Postgres cuts it to 63", long, long),
+            "Order.order: the column name \"order\" is a reserved word, which SQL written by hand has to quote".to_string()
+        ]);
+    }
+
+    #[test]
+    fn finds_missing_references_and_duplicate_tables() {
+        let references = References::new("Author".to_string(), vec!["id".to_string()]);
+        let author = Column::new(
+            "author_id".to_string(),
+            DbType { base: "UUID".to_string(), references: Some(references), ..DbType::default() }
+        ).with_field(Some("author".to_string()));
+        let tables = vec![
+            table("Post", vec![author]).with_model(Some("Post".to_string())),
+            table("Post", Vec::new()).with_model(Some("Article".to_string()))
+        ];
+        assert_eq!(messages(tables, Vec::new()), vec![
+            "Post.author: it references \"Author\", which no model in activate_models! creates".to_string(),
+            "Article: the table \"Post\" is also created by Post".to_string()
+        ]);
+    }
+
+    #[test]
+    fn finds_column_problems_once() {
+        let count = column("count", "BIGINT").with_problems(vec!["u64 values overflow".to_string()]);
+        let tags = column("tags", "VARCHAR(0)[]");
+        let tables = vec![table("Post", vec![count, tags])];
+        assert_eq!(messages(tables, Vec::new()), vec![
+            "Post.count: u64 values overflow".to_string(),
+            "Post.tags: a Varchar<0> or max_length = 0 holds no characters, and Postgres refuses VARCHAR(0)".to_string()
+        ]);
+    }
+}
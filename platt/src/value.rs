@@ -0,0 +1,1771 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+use crate::db::Row;
+use crate::error::{Error, Result};
+use crate::schema::{BitString, BitStringFixed, Decimal, ExactString, ForeignKey, DbModel, HasDbType, Varchar};
+
+/// The two wire formats Postgres sends values in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsNull {
+    Yes,
+    No
+}
+
+/// The encoded value of one of the columns a model field is stored in, see
+/// [`ToSql::to_columns`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnValue {
+    pub column: String,
+    pub oid: Option<u32>,
+    /// The encoded value, `None` for `NULL`.
+    pub value: Option<Vec<u8>>
+}
+
+/// Encodes a Rust value as a parameter for the column type its
+/// [`HasDbType`](crate::schema::HasDbType) impl describes.
+pub trait ToSql {
+    /// Writes the value to `out`, or returns [`IsNull::Yes`] (writing
+    /// nothing) to send `NULL`.
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull>;
+
+    /// The OID of the built-in Postgres type this is encoded as. Types
+    /// without one (composites and enums) can only be sent in text format.
+    fn type_oid() -> Option<u32> where Self: Sized {
+        None
+    }
+
+    /// Whether the value is an array, which an array of it nests instead of
+    /// quoting it as a single element.
+    fn is_array() -> bool where Self: Sized {
+        false
+    }
+
+    fn oid(&self) -> Option<u32>;
+
+    /// Encodes the value into the columns a model field called `column` is
+    /// stored in, the counterpart of [`FromSql::from_row`]. Most types take
+    /// a single column encoded by [`ToSql::to_sql`].
+    fn to_columns(&self, column: &str, format: Format, out: &mut Vec<ColumnValue>) -> Result<()> {
+        let mut value = Vec::new();
+        let value = match self.to_sql(format, &mut value)? {
+            IsNull::Yes => None,
+            IsNull::No => Some(value)
+        };
+        out.push(ColumnValue { column: column.to_string(), oid: self.oid(), value });
+        Ok(())
+    }
+
+    /// The columns of a missing value, all `NULL`, as written by an `Option`
+    /// of the type.
+    fn null_columns(column: &str, out: &mut Vec<ColumnValue>) where Self: Sized {
+        out.push(ColumnValue { column: column.to_string(), oid: Self::type_oid(), value: None });
+    }
+}
+
+/// Decodes a value of the column type its
+/// [`HasDbType`](crate::schema::HasDbType) impl describes.
+pub trait FromSql: Sized {
+    /// Decodes `raw`, which is `None` for `NULL`.
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self>;
+
+    fn from_row(row: &Row, column: &str) -> Result<Self> {
+        match row.get_raw(column) {
+            Some(raw) => Self::from_sql(row.format(), raw),
+            None => Err(Error::Decode(format!("no column named \"{}\"", column)))
+        }
+    }
+
+    /// Whether the value [`FromSql::from_row`] would read is missing, which
+    /// an `Option` of it reads as `None`. Types spanning several columns
+    /// look at the first of them.
+    fn is_null(row: &Row, column: &str) -> Result<bool> {
+        match row.get_raw(column) {
+            Some(raw) => Ok(raw.is_none()),
+            None => Err(Error::Decode(format!("no column named \"{}\"", column)))
+        }
+    }
+}
+
+/// The types whose values are encoded on their own, which arrays, composites
+/// and enum variants can hold. `PlattEnum`s with `repr = "columns"` span
+/// several columns of the model holding them, so they can only be model
+/// fields and are left out.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is stored in several columns, so it can only be a model field",
+    label = "can not be held by an array, a composite or an enum variant"
+)]
+pub trait Nestable { }
+
+macro_rules! nestable {
+    ($($rust:ty),+) => {
+        $( impl Nestable for $rust { } )+
+    };
+}
+
+nestable!(bool, u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, String, BitString, uuid::Uuid);
+impl<const SIZE: usize> Nestable for Varchar<SIZE> { }
+impl<const SIZE: usize> Nestable for ExactString<SIZE> { }
+impl<const SIZE: usize> Nestable for BitStringFixed<SIZE> { }
+impl<const BEFORE: u16, const AFTER: u16> Nestable for Decimal<BEFORE, AFTER> { }
+impl<T: Nestable> Nestable for Option<T> { }
+impl<T: Nestable> Nestable for Vec<T> { }
+impl<T: Nestable, const N: usize> Nestable for [T; N] { }
+impl<Other: DbModel> Nestable for ForeignKey<Other> where Other::PrimaryKey: Nestable { }
+
+/// The value of a primary key, which a [`ForeignKey`] holds. A composite
+/// key is a tuple of the values of its columns.
+pub trait KeyValue: Sized {
+    /// Decodes the key from a single column.
+    fn decode(format: Format, raw: Option<&[u8]>) -> Result<Self>;
+
+    /// Reads the key from `columns` of `row`, one per column of the key.
+    fn from_columns(row: &Row, columns: &[String]) -> Result<Self>;
+}
+
+impl<T: FromSql> KeyValue for T {
+    fn decode(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        <T as FromSql>::from_sql(format, raw)
+    }
+
+    fn from_columns(row: &Row, columns: &[String]) -> Result<Self> {
+        T::from_row(row, &columns[0])
+    }
+}
+
+macro_rules! tuple_key_value {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: FromSql),+> KeyValue for ($($name,)+) {
+            fn decode(_format: Format, _raw: Option<&[u8]>) -> Result<Self> {
+                Err(Error::Decode("a composite key is read from several columns".to_string()))
+            }
+
+            fn from_columns(row: &Row, columns: &[String]) -> Result<Self> {
+                Ok(($($name::from_row(row, &columns[$index])?,)+))
+            }
+        }
+    };
+}
+
+tuple_key_value!(A 0, B 1);
+tuple_key_value!(A 0, B 1, C 2);
+tuple_key_value!(A 0, B 1, C 2, D 3);
+
+pub fn non_null(raw: Option<&[u8]>) -> Result<&[u8]> {
+    raw.ok_or(Error::UnexpectedNull)
+}
+
+pub(crate) fn text(raw: &[u8]) -> Result<&str> {
+    std::str::from_utf8(raw).map_err(|error| Error::Decode(error.to_string()))
+}
+
+fn parse<T: FromStr>(raw: &[u8]) -> Result<T> where T::Err: std::fmt::Display {
+    let raw = text(raw)?;
+    raw.parse().map_err(|error| Error::Decode(format!("invalid value {:?}: {}", raw, error)))
+}
+
+fn fixed<const N: usize>(raw: &[u8]) -> Result<[u8; N]> {
+    <[u8; N]>::try_from(raw).map_err(|_| Error::Decode(format!("expected {} bytes, got {}", N, raw.len())))
+}
+
+/// Maps the OID of a built-in type to the OID of its array type.
+pub(crate) fn array_oid(element: u32) -> Option<u32> {
+    Some(match element {
+        oid::BOOL => 1000,
+        oid::INT2 => 1005,
+        oid::INT4 => 1007,
+        oid::INT8 => 1016,
+        oid::TEXT => 1009,
+        oid::FLOAT4 => 1021,
+        oid::FLOAT8 => 1022,
+        oid::BPCHAR => 1014,
+        oid::VARCHAR => 1015,
+        oid::NUMERIC => 1231,
+        oid::BIT => 1561,
+        oid::VARBIT => 1563,
+        oid::DATE => 1182,
+        oid::TIME => 1183,
+        oid::TIMESTAMP => 1115,
+        oid::TIMESTAMPTZ => 1185,
+        oid::UUID => 2951,
+        oid::JSON => 199,
+        oid::JSONB => 3807,
+        _ => return None
+    })
+}
+
+pub mod oid {
+    pub const BOOL: u32 = 16;
+    pub const INT8: u32 = 20;
+    pub const INT2: u32 = 21;
+    pub const INT4: u32 = 23;
+    pub const TEXT: u32 = 25;
+    pub const JSON: u32 = 114;
+    pub const FLOAT4: u32 = 700;
+    pub const FLOAT8: u32 = 701;
+    pub const BPCHAR: u32 = 1042;
+    pub const VARCHAR: u32 = 1043;
+    pub const DATE: u32 = 1082;
+    pub const TIME: u32 = 1083;
+    pub const TIMESTAMP: u32 = 1114;
+    pub const TIMESTAMPTZ: u32 = 1184;
+    pub const BIT: u32 = 1560;
+    pub const VARBIT: u32 = 1562;
+    pub const NUMERIC: u32 = 1700;
+    pub const UUID: u32 = 2950;
+    pub const JSONB: u32 = 3802;
+}
+
+macro_rules! type_oid {
+    ($oid:expr) => {
+        fn type_oid() -> Option<u32> {
+            Some($oid)
+        }
+
+        fn oid(&self) -> Option<u32> {
+            Some($oid)
+        }
+    };
+}
+
+impl ToSql for bool {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        match format {
+            Format::Text => out.push(if *self { b't' } else { b'f' }),
+            Format::Binary => out.push(*self as u8)
+        }
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::BOOL);
+}
+
+impl FromSql for bool {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        match (format, non_null(raw)?) {
+            (Format::Text, b"t") | (Format::Text, b"true") => Ok(true),
+            (Format::Text, b"f") | (Format::Text, b"false") => Ok(false),
+            (Format::Binary, [byte]) => Ok(*byte != 0),
+            (_, raw) => Err(Error::Decode(format!("invalid boolean {:?}", raw)))
+        }
+    }
+}
+
+/// Integers are sent as the Postgres type `schema.rs` maps them to, checking
+/// that unsigned values fit into it.
+macro_rules! integer_sql {
+    ($rust:ty, $db:ty, $sql:expr, $oid:expr) => {
+        impl ToSql for $rust {
+            fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+                let value = <$db>::try_from(*self).map_err(|_| Error::Encode(format!(
+                    "{} does not fit into the {} it is stored as", self, $sql
+                )))?;
+                match format {
+                    Format::Text => out.extend_from_slice(value.to_string().as_bytes()),
+                    Format::Binary => out.extend_from_slice(&value.to_be_bytes())
+                }
+                Ok(IsNull::No)
+            }
+
+            type_oid!($oid);
+        }
+
+        impl FromSql for $rust {
+            fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+                let raw = non_null(raw)?;
+                let value = match format {
+                    Format::Text => parse::<$db>(raw)?,
+                    Format::Binary => <$db>::from_be_bytes(fixed(raw)?)
+                };
+                <$rust>::try_from(value).map_err(|_| Error::Decode(format!(
+                    "{} is out of range for {}", value, stringify!($rust)
+                )))
+            }
+        }
+    };
+}
+
+integer_sql!(u8, i16, "SMALLINT", oid::INT2);
+integer_sql!(u16, i16, "SMALLINT", oid::INT2);
+integer_sql!(u32, i32, "INT", oid::INT4);
+integer_sql!(u64, i64, "BIGINT", oid::INT8);
+integer_sql!(i8, i16, "SMALLINT", oid::INT2);
+integer_sql!(i16, i16, "SMALLINT", oid::INT2);
+integer_sql!(i32, i32, "INT", oid::INT4);
+integer_sql!(i64, i64, "BIGINT", oid::INT8);
+
+macro_rules! float_sql {
+    ($rust:ty, $oid:expr) => {
+        impl ToSql for $rust {
+            fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+                match format {
+                    Format::Text if self.is_nan() => out.extend_from_slice(b"NaN"),
+                    Format::Text if self.is_infinite() => {
+                        out.extend_from_slice(if *self > 0.0 { b"Infinity" } else { b"-Infinity" })
+                    }
+                    Format::Text => out.extend_from_slice(self.to_string().as_bytes()),
+                    Format::Binary => out.extend_from_slice(&self.to_be_bytes())
+                }
+                Ok(IsNull::No)
+            }
+
+            type_oid!($oid);
+        }
+
+        impl FromSql for $rust {
+            fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+                let raw = non_null(raw)?;
+                match format {
+                    Format::Text => match raw {
+                        b"NaN" => Ok(<$rust>::NAN),
+                        b"Infinity" => Ok(<$rust>::INFINITY),
+                        b"-Infinity" => Ok(<$rust>::NEG_INFINITY),
+                        raw => parse(raw)
+                    },
+                    Format::Binary => Ok(<$rust>::from_be_bytes(fixed(raw)?))
+                }
+            }
+        }
+    };
+}
+
+float_sql!(f32, oid::FLOAT4);
+float_sql!(f64, oid::FLOAT8);
+
+impl ToSql for String {
+    fn to_sql(&self, _format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        out.extend_from_slice(self.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::TEXT);
+}
+
+impl FromSql for String {
+    fn from_sql(_format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        text(non_null(raw)?).map(str::to_string)
+    }
+}
+
+pub mod non_zero {
+    use std::num::*;
+    use super::*;
+
+    macro_rules! non_zero_sql {
+        ($rust:ty, $inner:ty) => {
+            impl ToSql for $rust {
+                fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+                    self.get().to_sql(format, out)
+                }
+
+                fn type_oid() -> Option<u32> {
+                    <$inner>::type_oid()
+                }
+
+                fn oid(&self) -> Option<u32> {
+                    <$inner>::type_oid()
+                }
+            }
+
+            impl FromSql for $rust {
+                fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+                    <$rust>::new(<$inner>::from_sql(format, raw)?)
+                        .ok_or_else(|| Error::Decode(format!("0 is out of range for {}", stringify!($rust))))
+                }
+            }
+        };
+    }
+
+    non_zero_sql!(NonZeroI8, i8);
+    non_zero_sql!(NonZeroI16, i16);
+    non_zero_sql!(NonZeroI32, i32);
+    non_zero_sql!(NonZeroI64, i64);
+    non_zero_sql!(NonZeroU8, u8);
+    non_zero_sql!(NonZeroU16, u16);
+    non_zero_sql!(NonZeroU32, u32);
+    non_zero_sql!(NonZeroU64, u64);
+
+    nestable!(NonZeroI8, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroU8, NonZeroU16, NonZeroU32, NonZeroU64);
+}
+
+impl<const SIZE: usize> ToSql for Varchar<SIZE> {
+    fn to_sql(&self, _format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        out.extend_from_slice(self.as_str().as_bytes());
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::VARCHAR);
+}
+
+impl<const SIZE: usize> FromSql for Varchar<SIZE> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let value = String::from_sql(format, raw)?;
+        Varchar::new(value).ok_or_else(|| Error::Decode(format!("value is longer than VARCHAR({})", SIZE)))
+    }
+}
+
+impl<const SIZE: usize> ToSql for ExactString<SIZE> {
+    fn to_sql(&self, _format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        out.extend_from_slice(self.as_str().as_bytes());
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::BPCHAR);
+}
+
+impl<const SIZE: usize> FromSql for ExactString<SIZE> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let value = String::from_sql(format, raw)?;
+        ExactString::new(value).ok_or_else(|| Error::Decode(format!("value is longer than CHAR({})", SIZE)))
+    }
+}
+
+const NUMERIC_POSITIVE: u16 = 0x0000;
+const NUMERIC_NEGATIVE: u16 = 0x4000;
+
+impl<const BEFORE: u16, const AFTER: u16> ToSql for Decimal<BEFORE, AFTER> {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        let text = self.to_string();
+        if format == Format::Text {
+            out.extend_from_slice(text.as_bytes());
+            return Ok(IsNull::No);
+        }
+
+        // Split the decimal digits into base 10000 digits aligned on the
+        // decimal point, as the binary NUMERIC format expects.
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.as_str())
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let integer = format!("{}{}", "0".repeat((4 - integer.len() % 4) % 4), integer);
+        let fraction = format!("{}{}", fraction, "0".repeat((4 - fraction.len() % 4) % 4));
+        let mut groups: Vec<i16> = integer.as_bytes().chunks(4)
+            .chain(fraction.as_bytes().chunks(4))
+            .map(|chunk| std::str::from_utf8(chunk).unwrap().parse().unwrap())
+            .collect();
+        let mut weight = (integer.len() / 4) as i16 - 1;
+        while groups.first() == Some(&0) {
+            groups.remove(0);
+            weight -= 1;
+        }
+        while groups.last() == Some(&0) {
+            groups.pop();
+        }
+        if groups.is_empty() {
+            weight = 0;
+        }
+
+        out.extend_from_slice(&(groups.len() as i16).to_be_bytes());
+        out.extend_from_slice(&weight.to_be_bytes());
+        out.extend_from_slice(&(if negative { NUMERIC_NEGATIVE } else { NUMERIC_POSITIVE }).to_be_bytes());
+        out.extend_from_slice(&AFTER.to_be_bytes());
+        for group in groups {
+            out.extend_from_slice(&group.to_be_bytes());
+        }
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::NUMERIC);
+}
+
+impl<const BEFORE: u16, const AFTER: u16> FromSql for Decimal<BEFORE, AFTER> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let raw = non_null(raw)?;
+        if format == Format::Text {
+            return text(raw)?.parse();
+        }
+
+        if raw.len() < 8 {
+            return Err(Error::Decode("truncated NUMERIC value".to_string()));
+        }
+        let count = i16::from_be_bytes([raw[0], raw[1]]) as usize;
+        let weight = i16::from_be_bytes([raw[2], raw[3]]) as isize;
+        let sign = u16::from_be_bytes([raw[4], raw[5]]);
+        if raw.len() != 8 + 2 * count {
+            return Err(Error::Decode("truncated NUMERIC value".to_string()));
+        }
+        let groups: Vec<i16> = raw[8..].chunks(2).map(|chunk| i16::from_be_bytes([chunk[0], chunk[1]])).collect();
+        let group = |exponent: isize| -> i16 {
+            let index = weight - exponent;
+            if index >= 0 && (index as usize) < groups.len() { groups[index as usize] } else { 0 }
+        };
+
+        let mut text = match sign {
+            NUMERIC_POSITIVE => String::new(),
+            NUMERIC_NEGATIVE => "-".to_string(),
+            _ => return Err(Error::Decode("NaN and infinite NUMERIC values are not supported".to_string()))
+        };
+        for exponent in (0..=weight.max(0)).rev() {
+            text += &format!("{:04}", group(exponent));
+        }
+        let lowest = weight - groups.len() as isize + 1;
+        if lowest < 0 {
+            text.push('.');
+            for exponent in (lowest..0).rev() {
+                text += &format!("{:04}", group(exponent));
+            }
+        }
+        text.parse()
+    }
+}
+
+fn bits_to_sql(bits: &[bool], format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+    match format {
+        Format::Text => out.extend(bits.iter().map(|bit| if *bit { b'1' } else { b'0' })),
+        Format::Binary => {
+            let length = i32::try_from(bits.len()).map_err(|_| Error::Encode("bit string is too long".to_string()))?;
+            out.extend_from_slice(&length.to_be_bytes());
+            for byte in bits.chunks(8) {
+                out.push(byte.iter().enumerate().fold(0u8, |acc, (index, bit)| acc | ((*bit as u8) << (7 - index))));
+            }
+        }
+    }
+    Ok(IsNull::No)
+}
+
+fn bits_from_sql(format: Format, raw: Option<&[u8]>) -> Result<Vec<bool>> {
+    let raw = non_null(raw)?;
+    match format {
+        Format::Text => raw.iter().map(|bit| match bit {
+            b'0' => Ok(false),
+            b'1' => Ok(true),
+            _ => Err(Error::Decode(format!("invalid bit {:?}", *bit as char)))
+        }).collect(),
+        Format::Binary => {
+            if raw.len() < 4 {
+                return Err(Error::Decode("truncated bit string".to_string()));
+            }
+            let length = i32::from_be_bytes(fixed(&raw[..4])?) as usize;
+            let bytes = &raw[4..];
+            if bytes.len() * 8 < length {
+                return Err(Error::Decode("truncated bit string".to_string()));
+            }
+            Ok((0..length).map(|index| bytes[index / 8] & (1 << (7 - index % 8)) != 0).collect())
+        }
+    }
+}
+
+impl ToSql for BitString {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        bits_to_sql(&self.0, format, out)
+    }
+
+    type_oid!(oid::VARBIT);
+}
+
+impl FromSql for BitString {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        bits_from_sql(format, raw).map(BitString)
+    }
+}
+
+impl<const SIZE: usize> ToSql for BitStringFixed<SIZE> {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        bits_to_sql(&self.0, format, out)
+    }
+
+    type_oid!(oid::BIT);
+}
+
+impl<const SIZE: usize> FromSql for BitStringFixed<SIZE> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let bits = bits_from_sql(format, raw)?;
+        let length = bits.len();
+        <[bool; SIZE]>::try_from(bits)
+            .map(BitStringFixed)
+            .map_err(|_| Error::Decode(format!("expected {} bits, got {}", SIZE, length)))
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        match self {
+            Some(value) => value.to_sql(format, out),
+            None => Ok(IsNull::Yes)
+        }
+    }
+
+    fn type_oid() -> Option<u32> {
+        T::type_oid()
+    }
+
+    fn is_array() -> bool {
+        T::is_array()
+    }
+
+    fn oid(&self) -> Option<u32> {
+        T::type_oid()
+    }
+
+    /// Writes through [`ToSql::to_columns`] of `T`, which may span several
+    /// columns, or the [`ToSql::null_columns`] of `T` for `None`.
+    fn to_columns(&self, column: &str, format: Format, out: &mut Vec<ColumnValue>) -> Result<()> {
+        match self {
+            Some(value) => value.to_columns(column, format, out),
+            None => {
+                T::null_columns(column, out);
+                Ok(())
+            }
+        }
+    }
+
+    fn null_columns(column: &str, out: &mut Vec<ColumnValue>) {
+        T::null_columns(column, out)
+    }
+}
+
+impl<T: FromSql> FromSql for Option<T> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        match raw {
+            Some(raw) => T::from_sql(format, Some(raw)).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Reads through [`FromSql::from_row`] of `T`, which may span several
+    /// columns, unless [`FromSql::is_null`] of `T` says it is missing.
+    fn from_row(row: &Row, column: &str) -> Result<Self> {
+        if T::is_null(row, column)? {
+            return Ok(None);
+        }
+        T::from_row(row, column).map(Some)
+    }
+}
+
+fn array_to_sql<T: ToSql>(elements: &[T], format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+    match format {
+        Format::Text => {
+            out.push(b'{');
+            for (index, element) in elements.iter().enumerate() {
+                if index > 0 {
+                    out.push(b',');
+                }
+                let mut value = Vec::new();
+                match element.to_sql(Format::Text, &mut value)? {
+                    IsNull::Yes => out.extend_from_slice(b"NULL"),
+                    IsNull::No if T::is_array() => out.extend_from_slice(&value),
+                    IsNull::No => quote_element(&value, out)
+                }
+            }
+            out.push(b'}');
+        }
+        Format::Binary => {
+            let element_oid = T::type_oid()
+                .filter(|oid| array_oid(*oid).is_some())
+                .ok_or_else(|| Error::Encode(
+                    "arrays of this type can only be sent in text format".to_string()
+                ))?;
+            let length = i32::try_from(elements.len()).map_err(|_| Error::Encode("array is too long".to_string()))?;
+            let header = out.len();
+            out.extend_from_slice(&(if elements.is_empty() { 0i32 } else { 1i32 }).to_be_bytes());
+            out.extend_from_slice(&0i32.to_be_bytes());
+            out.extend_from_slice(&element_oid.to_be_bytes());
+            if !elements.is_empty() {
+                out.extend_from_slice(&length.to_be_bytes());
+                out.extend_from_slice(&1i32.to_be_bytes());
+            }
+            let mut has_null = false;
+            for element in elements {
+                let start = out.len();
+                out.extend_from_slice(&0i32.to_be_bytes());
+                match element.to_sql(Format::Binary, out)? {
+                    IsNull::Yes => {
+                        has_null = true;
+                        out[start..start + 4].copy_from_slice(&(-1i32).to_be_bytes());
+                    }
+                    IsNull::No => {
+                        let size = (out.len() - start - 4) as i32;
+                        out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+                    }
+                }
+            }
+            if has_null {
+                out[header + 4..header + 8].copy_from_slice(&1i32.to_be_bytes());
+            }
+        }
+    }
+    Ok(IsNull::No)
+}
+
+fn quote_element(value: &[u8], out: &mut Vec<u8>) {
+    let needs_quotes = value.is_empty()
+        || value.eq_ignore_ascii_case(b"NULL")
+        || value.iter().any(|byte| matches!(byte, b'{' | b'}' | b',' | b'"' | b'\\') || byte.is_ascii_whitespace());
+    if !needs_quotes {
+        out.extend_from_slice(value);
+        return;
+    }
+    out.push(b'"');
+    for byte in value {
+        if matches!(byte, b'"' | b'\\') {
+            out.push(b'\\');
+        }
+        out.push(*byte);
+    }
+    out.push(b'"');
+}
+
+/// Splits the text representation of a one dimensional array into its
+/// elements. Nested arrays are returned unparsed, braces included.
+fn split_array(raw: &str) -> Result<Vec<Option<String>>> {
+    let invalid = || Error::Decode(format!("invalid array {:?}", raw));
+    // Arrays with non-default bounds are prefixed with them, e.g. `[0:1]={1,2}`.
+    let body = match raw.find("={") {
+        Some(index) if raw.starts_with('[') => &raw[index + 1..],
+        _ => raw
+    };
+    let body = body.strip_prefix('{').and_then(|body| body.strip_suffix('}')).ok_or_else(invalid)?;
+    let mut elements = Vec::new();
+    let mut chars = body.chars().peekable();
+    while chars.peek().is_some() {
+        let mut element = String::new();
+        let mut quoted = false;
+        match chars.peek() {
+            Some('"') => {
+                quoted = true;
+                chars.next();
+                loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        '"' => break,
+                        '\\' => element.push(chars.next().ok_or_else(invalid)?),
+                        c => element.push(c)
+                    }
+                }
+            }
+            Some('{') => {
+                let mut depth = 0;
+                let mut in_quotes = false;
+                loop {
+                    let c = chars.next().ok_or_else(invalid)?;
+                    element.push(c);
+                    match c {
+                        '\\' if in_quotes => element.push(chars.next().ok_or_else(invalid)?),
+                        '"' => in_quotes = !in_quotes,
+                        '{' if !in_quotes => depth += 1,
+                        '}' if !in_quotes => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => ()
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.peek() {
+                    if *c == ',' {
+                        break;
+                    }
+                    element.push(*c);
+                    chars.next();
+                }
+            }
+        }
+        match chars.next() {
+            None | Some(',') => (),
+            Some(_) => return Err(invalid())
+        }
+        if !quoted && element.eq_ignore_ascii_case("NULL") {
+            elements.push(None);
+        } else {
+            elements.push(Some(element));
+        }
+    }
+    Ok(elements)
+}
+
+fn array_from_sql<T: FromSql>(format: Format, raw: Option<&[u8]>) -> Result<Vec<T>> {
+    let raw = non_null(raw)?;
+    match format {
+        Format::Text => split_array(text(raw)?)?
+            .into_iter()
+            .map(|element| T::from_sql(Format::Text, element.as_ref().map(|element| element.as_bytes())))
+            .collect(),
+        Format::Binary => {
+            let truncated = || Error::Decode("truncated array".to_string());
+            let int = |offset: usize| -> Result<i32> {
+                raw.get(offset..offset + 4).map(|bytes| i32::from_be_bytes(fixed(bytes).unwrap())).ok_or_else(truncated)
+            };
+            let dimensions = int(0)?;
+            if dimensions == 0 {
+                return Ok(Vec::new());
+            }
+            if dimensions != 1 {
+                return Err(Error::Decode("multidimensional arrays can only be read in text format".to_string()));
+            }
+            let length = int(12)?;
+            let mut offset = 20;
+            let mut elements = Vec::with_capacity(length.max(0) as usize);
+            for _ in 0..length {
+                let size = int(offset)?;
+                offset += 4;
+                if size < 0 {
+                    elements.push(T::from_sql(Format::Binary, None)?);
+                } else {
+                    let value = raw.get(offset..offset + size as usize).ok_or_else(truncated)?;
+                    elements.push(T::from_sql(Format::Binary, Some(value))?);
+                    offset += size as usize;
+                }
+            }
+            Ok(elements)
+        }
+    }
+}
+
+impl<T: ToSql + Nestable> ToSql for Vec<T> {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        array_to_sql(self, format, out)
+    }
+
+    fn type_oid() -> Option<u32> {
+        T::type_oid().and_then(array_oid)
+    }
+
+    fn is_array() -> bool {
+        true
+    }
+
+    fn oid(&self) -> Option<u32> {
+        T::type_oid().and_then(array_oid)
+    }
+}
+
+impl<T: FromSql + Nestable> FromSql for Vec<T> {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        array_from_sql(format, raw)
+    }
+}
+
+impl<T: ToSql + Nestable, const N: usize> ToSql for [T; N] {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        array_to_sql(self, format, out)
+    }
+
+    fn type_oid() -> Option<u32> {
+        T::type_oid().and_then(array_oid)
+    }
+
+    fn is_array() -> bool {
+        true
+    }
+
+    fn oid(&self) -> Option<u32> {
+        T::type_oid().and_then(array_oid)
+    }
+}
+
+impl<T: FromSql + Nestable, const N: usize> FromSql for [T; N] {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let elements = array_from_sql(format, raw)?;
+        let length = elements.len();
+        <[T; N]>::try_from(elements).map_err(|_| Error::Decode(format!("expected {} array elements, got {}", N, length)))
+    }
+}
+
+// UUIDs are the primary keys models get by default, so foreign keys can
+// store them without the `uuid_type` feature.
+impl ToSql for uuid::Uuid {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        match format {
+            Format::Text => out.extend_from_slice(self.to_hyphenated().to_string().as_bytes()),
+            Format::Binary => out.extend_from_slice(self.as_bytes())
+        }
+        Ok(IsNull::No)
+    }
+
+    type_oid!(oid::UUID);
+}
+
+impl FromSql for uuid::Uuid {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        let raw = non_null(raw)?;
+        match format {
+            Format::Text => parse(raw),
+            Format::Binary => uuid::Uuid::from_slice(raw).map_err(|error| Error::Decode(error.to_string()))
+        }
+    }
+}
+
+impl<Other: DbModel> ToSql for ForeignKey<Other> where Other::PrimaryKey: ToSql {
+    fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        self.key().to_sql(format, out)
+    }
+
+    fn type_oid() -> Option<u32> {
+        Other::PrimaryKey::type_oid()
+    }
+
+    fn oid(&self) -> Option<u32> {
+        self.key().oid()
+    }
+}
+
+/// The names of the columns a foreign key field called `column` is stored
+/// in.
+fn foreign_key_columns<Other: DbModel>(column: &str) -> Vec<String> {
+    <ForeignKey<Other> as HasDbType>::columns(column).iter().map(|column| column.name().to_string()).collect()
+}
+
+impl<Other: DbModel> FromSql for ForeignKey<Other> where Other::PrimaryKey: KeyValue {
+    fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+        Other::PrimaryKey::decode(format, raw).map(ForeignKey::new)
+    }
+
+    fn from_row(row: &Row, column: &str) -> Result<Self> {
+        Other::PrimaryKey::from_columns(row, &foreign_key_columns::<Other>(column)).map(ForeignKey::new)
+    }
+
+    fn is_null(row: &Row, column: &str) -> Result<bool> {
+        <Option<bool> as FromSql>::is_null(row, &foreign_key_columns::<Other>(column)[0])
+    }
+}
+
+#[cfg(feature = "chrono_type")]
+pub mod chrono_type {
+    use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    use super::*;
+
+    fn epoch() -> NaiveDateTime {
+        NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0)
+    }
+
+    fn micros(duration: Duration) -> Result<i64> {
+        duration.num_microseconds().ok_or_else(|| Error::Encode("timestamp is out of range".to_string()))
+    }
+
+    fn parse_chrono<T>(raw: &[u8], parse: impl Fn(&str) -> chrono::ParseResult<T>) -> Result<T> {
+        let raw = text(raw)?;
+        parse(raw).map_err(|error| Error::Decode(format!("invalid value {:?}: {}", raw, error)))
+    }
+
+    fn after_epoch(micros: i64) -> Result<NaiveDateTime> {
+        epoch().checked_add_signed(Duration::microseconds(micros))
+            .ok_or_else(|| Error::Decode("timestamp is out of range".to_string()))
+    }
+
+    impl ToSql for NaiveDate {
+        fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            match format {
+                Format::Text => out.extend_from_slice(self.format("%Y-%m-%d").to_string().as_bytes()),
+                Format::Binary => {
+                    let days = i32::try_from(self.signed_duration_since(epoch().date()).num_days())
+                        .map_err(|_| Error::Encode("date is out of range".to_string()))?;
+                    out.extend_from_slice(&days.to_be_bytes());
+                }
+            }
+            Ok(IsNull::No)
+        }
+
+        type_oid!(oid::DATE);
+    }
+
+    impl FromSql for NaiveDate {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            let raw = non_null(raw)?;
+            match format {
+                Format::Text => parse_chrono(raw, |raw| NaiveDate::parse_from_str(raw, "%Y-%m-%d")),
+                Format::Binary => {
+                    let days = i32::from_be_bytes(fixed(raw)?);
+                    epoch().date().checked_add_signed(Duration::days(days.into()))
+                        .ok_or_else(|| Error::Decode("date is out of range".to_string()))
+                }
+            }
+        }
+    }
+
+    impl ToSql for NaiveTime {
+        fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            match format {
+                Format::Text => out.extend_from_slice(self.format("%H:%M:%S%.f").to_string().as_bytes()),
+                Format::Binary => {
+                    let micros = micros(self.signed_duration_since(NaiveTime::from_hms(0, 0, 0)))?;
+                    out.extend_from_slice(&micros.to_be_bytes());
+                }
+            }
+            Ok(IsNull::No)
+        }
+
+        type_oid!(oid::TIME);
+    }
+
+    impl FromSql for NaiveTime {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            let raw = non_null(raw)?;
+            match format {
+                Format::Text => parse_chrono(raw, |raw| NaiveTime::parse_from_str(raw, "%H:%M:%S%.f")),
+                Format::Binary => {
+                    let micros = i64::from_be_bytes(fixed(raw)?);
+                    Ok(NaiveTime::from_hms(0, 0, 0) + Duration::microseconds(micros))
+                }
+            }
+        }
+    }
+
+    impl ToSql for NaiveDateTime {
+        fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            match format {
+                Format::Text => out.extend_from_slice(self.format("%Y-%m-%d %H:%M:%S%.f").to_string().as_bytes()),
+                Format::Binary => out.extend_from_slice(&micros(self.signed_duration_since(epoch()))?.to_be_bytes())
+            }
+            Ok(IsNull::No)
+        }
+
+        type_oid!(oid::TIMESTAMP);
+    }
+
+    impl FromSql for NaiveDateTime {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            let raw = non_null(raw)?;
+            match format {
+                Format::Text => parse_chrono(raw, |raw| NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f")),
+                Format::Binary => after_epoch(i64::from_be_bytes(fixed(raw)?))
+            }
+        }
+    }
+
+    impl<Tz: TimeZone> ToSql for DateTime<Tz> {
+        fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            let utc = self.naive_utc();
+            match format {
+                Format::Text => out.extend_from_slice(utc.format("%Y-%m-%d %H:%M:%S%.f+00").to_string().as_bytes()),
+                Format::Binary => out.extend_from_slice(&micros(utc.signed_duration_since(epoch()))?.to_be_bytes())
+            }
+            Ok(IsNull::No)
+        }
+
+        type_oid!(oid::TIMESTAMPTZ);
+    }
+
+    impl FromSql for DateTime<FixedOffset> {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            let raw = non_null(raw)?;
+            match format {
+                Format::Text => parse_chrono(raw, |raw| DateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f%#z")),
+                Format::Binary => {
+                    let utc = after_epoch(i64::from_be_bytes(fixed(raw)?))?;
+                    Ok(FixedOffset::east(0).from_utc_datetime(&utc))
+                }
+            }
+        }
+    }
+
+    impl FromSql for DateTime<Utc> {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            DateTime::<FixedOffset>::from_sql(format, raw).map(|value| value.with_timezone(&Utc))
+        }
+    }
+    nestable!(NaiveDate, NaiveTime, NaiveDateTime);
+    impl<Tz: TimeZone> Nestable for DateTime<Tz> { }
+}
+
+/// Values stored as `JSONB` through serde, as used by `PlattEnum`s with
+/// `#[platt(repr = "jsonb")]`.
+pub mod jsonb {
+    use serde::{de::DeserializeOwned, Serialize};
+    use super::*;
+
+    /// The version byte the binary JSONB format starts with.
+    const JSONB_VERSION: u8 = 1;
+
+    pub(crate) fn json_from_slice<T: DeserializeOwned>(raw: &[u8]) -> Result<T> {
+        serde_json::from_slice(raw).map_err(|error| Error::Decode(error.to_string()))
+    }
+
+    pub fn to_sql<T: Serialize + ?Sized>(value: &T, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+        if format == Format::Binary {
+            out.push(JSONB_VERSION);
+        }
+        serde_json::to_writer(out, value).map_err(|error| Error::Encode(error.to_string()))?;
+        Ok(IsNull::No)
+    }
+
+    pub fn from_sql<T: DeserializeOwned>(format: Format, raw: Option<&[u8]>) -> Result<T> {
+        let raw = non_null(raw)?;
+        match format {
+            Format::Text => json_from_slice(raw),
+            Format::Binary => match raw.split_first() {
+                Some((&JSONB_VERSION, json)) => json_from_slice(json),
+                _ => Err(Error::Decode("unsupported JSONB version".to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+pub mod json {
+    use crate::schema::json::LenientJson;
+    use super::jsonb::json_from_slice;
+    use super::*;
+
+    impl ToSql for LenientJson {
+        fn to_sql(&self, _format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            serde_json::to_writer(out, &self.0).map_err(|error| Error::Encode(error.to_string()))?;
+            Ok(IsNull::No)
+        }
+
+        type_oid!(oid::JSON);
+    }
+
+    impl FromSql for LenientJson {
+        fn from_sql(_format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            json_from_slice(non_null(raw)?).map(LenientJson)
+        }
+    }
+
+    impl ToSql for serde_json::Value {
+        fn to_sql(&self, format: Format, out: &mut Vec<u8>) -> Result<IsNull> {
+            jsonb::to_sql(self, format, out)
+        }
+
+        type_oid!(oid::JSONB);
+    }
+
+    impl FromSql for serde_json::Value {
+        fn from_sql(format: Format, raw: Option<&[u8]>) -> Result<Self> {
+            jsonb::from_sql(format, raw)
+        }
+    }
+    nestable!(LenientJson, serde_json::Value);
+}
+
+/// Reading and writing composite (record) values, as used by the code the
+/// `PlattEnum` derive generates.
+pub mod record {
+    use super::*;
+
+    /// Writes the fields of a composite value one after another.
+    ///
+    /// The binary format carries the type OID of every field, so it can only
+    /// be used when every field has a built-in type.
+    pub struct RecordEncoder<'a> {
+        format: Format,
+        out: &'a mut Vec<u8>,
+        start: usize,
+        count: i32
+    }
+
+    impl<'a> RecordEncoder<'a> {
+        pub fn new(format: Format, out: &'a mut Vec<u8>) -> Self {
+            let start = out.len();
+            match format {
+                Format::Text => out.push(b'('),
+                Format::Binary => out.extend_from_slice(&0i32.to_be_bytes())
+            }
+            Self { format, out, start, count: 0 }
+        }
+
+        fn separator(&mut self) {
+            if self.format == Format::Text && self.count > 0 {
+                self.out.push(b',');
+            }
+            self.count += 1;
+        }
+
+        fn write(&mut self, oid: Option<u32>, encode: impl FnOnce(Format, &mut Vec<u8>) -> Result<IsNull>) -> Result<()> {
+            self.separator();
+            let mut value = Vec::new();
+            let is_null = encode(self.format, &mut value)?;
+            match self.format {
+                Format::Text => {
+                    if is_null == IsNull::No {
+                        quote_field(&value, self.out);
+                    }
+                }
+                Format::Binary => {
+                    let oid = oid.ok_or_else(|| Error::Encode(
+                        "composites containing user-defined types can only be sent in text format".to_string()
+                    ))?;
+                    self.out.extend_from_slice(&oid.to_be_bytes());
+                    match is_null {
+                        IsNull::Yes => self.out.extend_from_slice(&(-1i32).to_be_bytes()),
+                        IsNull::No => {
+                            self.out.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                            self.out.extend_from_slice(&value);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        pub fn field(&mut self, value: &dyn ToSql) -> Result<()> {
+            self.write(value.oid(), |format, out| value.to_sql(format, out))
+        }
+
+        /// A `NULL` field of a type without a built-in OID.
+        pub fn null(&mut self) -> Result<()> {
+            self.write(None, |_, _| Ok(IsNull::Yes))
+        }
+
+        /// A field that is itself a composite, written by `fields`.
+        pub fn nested(&mut self, fields: impl FnOnce(&mut RecordEncoder<'_>) -> Result<()>) -> Result<()> {
+            self.write(None, |format, out| {
+                let mut nested = RecordEncoder::new(format, out);
+                fields(&mut nested)?;
+                nested.finish()
+            })
+        }
+
+        pub fn finish(self) -> Result<IsNull> {
+            match self.format {
+                Format::Text => self.out.push(b')'),
+                Format::Binary => self.out[self.start..self.start + 4].copy_from_slice(&self.count.to_be_bytes())
+            }
+            Ok(IsNull::No)
+        }
+    }
+
+    fn quote_field(value: &[u8], out: &mut Vec<u8>) {
+        let needs_quotes = value.is_empty()
+            || value.iter().any(|byte| matches!(byte, b'(' | b')' | b',' | b'"' | b'\\') || byte.is_ascii_whitespace());
+        if !needs_quotes {
+            out.extend_from_slice(value);
+            return;
+        }
+        out.push(b'"');
+        for byte in value {
+            if matches!(byte, b'"' | b'\\') {
+                out.push(*byte);
+            }
+            out.push(*byte);
+        }
+        out.push(b'"');
+    }
+
+    /// Reads the fields of a composite value in order.
+    pub struct RecordDecoder {
+        format: Format,
+        fields: std::vec::IntoIter<Option<Vec<u8>>>
+    }
+
+    impl RecordDecoder {
+        pub fn new(format: Format, raw: &[u8]) -> Result<Self> {
+            let fields = match format {
+                Format::Text => split_record(text(raw)?)?,
+                Format::Binary => split_binary_record(raw)?
+            };
+            Ok(Self { format, fields: fields.into_iter() })
+        }
+
+        pub fn format(&self) -> Format {
+            self.format
+        }
+
+        /// The raw value of the next field, `None` for `NULL`.
+        pub fn raw_field(&mut self) -> Result<Option<Vec<u8>>> {
+            self.fields.next().ok_or_else(|| Error::Decode("composite value has too few fields".to_string()))
+        }
+
+        pub fn field<T: FromSql>(&mut self) -> Result<T> {
+            let raw = self.raw_field()?;
+            T::from_sql(self.format, raw.as_deref())
+        }
+    }
+
+    fn split_record(raw: &str) -> Result<Vec<Option<Vec<u8>>>> {
+        let invalid = || Error::Decode(format!("invalid composite value {:?}", raw));
+        let body = raw.strip_prefix('(').and_then(|body| body.strip_suffix(')')).ok_or_else(invalid)?;
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut bytes = body.bytes().peekable();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'"' if in_quotes && bytes.peek() == Some(&b'"') => {
+                    bytes.next();
+                    field.push(b'"');
+                }
+                b'"' => {
+                    in_quotes = !in_quotes;
+                    quoted = true;
+                }
+                b'\\' => field.push(bytes.next().ok_or_else(invalid)?),
+                b',' if !in_quotes => {
+                    fields.push(if field.is_empty() && !quoted { None } else { Some(std::mem::take(&mut field)) });
+                    quoted = false;
+                }
+                byte => field.push(byte)
+            }
+        }
+        if in_quotes {
+            return Err(invalid());
+        }
+        fields.push(if field.is_empty() && !quoted { None } else { Some(field) });
+        Ok(fields)
+    }
+
+    fn split_binary_record(raw: &[u8]) -> Result<Vec<Option<Vec<u8>>>> {
+        let truncated = || Error::Decode("truncated composite value".to_string());
+        let int = |offset: usize| -> Result<i32> {
+            raw.get(offset..offset + 4).map(|bytes| i32::from_be_bytes(fixed(bytes).unwrap())).ok_or_else(truncated)
+        };
+        let count = int(0)?;
+        let mut offset = 4;
+        let mut fields = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            // Skip the field's type OID.
+            let size = int(offset + 4)?;
+            offset += 8;
+            if size < 0 {
+                fields.push(None);
+            } else {
+                fields.push(Some(raw.get(offset..offset + size as usize).ok_or_else(truncated)?.to_vec()));
+                offset += size as usize;
+            }
+        }
+        Ok(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::record::{RecordDecoder, RecordEncoder};
+    use crate::PlattEnum;
+
+    fn encode<T: ToSql>(value: &T, format: Format) -> Vec<u8> {
+        let mut out = Vec::new();
+        assert_eq!(value.to_sql(format, &mut out).unwrap(), IsNull::No);
+        out
+    }
+
+    fn round_trip<T: ToSql + FromSql>(value: &T, format: Format) -> T {
+        T::from_sql(format, Some(&encode(value, format))).unwrap()
+    }
+
+    #[test]
+    fn text_array_quotes_elements() {
+        let elements = vec![
+            "{a}".to_string(), "".to_string(), "NULL".to_string(), "a,b".to_string(),
+            "say \"hi\" \\o/".to_string(), " padded ".to_string(), "plain".to_string()
+        ];
+        assert_eq!(
+            String::from_utf8(encode(&elements, Format::Text)).unwrap(),
+            r#"{"{a}","","NULL","a,b","say \"hi\" \\o/"," padded ",plain}"#
+        );
+        assert_eq!(round_trip(&elements, Format::Text), elements);
+    }
+
+    #[test]
+    fn text_array_nulls() {
+        let elements = vec![Some("NULL".to_string()), None, Some("x".to_string())];
+        assert_eq!(String::from_utf8(encode(&elements, Format::Text)).unwrap(), r#"{"NULL",NULL,x}"#);
+        assert_eq!(round_trip(&elements, Format::Text), elements);
+    }
+
+    #[test]
+    fn text_array_nests_arrays() {
+        let elements = vec![vec![1i32, 2], vec![3, 4]];
+        assert_eq!(String::from_utf8(encode(&elements, Format::Text)).unwrap(), "{{1,2},{3,4}}");
+        assert_eq!(round_trip(&elements, Format::Text), elements);
+        let strings = vec![vec!["{".to_string()], vec!["}".to_string()]];
+        assert_eq!(String::from_utf8(encode(&strings, Format::Text)).unwrap(), r#"{{"{"},{"}"}}"#);
+        assert_eq!(round_trip(&strings, Format::Text), strings);
+    }
+
+    #[test]
+    fn binary_array_round_trip() {
+        let elements = vec![Some(1i64), None, Some(-3)];
+        assert_eq!(round_trip(&elements, Format::Binary), elements);
+        let empty: Vec<i32> = Vec::new();
+        assert_eq!(round_trip(&empty, Format::Binary), empty);
+        let fixed = [1i16, 2, 3];
+        assert_eq!(round_trip(&fixed, Format::Binary), fixed);
+    }
+
+    /// A binary `NUMERIC`: digit count, weight, sign and scale, then the
+    /// base 10000 digits.
+    fn numeric(weight: i16, sign: u16, scale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut out = (digits.len() as i16).to_be_bytes().to_vec();
+        out.extend_from_slice(&weight.to_be_bytes());
+        out.extend_from_slice(&sign.to_be_bytes());
+        out.extend_from_slice(&scale.to_be_bytes());
+        for digit in digits {
+            out.extend_from_slice(&digit.to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn binary_numeric_groups_digits_around_the_point() {
+        let value: Decimal<10, 3> = "12345.678".parse().unwrap();
+        assert_eq!(encode(&value, Format::Binary), numeric(1, NUMERIC_POSITIVE, 3, &[1, 2345, 6780]));
+        let value: Decimal<5, 2> = "-0.05".parse().unwrap();
+        assert_eq!(encode(&value, Format::Binary), numeric(-1, NUMERIC_NEGATIVE, 2, &[500]));
+        let value: Decimal<10, 4> = "10000".parse().unwrap();
+        assert_eq!(encode(&value, Format::Binary), numeric(1, NUMERIC_POSITIVE, 4, &[1]));
+        let value: Decimal<5, 2> = "0".parse().unwrap();
+        assert_eq!(encode(&value, Format::Binary), numeric(0, NUMERIC_POSITIVE, 2, &[]));
+
+        for text in ["12345.678", "-12345.678", "0.001", "-9999999.999", "10000", "0"] {
+            let value: Decimal<10, 3> = text.parse().unwrap();
+            assert_eq!(round_trip(&value, Format::Binary), value);
+            assert_eq!(round_trip(&value, Format::Text), value);
+        }
+    }
+
+    #[test]
+    fn binary_numeric_pads_to_the_scale() {
+        // Postgres drops trailing zero digits, whatever the column's scale.
+        let value = Decimal::<10, 4>::from_sql(Format::Binary, Some(&numeric(0, NUMERIC_POSITIVE, 1, &[1, 5000]))).unwrap();
+        assert_eq!(value.unscaled(), 15000);
+        assert_eq!(value.to_string(), "1.5000");
+        let value = Decimal::<10, 2>::from_sql(Format::Binary, Some(&numeric(1, NUMERIC_NEGATIVE, 0, &[3]))).unwrap();
+        assert_eq!(value.unscaled(), -3_000_000);
+
+        let too_long = numeric(2, NUMERIC_POSITIVE, 0, &[1]);
+        assert!(matches!(Decimal::<5, 2>::from_sql(Format::Binary, Some(&too_long)), Err(Error::Decode(_))));
+        let too_precise = numeric(-1, NUMERIC_POSITIVE, 4, &[1]);
+        assert!(matches!(Decimal::<5, 2>::from_sql(Format::Binary, Some(&too_precise)), Err(Error::Decode(_))));
+        let nan = numeric(0, 0xC000, 0, &[]);
+        assert!(matches!(Decimal::<5, 2>::from_sql(Format::Binary, Some(&nan)), Err(Error::Decode(_))));
+        assert!(matches!(Decimal::<5, 2>::from_sql(Format::Binary, Some(&[0, 1, 0, 0])), Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn unsigned_integers_check_their_range() {
+        for format in [Format::Text, Format::Binary] {
+            assert_eq!(round_trip(&32767u16, format), 32767);
+            assert_eq!(round_trip(&2147483647u32, format), 2147483647);
+            assert_eq!(round_trip(&(i64::MAX as u64), format), i64::MAX as u64);
+        }
+        let mut out = Vec::new();
+        assert!(matches!(40000u16.to_sql(Format::Binary, &mut out), Err(Error::Encode(_))));
+        assert!(matches!(3_000_000_000u32.to_sql(Format::Text, &mut out), Err(Error::Encode(_))));
+        assert!(matches!(u64::MAX.to_sql(Format::Binary, &mut out), Err(Error::Encode(_))));
+        assert!(out.is_empty());
+
+        assert!(matches!(u16::from_sql(Format::Binary, Some(&(-1i16).to_be_bytes())), Err(Error::Decode(_))));
+        assert!(matches!(u32::from_sql(Format::Text, Some(b"-5")), Err(Error::Decode(_))));
+        assert!(matches!(u64::from_sql(Format::Binary, Some(&i64::MIN.to_be_bytes())), Err(Error::Decode(_))));
+        assert!(matches!(u8::from_sql(Format::Binary, Some(&256i16.to_be_bytes())), Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn non_zero_integers_check_their_range() {
+        use std::num::{NonZeroI32, NonZeroU16, NonZeroU64};
+
+        let value = NonZeroI32::new(-7).unwrap();
+        assert_eq!(encode(&value, Format::Binary), (-7i32).to_be_bytes());
+        assert_eq!(round_trip(&value, Format::Binary), value);
+        assert_eq!(NonZeroI32::type_oid(), Some(oid::INT4));
+
+        let mut out = Vec::new();
+        assert!(matches!(NonZeroU16::new(40000).unwrap().to_sql(Format::Binary, &mut out), Err(Error::Encode(_))));
+        assert!(matches!(NonZeroU16::from_sql(Format::Binary, Some(&0i16.to_be_bytes())), Err(Error::Decode(_))));
+        assert!(matches!(NonZeroU16::from_sql(Format::Binary, Some(&(-2i16).to_be_bytes())), Err(Error::Decode(_))));
+        assert!(matches!(NonZeroU64::from_sql(Format::Text, Some(b"0")), Err(Error::Decode(_))));
+    }
+
+    #[cfg(feature = "chrono_type")]
+    #[test]
+    fn binary_chrono_counts_from_2000() {
+        use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+        let date = NaiveDate::from_ymd(2000, 1, 1);
+        assert_eq!(encode(&date, Format::Binary), 0i32.to_be_bytes());
+        assert_eq!(encode(&NaiveDate::from_ymd(1999, 12, 31), Format::Binary), (-1i32).to_be_bytes());
+        assert_eq!(NaiveDate::from_sql(Format::Binary, Some(&366i32.to_be_bytes())).unwrap(), NaiveDate::from_ymd(2001, 1, 1));
+
+        let time = NaiveTime::from_hms_micro(0, 0, 1, 500_000);
+        assert_eq!(encode(&time, Format::Binary), 1_500_000i64.to_be_bytes());
+        assert_eq!(round_trip(&time, Format::Binary), time);
+
+        let timestamp = NaiveDate::from_ymd(2000, 1, 2).and_hms(0, 0, 0);
+        assert_eq!(encode(&timestamp, Format::Binary), 86_400_000_000i64.to_be_bytes());
+        let before = NaiveDate::from_ymd(1970, 1, 1).and_hms_micro(0, 0, 0, 1);
+        assert_eq!(round_trip(&before, Format::Binary), before);
+        assert!(matches!(NaiveDateTime::from_sql(Format::Binary, Some(&i64::MAX.to_be_bytes())), Err(Error::Decode(_))));
+
+        let instant = Utc.ymd(2000, 1, 1).and_hms(0, 0, 1);
+        assert_eq!(encode(&instant, Format::Binary), 1_000_000i64.to_be_bytes());
+        assert_eq!(DateTime::<Utc>::from_sql(Format::Binary, Some(&1_000_000i64.to_be_bytes())).unwrap(), instant);
+        assert_eq!(round_trip(&instant, Format::Text), instant);
+    }
+
+    #[test]
+    fn bit_strings_pack_bits_high_first() {
+        let bits = BitString(vec![true, false, true, true, false, false, false, false, true]);
+        assert_eq!(encode(&bits, Format::Text), b"101100001");
+        assert_eq!(encode(&bits, Format::Binary), [0, 0, 0, 9, 0b1011_0000, 0b1000_0000]);
+        assert_eq!(round_trip(&BitString(vec![]), Format::Binary), BitString(vec![]));
+        for format in [Format::Text, Format::Binary] {
+            assert_eq!(round_trip(&bits, format), bits);
+        }
+        assert!(matches!(BitString::from_sql(Format::Text, Some(b"102")), Err(Error::Decode(_))));
+        assert!(matches!(BitString::from_sql(Format::Binary, Some(&[0, 0, 0, 9, 0xff])), Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn fixed_bit_strings_check_their_length() {
+        let bits = BitStringFixed([true, false, true]);
+        assert_eq!(encode(&bits, Format::Binary), [0, 0, 0, 3, 0b1010_0000]);
+        for format in [Format::Text, Format::Binary] {
+            assert_eq!(round_trip(&bits, format), bits);
+        }
+        assert!(matches!(BitStringFixed::<3>::from_sql(Format::Text, Some(b"10")), Err(Error::Decode(_))));
+        assert!(matches!(BitStringFixed::<3>::from_sql(Format::Binary, Some(&[0, 0, 0, 4, 0xf0])), Err(Error::Decode(_))));
+    }
+
+    fn encode_record(format: Format) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut record = RecordEncoder::new(format, &mut out);
+        record.field(&7i32).unwrap();
+        record.field(&"a \"b\", (c)".to_string()).unwrap();
+        record.field(&Option::<i32>::None).unwrap();
+        record.field(&String::new()).unwrap();
+        record.finish().unwrap();
+        out
+    }
+
+    fn decode_record(format: Format, raw: &[u8]) -> (i32, String, Option<i32>, String) {
+        let mut record = RecordDecoder::new(format, raw).unwrap();
+        (record.field().unwrap(), record.field().unwrap(), record.field().unwrap(), record.field().unwrap())
+    }
+
+    #[test]
+    fn text_record_round_trip() {
+        let raw = encode_record(Format::Text);
+        assert_eq!(String::from_utf8(raw.clone()).unwrap(), r#"(7,"a ""b"", (c)",,"")"#);
+        assert_eq!(decode_record(Format::Text, &raw), (7, "a \"b\", (c)".to_string(), None, String::new()));
+    }
+
+    #[test]
+    fn binary_record_round_trip() {
+        let raw = encode_record(Format::Binary);
+        assert_eq!(decode_record(Format::Binary, &raw), (7, "a \"b\", (c)".to_string(), None, String::new()));
+    }
+
+    #[test]
+    fn nested_text_record_round_trip() {
+        let mut out = Vec::new();
+        let mut record = RecordEncoder::new(Format::Text, &mut out);
+        record.field(&1i32).unwrap();
+        record.nested(|nested| {
+            nested.field(&"x,y".to_string())?;
+            nested.null()
+        }).unwrap();
+        record.finish().unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), r#"(1,"(""x,y"",)")"#);
+
+        let mut decoder = RecordDecoder::new(Format::Text, &out).unwrap();
+        assert_eq!(decoder.field::<i32>().unwrap(), 1);
+        let inner = decoder.raw_field().unwrap().unwrap();
+        let mut inner = RecordDecoder::new(Format::Text, &inner).unwrap();
+        assert_eq!(inner.field::<String>().unwrap(), "x,y");
+        assert_eq!(inner.field::<Option<String>>().unwrap(), None);
+    }
+
+    #[derive(PlattEnum, Debug, PartialEq)]
+    enum Role {
+        Admin,
+        #[platt(renamed_from = "User")]
+        Member
+    }
+
+    #[derive(PlattEnum, Debug, PartialEq)]
+    #[platt(repr = "composite")]
+    enum Level {
+        Low,
+        High
+    }
+
+    #[test]
+    fn unit_enums_are_native_enums() {
+        assert_eq!(Role::enums(), [crate::schema::EnumType {
+            name: "Role".to_string(),
+            schema: None,
+            labels: vec!["Admin".to_string(), "Member".to_string()],
+            renamed_labels: vec![("User".to_string(), "Member".to_string())]
+        }]);
+        assert!(Role::composites().is_empty());
+        assert_eq!(Role::db_type().base, "\"Role\"");
+
+        assert!(Level::enums().is_empty());
+        assert!(!Level::composites().is_empty());
+    }
+
+    #[test]
+    fn native_enums_are_sent_as_labels() {
+        for format in [Format::Text, Format::Binary] {
+            assert_eq!(encode(&Role::Admin, format), b"Admin");
+            assert_eq!(encode(&Role::Member, format), b"Member");
+            assert_eq!(round_trip(&Role::Member, format), Role::Member);
+            assert_eq!(Role::from_sql(format, Some(b"Admin")).unwrap(), Role::Admin);
+        }
+        assert_eq!(Role::Admin.oid(), None);
+        assert_eq!(round_trip(&Level::High, Format::Text), Level::High);
+    }
+
+    #[test]
+    fn native_enums_reject_unknown_labels() {
+        for format in [Format::Text, Format::Binary] {
+            match Role::from_sql(format, Some(b"User")) {
+                Err(Error::UnknownLabel { type_name: "Role", label }) => assert_eq!(label, "User"),
+                other => panic!("expected UnknownLabel, got {:?}", other)
+            }
+        }
+        assert!(matches!(Role::from_sql(Format::Text, None), Err(Error::UnexpectedNull)));
+    }
+
+    #[derive(PlattEnum, Debug, PartialEq)]
+    enum PostStatus {
+        Draft,
+        Published { at: u16 },
+        Removed { at: u16, reason: String }
+    }
+
+    /// A composite value in the binary format Postgres sends, with `oid`
+    /// standing in for the OIDs of user-defined types.
+    fn binary_record(fields: &[(u32, Option<Vec<u8>>)]) -> Vec<u8> {
+        let mut out = (fields.len() as i32).to_be_bytes().to_vec();
+        for (oid, value) in fields {
+            out.extend_from_slice(&oid.to_be_bytes());
+            match value {
+                Some(value) => {
+                    out.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                    out.extend_from_slice(value);
+                }
+                None => out.extend_from_slice(&(-1i32).to_be_bytes())
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn derived_composite_text_round_trip() {
+        let removed = PostStatus::Removed { at: 5, reason: "spam, \"ads\"".to_string() };
+        assert_eq!(
+            String::from_utf8(encode(&removed, Format::Text)).unwrap(),
+            r#"(2,,"(5,""spam, """"ads"""""")")"#
+        );
+        assert_eq!(String::from_utf8(encode(&PostStatus::Draft, Format::Text)).unwrap(), "(0,,)");
+        for status in [PostStatus::Draft, PostStatus::Published { at: 3 }, removed] {
+            assert_eq!(round_trip(&status, Format::Text), status);
+        }
+    }
+
+    #[test]
+    fn derived_composite_binary_decode() {
+        let user_type = 16384;
+        let removed = binary_record(&[
+            (oid::INT2, Some(5i16.to_be_bytes().to_vec())),
+            (oid::TEXT, Some(b"spam".to_vec()))
+        ]);
+        let raw = binary_record(&[(oid::INT4, Some(2i32.to_be_bytes().to_vec())), (user_type, None), (user_type, Some(removed))]);
+        assert_eq!(
+            PostStatus::from_sql(Format::Binary, Some(&raw)).unwrap(),
+            PostStatus::Removed { at: 5, reason: "spam".to_string() }
+        );
+        let raw = binary_record(&[(oid::INT4, Some(0i32.to_be_bytes().to_vec())), (user_type, None), (user_type, None)]);
+        assert_eq!(PostStatus::from_sql(Format::Binary, Some(&raw)).unwrap(), PostStatus::Draft);
+
+        // The OIDs of the variant composites are not known up front.
+        let mut out = Vec::new();
+        assert!(matches!(PostStatus::Draft.to_sql(Format::Binary, &mut out), Err(Error::Encode(_))));
+    }
+
+    #[test]
+    fn derived_composite_unknown_discriminant() {
+        let text = b"(3,,)".to_vec();
+        let binary = binary_record(&[(oid::INT4, Some(3i32.to_be_bytes().to_vec())), (0, None), (0, None)]);
+        for (format, raw) in [(Format::Text, text), (Format::Binary, binary)] {
+            match PostStatus::from_sql(format, Some(&raw)) {
+                Err(Error::UnknownDiscriminant { type_name: "PostStatus", discriminant: 3 }) => (),
+                other => panic!("expected UnknownDiscriminant, got {:?}", other)
+            }
+        }
+    }
+
+    #[derive(PlattEnum, Debug, PartialEq)]
+    enum Figure {
+        Point,
+        Circle(i32),
+        Rect(i32, Option<String>)
+    }
+
+    #[test]
+    fn tuple_variants_name_fields_by_position() {
+        let composites = Figure::composites();
+        let fields = |name: &str| -> Vec<String> {
+            let composite = composites.iter().find(|composite| composite.name == name).unwrap();
+            composite.fields.iter().map(|(field, _)| field.clone()).collect()
+        };
+        assert_eq!(fields("Figure_Circle"), ["_0"]);
+        assert_eq!(fields("Figure_Rect"), ["_0", "_1"]);
+    }
+
+    #[test]
+    fn tuple_variant_round_trip() {
+        let rect = Figure::Rect(2, Some("a, b".to_string()));
+        assert_eq!(String::from_utf8(encode(&rect, Format::Text)).unwrap(), r#"(2,,"(2,""a, b"")")"#);
+        for figure in [Figure::Point, Figure::Circle(-3), Figure::Rect(4, None), rect] {
+            assert_eq!(round_trip(&figure, Format::Text), figure);
+        }
+
+        let user_type = 16384;
+        let rect = binary_record(&[(oid::INT4, Some(7i32.to_be_bytes().to_vec())), (oid::TEXT, None)]);
+        let raw = binary_record(&[(oid::INT4, Some(2i32.to_be_bytes().to_vec())), (user_type, None), (user_type, Some(rect))]);
+        assert_eq!(Figure::from_sql(Format::Binary, Some(&raw)).unwrap(), Figure::Rect(7, None));
+    }
+
+    #[derive(PlattEnum, Debug, PartialEq)]
+    #[platt(repr = "columns")]
+    enum Shape {
+        Empty,
+        Circle { radius: i32 },
+        Rect(i32, Option<String>)
+    }
+
+    /// The row holding `columns`, as read back after writing them.
+    fn row_of(columns: &[ColumnValue], format: Format) -> Row {
+        let names: Vec<String> = columns.iter().map(|column| column.column.clone()).collect();
+        Row::new(names.into(), format, columns.iter().map(|column| column.value.clone()).collect())
+    }
+
+    #[test]
+    fn flattened_enum_round_trip() {
+        let shapes = [Shape::Empty, Shape::Circle { radius: 3 }, Shape::Rect(2, None), Shape::Rect(2, Some("x".to_string()))];
+        for format in [Format::Text, Format::Binary] {
+            for shape in &shapes {
+                let mut columns = Vec::new();
+                shape.to_columns("shape", format, &mut columns).unwrap();
+                let names: Vec<&str> = columns.iter().map(|column| column.column.as_str()).collect();
+                assert_eq!(names, ["shape", "shape_circle_radius", "shape_rect_0", "shape_rect_1"]);
+                assert_eq!(&Shape::from_row(&row_of(&columns, format), "shape").unwrap(), shape);
+            }
+        }
+
+        let mut columns = Vec::new();
+        Shape::Circle { radius: 3 }.to_columns("shape", Format::Text, &mut columns).unwrap();
+        let values: Vec<Option<&[u8]>> = columns.iter().map(|column| column.value.as_deref()).collect();
+        assert_eq!(values, [Some(&b"Circle"[..]), Some(&b"3"[..]), None, None]);
+    }
+
+    #[test]
+    fn optional_flattened_enum_round_trip() {
+        for shape in [None, Some(Shape::Rect(1, Some("y".to_string())))] {
+            let mut columns = Vec::new();
+            shape.to_columns("shape", Format::Binary, &mut columns).unwrap();
+            assert_eq!(columns.len(), 4);
+            assert_eq!(Option::<Shape>::from_row(&row_of(&columns, Format::Binary), "shape").unwrap(), shape);
+        }
+    }
+
+    #[test]
+    fn flattened_enum_unknown_label() {
+        let columns = [ColumnValue { column: "shape".to_string(), oid: None, value: Some(b"Square".to_vec()) }];
+        match Shape::from_row(&row_of(&columns, Format::Text), "shape") {
+            Err(Error::UnknownLabel { type_name: "Shape", label }) => assert_eq!(label, "Square"),
+            other => panic!("expected UnknownLabel, got {:?}", other.map(|_| ()))
+        }
+    }
+}
@@ -1,3 +1 @@
-mod models;
-
 fn main() { }
@@ -1,9 +1,125 @@
-use std::fs::File;
-use std::io::Read;
 mod models;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+use std::fs::File;
+use platt::migrate::{MigrationStep, Migrator};
+
+const MIGRATIONS_DIR: &str = "migrations";
+
+async fn connect() -> Result<tokio_postgres::Client, Box<dyn std::error::Error>> {
+    let url = std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL is not set")?;
+    let (client, connection) = tokio_postgres::connect(&url, tokio_postgres::NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            eprintln!("connection error: {}", error);
+        }
+    });
+    Ok(client)
+}
+
+/// Prints the SQL creating the schema, or runs one of the commands:
+///
+/// - `diff`: the statements bringing the schema saved in
+///   `current_schema.json` up to date
+/// - `makemigrations <name> [--run <data migration>]...`: writes a
+///   migration for the model changes, running the given data migrations
+///   between them
+/// - `migrate [--to <version> [--acknowledge]]`: applies pending migrations
+///   to `DATABASE_URL`, or applies and reverts them until `<version>` is the
+///   latest applied one, which only reverts migrations dropping data with
+///   `--acknowledge`
+/// - `check`: lists the problems [`Schema::validate`] finds in the models,
+///   then the statements `DATABASE_URL` is missing to match them when it is
+///   set, exiting with an error if there are any of either
+/// - `inspectdb`: prints models matching the tables of `DATABASE_URL`
+///
+/// [`Schema::validate`]: platt::schema::Schema::validate
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let new_schema = models::get_schema();
-    println!("{}", new_schema.to_sql());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let migrator = Migrator::new(MIGRATIONS_DIR).with_data_migrations(models::get_data_migrations());
+    match args.first().map(String::as_str) {
+        Some("diff") => {
+            let current_schema = match File::open("current_schema.json") {
+                Ok(file) => serde_json::from_reader(file)?,
+                Err(_) => platt::schema::Schema::empty()
+            };
+            let diff = new_schema.diff(&current_schema);
+            for warning in diff.warnings() {
+                eprintln!("warning: {}", warning);
+            }
+            print!("{}", diff.to_sql());
+        }
+        Some("makemigrations") => {
+            let name = args.get(1).map(String::as_str).filter(|name| !name.starts_with("--")).unwrap_or("auto");
+            let mut data_migrations = Vec::new();
+            let mut rest = args.iter().skip(1).skip_while(|arg| !arg.starts_with("--"));
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--run" => data_migrations.push(rest.next().ok_or("--run needs a data migration")?.as_str()),
+                    _ => return Err(format!("unknown option {}", arg).into())
+                }
+            }
+            for warning in new_schema.diff(&migrator.current_schema()?).warnings() {
+                eprintln!("warning: {}", warning);
+            }
+            match migrator.make_with_data(&new_schema, name, &data_migrations)? {
+                Some(path) => println!("Wrote {}", path.display()),
+                None => println!("No changes")
+            }
+        }
+        Some("migrate") => {
+            let mut client = connect().await?;
+            let steps = match args.get(1).map(String::as_str) {
+                Some("--to") => {
+                    let version = args.get(2).ok_or("--to needs a version")?.parse()?;
+                    let acknowledged = match args.get(3).map(String::as_str) {
+                        Some("--acknowledge") => true,
+                        Some(arg) => return Err(format!("unknown option {}", arg).into()),
+                        None => false
+                    };
+                    migrator.with_reverts_acknowledged(acknowledged).migrate_to(&mut client, version).await?
+                }
+                _ => migrator.apply(&mut client).await?.into_iter().map(MigrationStep::Applied).collect()
+            };
+            if steps.is_empty() {
+                println!("No migrations to apply");
+            }
+            for step in steps {
+                match step {
+                    MigrationStep::Applied(version) => println!("Applied {:04}", version),
+                    MigrationStep::Reverted(version) => println!("Reverted {:04}", version)
+                }
+            }
+        }
+        Some("check") => {
+            let problems = new_schema.validate();
+            for problem in &problems {
+                eprintln!("error: {}", problem);
+            }
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+            if std::env::var("DATABASE_URL").is_err() {
+                println!("The models have no problems, set DATABASE_URL to compare them with the database");
+                return Ok(());
+            }
+            let mut client = connect().await?;
+            let live_schema = platt::schema::Schema::introspect(&mut client).await?;
+            let diff = new_schema.diff(&live_schema);
+            if !diff.is_empty() {
+                eprintln!("The database has drifted from the models, it needs:");
+                eprint!("{}", diff.to_sql());
+                std::process::exit(1);
+            }
+            println!("The database matches the models");
+        }
+        Some("inspectdb") => {
+            let mut client = connect().await?;
+            let live_schema = platt::schema::Schema::introspect(&mut client).await?;
+            print!("{}", platt::inspectdb::generate(&live_schema));
+        }
+        _ => println!("{}", new_schema.to_sql())
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,3 +1,8 @@
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use platt::db::Connection;
+use platt::migrate::HistoricalSchema;
 use platt::{PlattModel, PlattEnum};
 
 #[derive(PlattEnum)]
@@ -7,11 +12,18 @@ pub enum BlogPostStatus {
     Removed { at: u16, reason: String }
 }
 
+#[derive(PlattEnum)]
+pub enum Role {
+    Admin,
+    Member
+}
+
 #[derive(PlattModel)]
 #[platt(not_clonable)]
 pub struct User {
     email: String,
-    password: String
+    password: String,
+    role: Role
 }
 
 #[derive(PlattModel)]
@@ -24,4 +36,14 @@ pub struct BlogPost {
     posted_by: platt::schema::ForeignKey<User>
 }
 
-platt::activate_models!(User, BlogPost);
\ No newline at end of file
+/// Emails are compared case-insensitively, so existing ones are lowercased.
+fn lowercase_emails<'t>(conn: &'t mut dyn Connection, _schema: &'t HistoricalSchema) -> BoxFuture<'t, platt::error::Result<()>> {
+    async move {
+        conn.execute("UPDATE \"User\" SET \"email\" = lower(\"email\")", &[]).await?;
+        Ok(())
+    }.boxed()
+}
+
+platt::activate_models!(User, BlogPost; data_migrations {
+    "lowercase_emails" => lowercase_emails
+});
\ No newline at end of file
@@ -1,78 +1,549 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use proc_macro2::TokenStream as TokenStream2;
+use darling::{FromDeriveInput, FromVariant};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, DeriveInput, spanned::Spanned};
+use heck::SnakeCase;
 
+#[derive(Debug, Clone, FromDeriveInput)]
+#[darling(attributes(platt), supports(enum_any))]
+struct PlattEnum {
+    /// One of `"enum"` (a Postgres `ENUM`), `"composite"`, `"jsonb"` or
+    /// `"columns"`. Defaults to an `ENUM` when no variant holds data and to a
+    /// composite otherwise.
+    #[darling(default)]
+    repr: Option<String>,
+    /// The Postgres schema (namespace) the types of the enum live in,
+    /// `public` when unset.
+    #[darling(default)]
+    schema: Option<String>
+}
+
+#[derive(Debug, Clone, FromVariant)]
+#[darling(attributes(platt))]
+struct PlattVariantAttrs {
+    /// The name the variant had before, which the schema diff renames.
+    #[darling(default)]
+    renamed_from: Option<String>
+}
+
+enum Repr {
+    Enum,
+    Composite,
+    Jsonb,
+    Columns
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VariantStyle {
+    Unit,
+    Named,
+    Tuple
+}
+
+struct PlattVariant {
+    ident: syn::Ident,
+    discriminant: u32,
+    style: VariantStyle,
+    /// The fields of the variant; those of a tuple variant are named after
+    /// their position, as in `_0` and `_1`.
+    fields: Vec<(syn::Ident, syn::TypePath)>,
+    composite_name: Option<String>,
+    renamed_from: Option<String>
+}
+
+impl PlattVariant {
+    fn snake(&self) -> String {
+        self.ident.to_string().to_snake_case()
+    }
+
+    /// A pattern matching the variant, binding its fields by name.
+    fn pattern(&self) -> TokenStream2 {
+        let variant_ident = &self.ident;
+        let field_idents = self.fields.iter().map(|(ident, _)| ident);
+        match self.style {
+            VariantStyle::Unit => quote! { Self::#variant_ident },
+            VariantStyle::Named => quote! { Self::#variant_ident { #(#field_idents),* } },
+            VariantStyle::Tuple => quote! { Self::#variant_ident( #(#field_idents),* ) }
+        }
+    }
+
+    /// Builds the variant out of one expression per field.
+    fn construct(&self, values: &[TokenStream2]) -> TokenStream2 {
+        let variant_ident = &self.ident;
+        let field_idents = self.fields.iter().map(|(ident, _)| ident);
+        match self.style {
+            VariantStyle::Unit => quote! { Self::#variant_ident },
+            VariantStyle::Named => quote! { Self::#variant_ident { #( #field_idents: #values ),* } },
+            VariantStyle::Tuple => quote! { Self::#variant_ident( #(#values),* ) }
+        }
+    }
+
+    /// The suffix of the column holding `field` when flattened into columns,
+    /// e.g. `_removed_reason` or `_rect_0`.
+    fn column_suffix(&self, field: &syn::Ident) -> String {
+        match self.style {
+            VariantStyle::Tuple => format!("_{}{}", self.snake(), field),
+            _ => format!("_{}_{}", self.snake(), field)
+        }
+    }
+}
 
 pub fn inner(model: TokenStream) -> TokenStream {
     let model = parse_macro_input!(model as DeriveInput);
-    let mut req_composites = quote! { let mut composites = ::std::vec::Vec::new();  };
+    let platt_enum = PlattEnum::from_derive_input(&model).unwrap();
 
     if model.generics.lt_token.is_some() {
         panic!("Platt does not support models with generics. Perhaps use an enum instead.")
     }
     let enum_name = model.ident.clone();
-    let data = if let syn::Data::Enum(data) = model.data {
+    let data = if let syn::Data::Enum(ref data) = model.data {
         data
     } else {
         panic!("Platt enums can only be derived on enums.")
     };
 
-    let mut composites = Vec::new();
-    for variant in &data.variants {
-        match variant.fields {
-            syn::Fields::Named(ref fields) => {
-                if fields.named.len() > 0 {
-                    let composite_name = format!("{}_{}", enum_name, variant.ident);
-                    let mut composite = quote! { let mut composite = ::platt::schema::Composite { name: #composite_name.to_string(), fields: Vec::new() }; };
-                    for p_field in variant.fields.iter() {
-                        let column_name = format!("{}", p_field.ident.as_ref().unwrap());
-                        match &p_field.ty {
-                            // syn::Type::Array(ty_array) =>  { }
-                            syn::Type::Path(ty_path) =>  { 
-                                req_composites.extend(quote! { composites.extend(<#ty_path as ::platt::schema::HasDbType>::composites()); });
-                                composite.extend(quote!{
-                                    composite.fields.push( (#column_name.to_string(), <#ty_path as ::platt::schema::HasDbType>::db_type()) );
-                                })
-                            }
-                            _ => panic!("Platt models can only contain type paths.")
-                        }
-                    }
-                    req_composites.extend(quote! {
-                        {
-                            #composite
-                            composites.push(composite);
-                        }
-                    });
-                    composites.push((variant.ident.to_string(), composite_name));
+    let mut variants = Vec::new();
+    for (discriminant, variant) in data.variants.iter().enumerate() {
+        let mut fields = Vec::new();
+        let style = match variant.fields {
+            syn::Fields::Named(_) => VariantStyle::Named,
+            syn::Fields::Unnamed(_) => VariantStyle::Tuple,
+            syn::Fields::Unit => VariantStyle::Unit
+        };
+        for (position, p_field) in variant.fields.iter().enumerate() {
+            let field_ident = match &p_field.ident {
+                Some(ident) => ident.clone(),
+                None => format_ident!("_{}", position)
+            };
+            match &p_field.ty {
+                syn::Type::Path(ty_path) => fields.push((field_ident, ty_path.clone())),
+                _ => panic!("Platt models can only contain type paths.")
+            }
+        }
+        let composite_name = if fields.is_empty() {
+            None
+        } else {
+            Some(format!("{}_{}", enum_name, variant.ident))
+        };
+        variants.push(PlattVariant {
+            ident: variant.ident.clone(),
+            discriminant: discriminant as u32,
+            style,
+            fields,
+            composite_name,
+            renamed_from: PlattVariantAttrs::from_variant(variant).unwrap().renamed_from
+        });
+    }
+
+    let unit_only = variants.iter().all(|variant| variant.composite_name.is_none());
+    let repr = match platt_enum.repr.as_deref() {
+        None if unit_only => Repr::Enum,
+        None | Some("composite") => Repr::Composite,
+        Some("enum") if unit_only => Repr::Enum,
+        Some("enum") => panic!("Only enums without data can be stored as a Postgres ENUM."),
+        Some("jsonb") => Repr::Jsonb,
+        Some("columns") => Repr::Columns,
+        Some(other) => panic!(
+            "Unknown PlattEnum repr \"{}\", expected \"enum\", \"composite\", \"jsonb\" or \"columns\".",
+            other
+        )
+    };
+    if matches!(repr, Repr::Jsonb | Repr::Columns) && variants.iter().any(|variant| variant.renamed_from.is_some()) {
+        panic!(
+            "renamed_from on variants needs the \"enum\" or \"composite\" repr, as the stored values of a \
+            \"jsonb\" or \"columns\" enum hold the variant names. Rename them with a data migration instead."
+        );
+    }
+    let schema = platt_enum.schema.as_deref().filter(|schema| *schema != "public");
+    let result = match repr {
+        Repr::Enum => native_enum(&enum_name, &variants, schema),
+        Repr::Composite => composite(&enum_name, &variants, schema),
+        Repr::Jsonb => jsonb(&enum_name, &variants, serde_tag(&model.attrs)),
+        Repr::Columns => columns(&enum_name, &variants)
+    };
+    result.into()
+}
+
+/// The `schema` field of the types of the enum.
+fn schema_tokens(schema: Option<&str>) -> TokenStream2 {
+    match schema {
+        Some(schema) => quote! { ::std::option::Option::Some(#schema.to_string()) },
+        None => quote! { ::std::option::Option::None }
+    }
+}
+
+/// The quoted name of the type `name`, qualified by `schema` when set.
+fn qualified_type(schema: Option<&str>, name: &str) -> String {
+    match schema {
+        Some(schema) => format!("\"{}\".\"{}\"", schema, name),
+        None => format!("\"{}\"", name)
+    }
+}
+
+/// Collects the types the fields of every variant need.
+fn required_types(variants: &[PlattVariant]) -> (TokenStream2, TokenStream2) {
+    let mut req_composites = quote! { let mut composites = ::std::vec::Vec::new();  };
+    let mut req_enums = quote! { let mut enums = ::std::vec::Vec::new(); };
+    for variant in variants {
+        for (_, ty_path) in &variant.fields {
+            req_composites.extend(quote! { composites.extend(<#ty_path as ::platt::schema::HasDbType>::composites()); });
+            req_enums.extend(quote! { enums.extend(<#ty_path as ::platt::schema::HasDbType>::enums()); });
+        }
+    }
+    (req_composites, req_enums)
+}
+
+/// Checks that every variant field is `Nestable`, as they are encoded on
+/// their own, failing to compile otherwise.
+fn nestable_fields(variants: &[PlattVariant]) -> TokenStream2 {
+    let assertions = variants.iter().flat_map(|variant| &variant.fields).map(|(_, ty_path)| {
+        quote_spanned! { ty_path.span() =>
+            const _: fn() = || {
+                fn nestable<T: ::platt::value::Nestable>() { }
+                nestable::<#ty_path>();
+            };
+        }
+    });
+    quote! { #( #assertions )* }
+}
+
+/// The filter handles of the enum: `is_<variant>()` conditions and, for every
+/// variant with data, a handle on its fields.
+///
+/// `is_variant` and `variant_expr` build a `String` from `self.expr`, the SQL
+/// of the enum column; `field_expr` builds one from `self.expr` of the
+/// variant handle. The handles of a `flattened` enum hold `name`, the name
+/// of the model field its columns are named after, instead of `self.expr`.
+fn filters(
+    enum_name: &syn::Ident,
+    variants: &[PlattVariant],
+    flattened: bool,
+    is_variant: impl Fn(&PlattVariant) -> TokenStream2,
+    variant_expr: impl Fn(&PlattVariant) -> TokenStream2,
+    field_expr: impl Fn(&PlattVariant, &syn::Ident, &syn::TypePath) -> TokenStream2
+) -> TokenStream2 {
+    let filters_mod = format_ident!("{}_filters_mod", enum_name.to_string().to_snake_case());
+    let enum_filter = format_ident!("{}Filter", enum_name);
+    let handle = if flattened { format_ident!("name") } else { format_ident!("expr") };
+    let mut variant_filters = quote! { };
+    let mut variant_getters = quote! { };
+    for variant in variants {
+        let is_variant_ident = format_ident!("is_{}", variant.snake());
+        let condition = is_variant(variant);
+        variant_getters.extend(quote! {
+            pub fn #is_variant_ident(&self) -> ::platt::filter::Condition {
+                ::platt::filter::Condition::sql(#condition)
+            }
+        });
+        if variant.fields.is_empty() {
+            continue;
+        }
+        let variant_getter = format_ident!("{}", variant.snake());
+        let variant_filter = format_ident!("{}{}Filter", enum_name, variant.ident);
+        let expr = variant_expr(variant);
+        variant_getters.extend(quote! {
+            pub fn #variant_getter(&self) -> #variant_filter {
+                #variant_filter { #handle: #expr }
+            }
+        });
+        let mut field_getters = quote! { };
+        for (field_ident, ty_path) in &variant.fields {
+            let expr = field_expr(variant, field_ident, ty_path);
+            field_getters.extend(quote! {
+                pub fn #field_ident(&self) -> <#ty_path as ::platt::filter::Filterable>::Filter {
+                    <#ty_path as ::platt::filter::Filterable>::filter(#expr)
                 }
+            });
+        }
+        variant_filters.extend(quote! {
+            pub struct #variant_filter { #handle: ::std::string::String }
+            impl #variant_filter { #field_getters }
+        });
+    }
+
+    // A flattened enum is held in the column named after the field.
+    let value = if flattened { quote! { format!("\"{}\"", self.name) } } else { quote! { self.expr } };
+    let filterable = if flattened {
+        let enum_name_str = enum_name.to_string();
+        quote! {
+            fn filter(_expr: ::std::string::String) -> Self::Filter {
+                panic!("{} is stored in several columns and can only be filtered as a model field", #enum_name_str)
+            }
+
+            fn column(name: &str) -> Self::Filter {
+                #filters_mod::#enum_filter { name: name.to_string() }
             }
-            syn::Fields::Unnamed(ref _fields) => { panic!(); }
-            syn::Fields::Unit => ()
+        }
+    } else {
+        quote! {
+            fn filter(expr: ::std::string::String) -> Self::Filter {
+                #filters_mod::#enum_filter { expr }
+            }
+        }
+    };
+
+    quote! {
+        #[allow(non_snake_case)]
+        pub mod #filters_mod {
+            use super::*;
+            pub struct #enum_filter { pub(super) #handle: ::std::string::String }
+            impl #enum_filter {
+                pub fn is_null(&self) -> ::platt::filter::Condition {
+                    ::platt::filter::Condition::sql(format!("{} IS NULL", #value))
+                }
+                pub fn is_not_null(&self) -> ::platt::filter::Condition {
+                    ::platt::filter::Condition::sql(format!("{} IS NOT NULL", #value))
+                }
+                #variant_getters
+            }
+            #variant_filters
+        }
+
+        impl ::platt::filter::Filterable for #enum_name {
+            type Filter = #filters_mod::#enum_filter;
+            #filterable
+        }
+    }
+}
+
+/// A unit-only enum stored as a Postgres `ENUM` whose labels are the variant
+/// names. Labels are sent and read as text in both formats.
+fn native_enum(enum_name: &syn::Ident, variants: &[PlattVariant], schema: Option<&str>) -> TokenStream2 {
+    let enum_name_str = enum_name.to_string();
+    let enum_schema = schema_tokens(schema);
+    let enum_type = qualified_type(schema, &enum_name_str);
+    let labels: Vec<String> = variants.iter().map(|variant| variant.ident.to_string()).collect();
+    let renamed_labels = variants.iter().filter_map(|variant| {
+        let old = variant.renamed_from.as_ref()?;
+        let new = variant.ident.to_string();
+        Some(quote! { (#old.to_string(), #new.to_string()) })
+    });
+    let patterns: Vec<_> = variants.iter().map(PlattVariant::pattern).collect();
+    let filters = filters(
+        enum_name,
+        variants,
+        false,
+        |variant| {
+            let condition = format!(" = '{}'", variant.ident);
+            quote! { format!("{}{}", self.expr, #condition) }
+        },
+        |_| unreachable!(),
+        |_, _, _| unreachable!()
+    );
+
+    quote! {
+        impl ::platt::schema::HasDbType for #enum_name {
+            fn enums() -> ::std::vec::Vec<::platt::schema::EnumType> {
+                vec![::platt::schema::EnumType {
+                    name: #enum_name_str.to_string(),
+                    schema: #enum_schema,
+                    labels: vec![#( #labels.to_string() ),*],
+                    renamed_labels: vec![#( #renamed_labels ),*]
+                }]
+            }
+            fn db_type() -> ::platt::schema::DbType {
+                ::platt::schema::DbType {
+                    base: #enum_type.to_string(),
+                    nullable: false,
+                    indexed: false,
+                    primary_key: false,
+                    unique: false,
+                    default: ::std::option::Option::None,
+                    identity: false,
+                    references: ::std::option::Option::None
+                }
+            }
+        }
+
+        #filters
+
+        impl ::platt::value::ToSql for #enum_name {
+            fn to_sql(
+                &self,
+                _format: ::platt::value::Format,
+                out: &mut ::std::vec::Vec<u8>
+            ) -> ::platt::error::Result<::platt::value::IsNull> {
+                let label: &str = match self {
+                    #( #patterns => #labels, )*
+                };
+                out.extend_from_slice(label.as_bytes());
+                Ok(::platt::value::IsNull::No)
+            }
+
+            fn oid(&self) -> ::std::option::Option<u32> {
+                ::std::option::Option::None
+            }
+        }
+
+        impl ::platt::value::Nestable for #enum_name { }
+
+        impl ::platt::value::FromSql for #enum_name {
+            fn from_sql(
+                format: ::platt::value::Format,
+                raw: ::std::option::Option<&[u8]>
+            ) -> ::platt::error::Result<Self> {
+                let label = <::std::string::String as ::platt::value::FromSql>::from_sql(format, raw)?;
+                match label.as_str() {
+                    #( #labels => Ok(#patterns), )*
+                    _ => Err(::platt::error::Error::UnknownLabel {
+                        type_name: #enum_name_str,
+                        label
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// The composite holds the discriminant followed by one field per variant
+/// with data, of which only the active variant's is not NULL.
+fn composite(enum_name: &syn::Ident, variants: &[PlattVariant], schema: Option<&str>) -> TokenStream2 {
+    let (mut req_composites, req_enums) = required_types(variants);
+    let composite_schema = schema_tokens(schema);
+    for variant in variants {
+        if let Some(composite_name) = &variant.composite_name {
+            let renamed_from = match &variant.renamed_from {
+                Some(old) => {
+                    let old_name = format!("{}_{}", enum_name, old);
+                    quote! { ::std::option::Option::Some(#old_name.to_string()) }
+                }
+                None => quote! { ::std::option::Option::None }
+            };
+            let mut composite = quote! {
+                let mut composite = ::platt::schema::Composite {
+                    name: #composite_name.to_string(),
+                    schema: #composite_schema,
+                    fields: Vec::new(),
+                    renamed_from: #renamed_from,
+                    renamed_fields: Vec::new()
+                };
+            };
+            for (field_ident, ty_path) in &variant.fields {
+                let column_name = field_ident.to_string();
+                composite.extend(quote!{
+                    composite.fields.push( (#column_name.to_string(), <#ty_path as ::platt::schema::HasDbType>::db_type()) );
+                })
+            }
+            req_composites.extend(quote! {
+                {
+                    #composite
+                    composites.push(composite);
+                }
+            });
         }
     }
 
     let enum_name_str = enum_name.to_string();
     let enum_composite_name = format!("{}__Composite", enum_name);
-    let mut enum_composite = quote! { 
-        let mut composite = ::platt::schema::Composite { 
-            name: #enum_composite_name.to_string(), 
-            fields: vec![(#enum_name_str.to_string(), <u32 as ::platt::schema::HasDbType>::db_type())]
-        }; 
+    let enum_composite_type = qualified_type(schema, &enum_composite_name);
+    let mut enum_composite = quote! {
+        let mut composite = ::platt::schema::Composite {
+            name: #enum_composite_name.to_string(),
+            schema: #composite_schema,
+            fields: vec![(#enum_name_str.to_string(), <u32 as ::platt::schema::HasDbType>::db_type())],
+            renamed_from: ::std::option::Option::None,
+            renamed_fields: Vec::new()
+        };
     };
-    for (variant_name, composite_name) in composites {
-        enum_composite.extend(quote![
-            composite.fields.push( (#variant_name.to_string(), ::platt::schema::DbType {
-                base: #composite_name.to_string(),
-                nullable: false,
-                indexed: false,
-                primary_key: false,
-                unique: false
-            })
-         );
-        ]);
-    }
-
-    let result = quote::quote! {
+    for variant in variants {
+        if let Some(composite_name) = &variant.composite_name {
+            let variant_name = variant.ident.to_string();
+            let variant_type = qualified_type(schema, composite_name);
+            if let Some(old) = &variant.renamed_from {
+                enum_composite.extend(quote! {
+                    composite.renamed_fields.push((#old.to_string(), #variant_name.to_string()));
+                });
+            }
+            enum_composite.extend(quote![
+                composite.fields.push( (#variant_name.to_string(), ::platt::schema::DbType {
+                    base: #variant_type.to_string(),
+                    nullable: true,
+                    indexed: false,
+                    primary_key: false,
+                    unique: false,
+                    default: ::std::option::Option::None,
+                    identity: false,
+                    references: ::std::option::Option::None
+                })
+             );
+            ]);
+        }
+    }
+
+    let mut encode_arms = quote! { };
+    for variant in variants {
+        let discriminant = variant.discriminant;
+        let field_idents: Vec<_> = variant.fields.iter().map(|(ident, _)| ident).collect();
+        let pattern = variant.pattern();
+        let mut body = quote! { record.field(&#discriminant)?; };
+        for other in variants {
+            if other.composite_name.is_none() {
+                continue;
+            }
+            if other.ident == variant.ident {
+                body.extend(quote! {
+                    record.nested(|record| {
+                        #( record.field(#field_idents)?; )*
+                        Ok(())
+                    })?;
+                });
+            } else {
+                body.extend(quote! { record.null()?; });
+            }
+        }
+        encode_arms.extend(quote! { #pattern => { #body } });
+    }
+
+    let mut decode_fields = quote! { };
+    let mut decode_arms = quote! { };
+    for variant in variants {
+        let discriminant = variant.discriminant;
+        if variant.composite_name.is_none() {
+            let decoded = variant.construct(&[]);
+            decode_arms.extend(quote! { #discriminant => Ok(#decoded), });
+            continue;
+        }
+        let raw_ident = format_ident!("{}_raw", variant.snake());
+        decode_fields.extend(quote! { let #raw_ident = record.raw_field()?; });
+        let values: Vec<_> = variant.fields.iter().map(|_| quote! { record.field()? }).collect();
+        let decoded = variant.construct(&values);
+        decode_arms.extend(quote! {
+            #discriminant => {
+                let mut record = ::platt::value::record::RecordDecoder::new(
+                    format,
+                    ::platt::value::non_null(#raw_ident.as_deref())?
+                )?;
+                Ok(#decoded)
+            }
+        });
+    }
+
+    // Variants are matched on the discriminant field of the composite, and
+    // the fields of a variant are reached through its nested composite.
+    let discriminant_field = format!("\"{}\"", enum_name_str);
+    let filters = filters(
+        enum_name,
+        variants,
+        false,
+        |variant| {
+            let discriminant = variant.discriminant;
+            quote! { format!("({}).{} = {}", self.expr, #discriminant_field, #discriminant) }
+        },
+        |variant| {
+            let variant_field = format!("\"{}\"", variant.ident);
+            quote! { format!("({}).{}", self.expr, #variant_field) }
+        },
+        |_, field_ident, _| {
+            let field = format!("\"{}\"", field_ident);
+            quote! { format!("({}).{}", self.expr, #field) }
+        }
+    );
+    let nestable_fields = nestable_fields(variants);
+
+    quote! {
+        #nestable_fields
+
         impl ::platt::schema::HasDbType for #enum_name {
             fn composites() -> ::std::vec::Vec<::platt::schema::Composite> {
                 #req_composites
@@ -82,16 +553,392 @@ pub fn inner(model: TokenStream) -> TokenStream {
                 }
                 composites
             }
+            fn enums() -> ::std::vec::Vec<::platt::schema::EnumType> {
+                #req_enums
+                enums
+            }
             fn db_type() -> ::platt::schema::DbType {
                 ::platt::schema::DbType {
-                    base: #enum_composite_name.to_string(),
+                    base: #enum_composite_type.to_string(),
                     nullable: false,
                     indexed: false,
                     primary_key: false,
-                    unique: false
+                    unique: false,
+                    default: ::std::option::Option::None,
+                    identity: false,
+                    references: ::std::option::Option::None
                 }
             }
         }
+
+        #filters
+
+        impl ::platt::value::ToSql for #enum_name {
+            fn to_sql(
+                &self,
+                format: ::platt::value::Format,
+                out: &mut ::std::vec::Vec<u8>
+            ) -> ::platt::error::Result<::platt::value::IsNull> {
+                let mut record = ::platt::value::record::RecordEncoder::new(format, out);
+                match self {
+                    #encode_arms
+                }
+                record.finish()
+            }
+
+            fn oid(&self) -> ::std::option::Option<u32> {
+                ::std::option::Option::None
+            }
+        }
+
+        impl ::platt::value::Nestable for #enum_name { }
+
+        impl ::platt::value::FromSql for #enum_name {
+            fn from_sql(
+                format: ::platt::value::Format,
+                raw: ::std::option::Option<&[u8]>
+            ) -> ::platt::error::Result<Self> {
+                let mut record = ::platt::value::record::RecordDecoder::new(format, ::platt::value::non_null(raw)?)?;
+                let discriminant: u32 = record.field()?;
+                #decode_fields
+                match discriminant {
+                    #decode_arms
+                    discriminant => Err(::platt::error::Error::UnknownDiscriminant {
+                        type_name: #enum_name_str,
+                        discriminant
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// The `tag` of a `#[serde(tag = "...")]` attribute, if the enum is
+/// internally tagged.
+fn serde_tag(attrs: &[syn::Attribute]) -> Option<String> {
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested {
+                    if let (true, syn::Lit::Str(tag)) = (name_value.path.is_ident("tag"), &name_value.lit) {
+                        return Some(tag.value());
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// An enum stored as `JSONB` through its serde impls, which are expected to
+/// use the variant names and either serde's default external tagging or
+/// `#[serde(tag = "...")]`. Variant fields are cast back to their column
+/// type when filtered on.
+fn jsonb(enum_name: &syn::Ident, variants: &[PlattVariant], tag: Option<String>) -> TokenStream2 {
+    let is_variant = |variant: &PlattVariant| {
+        let condition = match &tag {
+            Some(tag) => format!(" ->> '{}' = '{}'", tag, variant.ident),
+            None => format!(" ? '{}'", variant.ident)
+        };
+        quote! { format!("({}){}", self.expr, #condition) }
     };
-    result.into()
-}
\ No newline at end of file
+    let variant_expr = |variant: &PlattVariant| match &tag {
+        Some(_) => quote! { self.expr.clone() },
+        None => {
+            let key = format!(" -> '{}'", variant.ident);
+            quote! { format!("({}){}", self.expr, #key) }
+        }
+    };
+    if tag.is_some() && variants.iter().any(|variant| variant.style == VariantStyle::Tuple) {
+        panic!("Internally tagged PlattEnums stored as JSONB can not have tuple variants.");
+    }
+    // serde writes a newtype variant as its value and other tuple variants as
+    // an array.
+    let field_expr = |variant: &PlattVariant, field_ident: &syn::Ident, ty_path: &syn::TypePath| {
+        let key = match variant.style {
+            VariantStyle::Tuple if variant.fields.len() == 1 => " #>> '{}'".to_string(),
+            VariantStyle::Tuple => format!(" ->> {}", field_ident.to_string().trim_start_matches('_')),
+            _ => format!(" ->> '{}'", field_ident)
+        };
+        quote! {
+            format!(
+                "(({}){})::{}",
+                self.expr,
+                #key,
+                <#ty_path as ::platt::schema::HasDbType>::db_type().base
+            )
+        }
+    };
+    let filters = filters(enum_name, variants, false, is_variant, variant_expr, field_expr);
+
+    quote! {
+        impl ::platt::schema::HasDbType for #enum_name {
+            fn db_type() -> ::platt::schema::DbType {
+                ::platt::schema::DbType {
+                    base: "JSONB".to_string(),
+                    nullable: false,
+                    indexed: false,
+                    primary_key: false,
+                    unique: false,
+                    default: ::std::option::Option::None,
+                    identity: false,
+                    references: ::std::option::Option::None
+                }
+            }
+        }
+
+        #filters
+
+        impl ::platt::value::ToSql for #enum_name {
+            fn to_sql(
+                &self,
+                format: ::platt::value::Format,
+                out: &mut ::std::vec::Vec<u8>
+            ) -> ::platt::error::Result<::platt::value::IsNull> {
+                ::platt::value::jsonb::to_sql(self, format, out)
+            }
+
+            fn type_oid() -> ::std::option::Option<u32> {
+                ::std::option::Option::Some(::platt::value::oid::JSONB)
+            }
+
+            fn oid(&self) -> ::std::option::Option<u32> {
+                <Self as ::platt::value::ToSql>::type_oid()
+            }
+        }
+
+        impl ::platt::value::Nestable for #enum_name { }
+
+        impl ::platt::value::FromSql for #enum_name {
+            fn from_sql(
+                format: ::platt::value::Format,
+                raw: ::std::option::Option<&[u8]>
+            ) -> ::platt::error::Result<Self> {
+                ::platt::value::jsonb::from_sql(format, raw)
+            }
+        }
+    }
+}
+
+/// An enum flattened into the table of the model holding it: a `TEXT` column
+/// named after the field holds the variant name, followed by a nullable
+/// `<field>_<variant>_<variant field>` column per variant field. A `CHECK`
+/// constraint keeps the columns of every other variant `NULL`.
+///
+/// It can only be used directly as a model field, which is read through
+/// `FromSql::from_row` and written through `ToSql::to_columns`; it has no
+/// single-value encoding, so it is not `Nestable` and arrays, composites and
+/// enum variants can not hold it.
+fn columns(enum_name: &syn::Ident, variants: &[PlattVariant]) -> TokenStream2 {
+    let enum_name_str = enum_name.to_string();
+    let (req_composites, req_enums) = required_types(variants);
+
+    let mut columns = quote! { };
+    let mut decode_arms = quote! { };
+    let mut check_arms = quote! { };
+    for variant in variants {
+        let label = variant.ident.to_string();
+        let mut values = Vec::new();
+        for (field_ident, ty_path) in &variant.fields {
+            let suffix = variant.column_suffix(field_ident);
+            columns.extend(quote! {
+                let mut db_type = <#ty_path as ::platt::schema::HasDbType>::db_type();
+                db_type.nullable = true;
+                columns.push(::platt::schema::Column::new(format!("{}{}", name, #suffix), db_type));
+            });
+            values.push(quote! {
+                <#ty_path as ::platt::value::FromSql>::from_row(row, &format!("{}{}", column, #suffix))?
+            });
+        }
+        let decoded = variant.construct(&values);
+        decode_arms.extend(quote! { #label => Ok(#decoded), });
+
+        // The active variant's columns must be set unless they are nullable,
+        // and the columns of every other variant must be NULL.
+        let mut conditions = Vec::new();
+        for (field_ident, ty_path) in &variant.fields {
+            let suffix = variant.column_suffix(field_ident);
+            conditions.push(quote! {
+                if !<#ty_path as ::platt::schema::HasDbType>::db_type().nullable {
+                    conditions.push(format!("\"{}{}\" IS NOT NULL", name, #suffix));
+                }
+            });
+        }
+        for other in variants.iter().filter(|other| other.ident != variant.ident) {
+            for (field_ident, _) in &other.fields {
+                let suffix = other.column_suffix(field_ident);
+                conditions.push(quote! {
+                    conditions.push(format!("\"{}{}\" IS NULL", name, #suffix));
+                });
+            }
+        }
+        check_arms.extend(quote! {
+            {
+                let mut conditions: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #( #conditions )*
+                if conditions.is_empty() {
+                    conditions.push("TRUE".to_string());
+                }
+                arms.push(format!("WHEN '{}' THEN {}", #label, conditions.join(" AND ")));
+            }
+        });
+    }
+    let all_columns: Vec<String> = variants.iter()
+        .flat_map(|variant| variant.fields.iter().map(move |(field_ident, _)| variant.column_suffix(field_ident)))
+        .collect();
+
+    // The variant's fields are bound by name, so the parameters are named
+    // so as not to clash with them.
+    let mut encode_arms = quote! { };
+    let mut null_columns = quote! { };
+    for variant in variants {
+        let label = variant.ident.to_string();
+        let pattern = variant.pattern();
+        let mut body = quote! {
+            ::platt::value::ToSql::to_columns(&#label.to_string(), __column, __format, __out)?;
+        };
+        for other in variants {
+            for (field_ident, ty_path) in &other.fields {
+                let suffix = other.column_suffix(field_ident);
+                body.extend(if other.ident == variant.ident {
+                    quote! {
+                        ::platt::value::ToSql::to_columns(#field_ident, &format!("{}{}", __column, #suffix), __format, __out)?;
+                    }
+                } else {
+                    quote! {
+                        <#ty_path as ::platt::value::ToSql>::null_columns(&format!("{}{}", __column, #suffix), __out);
+                    }
+                });
+            }
+        }
+        encode_arms.extend(quote! { #pattern => { #body } });
+        for (field_ident, ty_path) in &variant.fields {
+            let suffix = variant.column_suffix(field_ident);
+            null_columns.extend(quote! {
+                <#ty_path as ::platt::value::ToSql>::null_columns(&format!("{}{}", __column, #suffix), __out);
+            });
+        }
+    }
+
+    let filters = filters(
+        enum_name,
+        variants,
+        true,
+        |variant| {
+            let condition = format!(" = '{}'", variant.ident);
+            quote! { format!("\"{}\"{}", self.name, #condition) }
+        },
+        |_| quote! { self.name.clone() },
+        |variant, field_ident, _| {
+            let suffix = variant.column_suffix(field_ident);
+            quote! { format!("\"{}{}\"", self.name, #suffix) }
+        }
+    );
+    let nestable_fields = nestable_fields(variants);
+
+    quote! {
+        #nestable_fields
+
+        impl ::platt::schema::HasDbType for #enum_name {
+            fn composites() -> ::std::vec::Vec<::platt::schema::Composite> {
+                #req_composites
+                composites
+            }
+            fn enums() -> ::std::vec::Vec<::platt::schema::EnumType> {
+                #req_enums
+                enums
+            }
+            fn db_type() -> ::platt::schema::DbType {
+                ::platt::schema::DbType {
+                    base: "TEXT".to_string(),
+                    nullable: false,
+                    indexed: false,
+                    primary_key: false,
+                    unique: false,
+                    default: ::std::option::Option::None,
+                    identity: false,
+                    references: ::std::option::Option::None
+                }
+            }
+            fn columns(name: &str) -> ::std::vec::Vec<::platt::schema::Column> {
+                let mut columns = vec![::platt::schema::Column::new(
+                    name.to_string(),
+                    <Self as ::platt::schema::HasDbType>::db_type()
+                )];
+                #columns
+                columns
+            }
+            fn check(name: &str) -> ::std::option::Option<::std::string::String> {
+                let mut arms: ::std::vec::Vec<::std::string::String> = ::std::vec::Vec::new();
+                #check_arms
+                let mut unset = vec![format!("\"{}\" IS NULL", name)];
+                #( unset.push(format!("\"{}{}\" IS NULL", name, #all_columns)); )*
+                ::std::option::Option::Some(format!(
+                    "CASE \"{}\" {} ELSE {} END",
+                    name,
+                    arms.join(" "),
+                    unset.join(" AND ")
+                ))
+            }
+        }
+
+        #filters
+
+        impl ::platt::value::ToSql for #enum_name {
+            fn to_sql(
+                &self,
+                _format: ::platt::value::Format,
+                _out: &mut ::std::vec::Vec<u8>
+            ) -> ::platt::error::Result<::platt::value::IsNull> {
+                Err(::platt::error::Error::Encode(format!(
+                    "{} is stored in several columns and can only be written through to_columns",
+                    #enum_name_str
+                )))
+            }
+
+            fn oid(&self) -> ::std::option::Option<u32> {
+                ::std::option::Option::None
+            }
+
+            fn to_columns(
+                &self,
+                __column: &str,
+                __format: ::platt::value::Format,
+                __out: &mut ::std::vec::Vec<::platt::value::ColumnValue>
+            ) -> ::platt::error::Result<()> {
+                match self {
+                    #encode_arms
+                }
+                Ok(())
+            }
+
+            fn null_columns(__column: &str, __out: &mut ::std::vec::Vec<::platt::value::ColumnValue>) {
+                <::std::string::String as ::platt::value::ToSql>::null_columns(__column, __out);
+                #null_columns
+            }
+        }
+
+        impl ::platt::value::FromSql for #enum_name {
+            fn from_sql(
+                _format: ::platt::value::Format,
+                _raw: ::std::option::Option<&[u8]>
+            ) -> ::platt::error::Result<Self> {
+                Err(::platt::error::Error::Decode(format!(
+                    "{} is stored in several columns and can only be read from a row",
+                    #enum_name_str
+                )))
+            }
+
+            fn from_row(row: &::platt::db::Row, column: &str) -> ::platt::error::Result<Self> {
+                let label = <::std::string::String as ::platt::value::FromSql>::from_row(row, column)?;
+                match label.as_str() {
+                    #decode_arms
+                    _ => Err(::platt::error::Error::UnknownLabel {
+                        type_name: #enum_name_str,
+                        label
+                    })
+                }
+            }
+        }
+    }
+}
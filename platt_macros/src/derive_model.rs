@@ -1,23 +1,33 @@
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use darling::{FromDeriveInput, FromField};
-use quote::{format_ident, quote};
-use syn::{parse_macro_input, DeriveInput, punctuated::Punctuated};
-use heck::{CamelCase, SnakeCase};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, DeriveInput, punctuated::Punctuated, spanned::Spanned};
+use heck::SnakeCase;
+use crate::model_attrs;
 
 #[derive(Debug, Clone, FromDeriveInput)]
 #[darling(
     attributes(platt),
-    forward_attrs(doc, cfg, allow),
     supports(struct_named)
 )]
 pub struct PlattModel {
     ident: syn::Ident,
-    attrs: Vec<syn::Attribute>,
-    vis: syn::Visibility,
-    generics: syn::Generics,
     data: darling::ast::Data<darling::util::Ignored, PlattField>,
     #[darling(default)]
-    not_clonable: Option<()>
+    not_clonable: Option<()>,
+    #[darling(default)]
+    database: Option<String>,
+    #[darling(default)]
+    renamed_from: Option<String>,
+    /// The name of the table, in place of the one the naming convention
+    /// gives the model.
+    #[darling(default)]
+    table: Option<String>,
+    /// The Postgres schema (namespace) the table lives in, `public` when
+    /// unset.
+    #[darling(default)]
+    schema: Option<String>
 }
 
 #[derive(Debug, Clone, FromField)]
@@ -25,16 +35,126 @@ pub struct PlattModel {
 struct PlattField {
     ident: Option<syn::Ident>,
     ty: syn::Type,
+    /// The name of the relation back from the model a `ForeignKey` field
+    /// points to.
+    #[darling(default)]
+    reverse: Option<String>,
+    #[darling(default)]
+    renamed_from: Option<String>,
+    /// The name of the column, when it differs from that of the field.
+    #[darling(default)]
+    column: Option<String>,
+    /// Makes the field the primary key of the model, or part of it when
+    /// several fields are marked, in place of the `id` models get.
+    #[darling(default)]
+    primary_key: Option<()>,
+    /// Has the database number the column, as `GENERATED BY DEFAULT AS
+    /// IDENTITY`.
+    #[darling(default)]
+    identity: Option<()>,
     #[darling(default)]
-    reverse: Option<String>
+    unique: Option<()>,
+    #[darling(default)]
+    index: Option<()>,
+    /// The SQL type of the column, in place of the one of the field type.
+    #[darling(default)]
+    db_type: Option<String>,
+    /// An SQL expression the column defaults to, e.g. `now()`.
+    #[darling(default)]
+    server_default: Option<String>,
+    /// Makes a text column a `VARCHAR(max_length)` one.
+    #[darling(default)]
+    max_length: Option<usize>,
+    /// What a `ForeignKey` field does when the row it points to is deleted:
+    /// `"cascade"`, `"set_null"`, `"set_default"`, `"restrict"` or
+    /// `"no_action"`.
+    #[darling(default)]
+    on_delete: Option<String>,
+    /// The same as `on_delete`, for when the key of the row changes.
+    #[darling(default)]
+    on_update: Option<String>,
+    /// Makes the foreign key `DEFERRABLE INITIALLY DEFERRED`.
+    #[darling(default)]
+    deferrable: Option<()>
  }
 
+/// The table name of the model `model` when it sets none itself: the name
+/// of the struct, in snake_case with the `snake_case_tables` feature and in
+/// the plural with `plural_tables`.
+fn conventional_table_name(model: &str) -> String {
+    let name = if cfg!(feature = "snake_case_tables") {
+        model.to_snake_case()
+    } else {
+        model.to_string()
+    };
+    if cfg!(feature = "plural_tables") {
+        pluralize(&name)
+    } else {
+        name
+    }
+}
+
+/// `name` in the plural the way most English nouns take it, e.g. `posts`,
+/// `boxes` and `categories`.
+fn pluralize(name: &str) -> String {
+    let lower = name.to_lowercase();
+    if ["s", "x", "z", "ch", "sh"].iter().any(|ending| lower.ends_with(ending)) {
+        format!("{}es", name)
+    } else if lower.ends_with('y') && !lower[..lower.len() - 1].ends_with(&['a', 'e', 'i', 'o', 'u'][..]) {
+        format!("{}ies", &name[..name.len() - 1])
+    } else {
+        format!("{}s", name)
+    }
+}
+
+/// Whether `DbType::limit_length` accepts `base`: `TEXT` or `VARCHAR(n)`,
+/// or an array of either.
+fn is_text_type(base: &str) -> bool {
+    let element = base.trim_end_matches(|c: char| c == '[' || c == ']' || c.is_ascii_digit());
+    element == "TEXT" || (element.starts_with("VARCHAR(") && element.ends_with(')'))
+}
+
+/// The `ReferentialAction` spelled as `action`, or `None` for `NO ACTION`.
+fn referential_action(field: &syn::Ident, ty_path: &syn::TypePath, action: &str, assertions: &mut TokenStream2) -> TokenStream2 {
+    let variant = match action.to_lowercase().replace(' ', "_").as_str() {
+        "cascade" => quote! { Cascade },
+        "set_null" => {
+            assertions.extend(quote_spanned! { ty_path.span() =>
+                const _: fn() = || {
+                    fn set_null<T: ::platt::schema::NullableForeignKey>() { }
+                    set_null::<#ty_path>();
+                };
+            });
+            quote! { SetNull }
+        }
+        "set_default" => quote! { SetDefault },
+        "restrict" => quote! { Restrict },
+        "no_action" => return quote! { ::std::option::Option::None },
+        other => panic!(
+            "Unknown referential action \"{}\" on {}, expected cascade, set_null, set_default, restrict or no_action.",
+            other, field
+        )
+    };
+    quote! { ::std::option::Option::Some(::platt::schema::ReferentialAction::#variant) }
+}
+
 pub fn inner(model: TokenStream) -> TokenStream {
-    let model = parse_macro_input!(model as DeriveInput);
+    let mut model = parse_macro_input!(model as DeriveInput);
+    let index_attrs = model_attrs::take_indexes(&mut model.attrs);
+    let unique_attrs = model_attrs::take_uniques(&mut model.attrs);
+    let check_attrs = model_attrs::take_checks(&mut model.attrs);
+    let mut defaults = Vec::new();
+    if let syn::Data::Struct(data) = &mut model.data {
+        for field in data.fields.iter_mut() {
+            defaults.push(model_attrs::take_default(&mut field.attrs));
+        }
+    }
     let platt_model = PlattModel::from_derive_input(&model).unwrap();
     let model_name = platt_model.ident;
     let model_name_str = model_name.to_string();
     let model_name_snake = model_name_str.to_snake_case();
+    let table_name_str = platt_model.table.clone()
+        .unwrap_or_else(|| conventional_table_name(&model_name_str));
     let filters_mod = format_ident!("{}_filters_mod", model_name_snake);
     let updates_mod = format_ident!("{}_updates_mod", model_name_snake);
     let data_mod = format_ident!("{}_data_mod", model_name_snake);
@@ -43,19 +163,22 @@ pub fn inner(model: TokenStream) -> TokenStream {
     let data_name_struct = format_ident!("{}Data", model_name);
 
     let mut req_composites = quote! { let mut composites = ::std::vec::Vec::new();  };
+    let mut req_enums = quote! { let mut enums = ::std::vec::Vec::new(); };
     let mut tables = quote!{ let mut tables = ::std::vec::Vec::new(); };
     let mut fields = quote!{ 
         let mut fields = ::std::vec::Vec::new(); 
-        fields.push(::platt::schema::Column::new("id".to_string(), ::platt::schema::primary_key()));
+        let mut checks = ::std::vec::Vec::new();
+        let mut foreign_keys = ::std::vec::Vec::new();
     };
-    let mut filter_structs = quote! { };
+    let mut key_columns = quote! { let mut key = ::std::vec::Vec::new(); };
+    let mut key_types = Vec::new();
     let mut filter_struct_getters = quote! { };
-    let mut filter_struct_fields = syn::FieldsNamed {
-        brace_token: syn::token::Brace::default(),
-        named: Punctuated::default()
-    };
     let mut update_struct_fields = quote! { };
     let mut data_struct_fields = quote! { };
+    let mut data_from_row = quote! { };
+    let mut data_from_model = quote! { };
+    // Checks on the field types, which fail to compile when they do not hold.
+    let mut assertions = quote! { };
 
     if model.generics.lt_token.is_some() {
         panic!("Platt does not support models with generics. Perhaps use an enum instead.")
@@ -70,51 +193,157 @@ pub fn inner(model: TokenStream) -> TokenStream {
         panic!("Platt models are only currently enabled for structs with named fields.");
     }
     
-    for p_field in &struct_data.fields {
-        let column_name = format!("{}", p_field.ident.as_ref().unwrap());
+    let key_fields = struct_data.fields.iter().filter(|field| field.primary_key.is_some()).count();
+    if key_fields == 0 {
+        fields.extend(quote! { fields.push(::platt::schema::Column::new("id".to_string(), ::platt::schema::primary_key())); });
+        key_columns.extend(quote! { key.push(::platt::schema::Column::new("id".to_string(), ::platt::schema::primary_key())); });
+    }
+    for (p_field, default) in struct_data.fields.iter().zip(defaults) {
+        let column_name = p_field.column.clone().unwrap_or_else(|| p_field.ident.as_ref().unwrap().to_string());
         let column_name_ident = format_ident!("{}", p_field.ident.as_ref().unwrap());
-        let column_name_type_ident = format_ident!("{}", column_name_ident.to_string().to_camel_case());
-        if column_name == "id" {
-            panic!("Platt uses a column name of 'id' internally. Please use a different name.")
+        if column_name == "id" && key_fields == 0 {
+            panic!("Platt uses a column name of 'id' internally, unless fields are marked primary_key. Please use a different name.")
         }
+        let default = match (default, &p_field.server_default) {
+            (Some(_), Some(_)) => panic!("{} has both a default and a server_default.", column_name_ident),
+            (default, server_default) => default.or_else(|| server_default.clone())
+        };
         match &p_field.ty {
             // syn::Type::Array(ty_array) =>  { }
             syn::Type::Path(ref ty_path) =>  { 
                 req_composites.extend(quote! { composites.extend(<#ty_path as ::platt::schema::HasDbType>::composites()); });
+                req_enums.extend(quote! { enums.extend(<#ty_path as ::platt::schema::HasDbType>::enums()); });
                 tables.extend(quote! {
                     tables.extend(<#ty_path as ::platt::schema::HasDbType>::tables());
                 });
-                fields.extend(quote! {
-                    fields.push(::platt::schema::Column::new(#column_name.to_string(), <#ty_path as ::platt::schema::HasDbType>::db_type()));
-                });
-                filter_structs.extend(quote! {
-                    pub struct #column_name_type_ident(::platt::query::FilterState<#ty_path>);
-                    impl ::platt::query::GetFilterState<#ty_path> for #column_name_type_ident {
-                        fn get(&mut self) -> &mut ::platt::query::FilterState<#ty_path> { &mut self.0 }
+                let check_name = format!("{}_{}_check", table_name_str, column_name);
+                let columns = match &p_field.renamed_from {
+                    Some(old) => quote! {
+                        <#ty_path as ::platt::schema::HasDbType>::columns(#column_name).into_iter()
+                            .zip(<#ty_path as ::platt::schema::HasDbType>::columns(#old))
+                            .map(|(column, old)| {
+                                let renamed_from = old.name().to_string();
+                                column.with_renamed_from(::std::option::Option::Some(renamed_from))
+                            })
+                    },
+                    None => quote! { <#ty_path as ::platt::schema::HasDbType>::columns(#column_name) }
+                };
+                let mut options = quote! { };
+                if p_field.primary_key.is_some() {
+                    if matches!(ty_path.path.segments.last(), Some(segment) if segment.ident == "Option") {
+                        panic!("{} is part of the primary key, so it can not be an Option.", column_name_ident);
                     }
-                    impl ::platt::query::Filters<#ty_path> for #column_name_type_ident { }
-                });
-                let mut filter_field_path = Punctuated::new();
-                filter_field_path.push(syn::PathSegment {
-                    ident: column_name_type_ident.clone(),
-                    arguments: syn::PathArguments::None
-                });
-                filter_struct_fields.named.push(syn::Field {
-                    attrs: Vec::new(),
-                    vis: syn::Visibility::Inherited,
-                    ident: Some(column_name_ident.clone()),
-                    colon_token: Some(<syn::Token![:]>::default()),
-                    ty: syn::TypePath {
-                        qself: None,
-                        path: syn::Path {
-                            leading_colon: None,
-                            segments: filter_field_path
+                    // Postgres makes a key of a single column unique.
+                    let unique = key_fields == 1;
+                    options.extend(quote! {
+                        db_type.primary_key = true;
+                        db_type.unique = #unique;
+                    });
+                }
+                if p_field.identity.is_some() {
+                    options.extend(quote! { db_type.identity = true; });
+                }
+                if p_field.unique.is_some() {
+                    options.extend(quote! { db_type.unique = true; });
+                }
+                if p_field.index.is_some() {
+                    options.extend(quote! { db_type.indexed = true; });
+                }
+                if let Some(base) = &p_field.db_type {
+                    options.extend(quote! { db_type.base = #base.to_string(); });
+                }
+                if let Some(max_length) = p_field.max_length {
+                    match &p_field.db_type {
+                        Some(base) if !is_text_type(base) => panic!(
+                            "max_length only applies to text columns, not the {} of {}.",
+                            base, column_name_ident
+                        ),
+                        Some(_) => (),
+                        None => assertions.extend(quote_spanned! { ty_path.span() =>
+                            const _: fn() = || {
+                                fn max_length<T: ::platt::schema::TextType>() { }
+                                max_length::<#ty_path>();
+                            };
+                        })
+                    }
+                    options.extend(quote! { db_type.limit_length(#max_length); });
+                }
+                if let Some(default) = &default {
+                    options.extend(quote! { db_type.default = ::std::option::Option::Some(#default.to_string()); });
+                }
+                // Applies to the reference of the column, or to that of the
+                // foreign key over its columns when it points to a composite
+                // key.
+                let mut reference_options = quote! { };
+                if let Some(action) = &p_field.on_delete {
+                    let action = referential_action(&column_name_ident, ty_path, action, &mut assertions);
+                    reference_options.extend(quote! { references.on_delete = #action; });
+                }
+                if let Some(action) = &p_field.on_update {
+                    let action = referential_action(&column_name_ident, ty_path, action, &mut assertions);
+                    reference_options.extend(quote! { references.on_update = #action; });
+                }
+                if p_field.deferrable.is_some() {
+                    reference_options.extend(quote! { references.deferrable = true; });
+                }
+                if !reference_options.is_empty() || p_field.reverse.is_some() {
+                    assertions.extend(quote_spanned! { ty_path.span() =>
+                        const _: fn() = || {
+                            fn foreign_key<T: ::platt::schema::ForeignKeyType>() { }
+                            foreign_key::<#ty_path>();
+                        };
+                    });
+                }
+                if !reference_options.is_empty() {
+                    options.extend(quote! {
+                        if let ::std::option::Option::Some(references) = db_type.references.as_mut() {
+                            #reference_options
                         }
-                    }.into()
+                    });
+                }
+                let columns = if options.is_empty() {
+                    columns
+                } else {
+                    quote! {
+                        #columns.into_iter().map(|mut column| {
+                            let db_type = column.db_type_mut();
+                            #options
+                            column
+                        })
+                    }
+                };
+                let references = if reference_options.is_empty() { quote! { references } } else { quote! { mut references } };
+                if p_field.primary_key.is_some() {
+                    key_columns.extend(quote! { key.extend(#columns); });
+                    key_types.push(ty_path.clone());
+                }
+                let field_name = p_field.ident.as_ref().unwrap().to_string();
+                // A column given another type by db_type no longer has the
+                // problems of the field type.
+                let problems = if p_field.db_type.is_some() {
+                    quote! { ::std::vec::Vec::new() }
+                } else {
+                    quote! { <#ty_path as ::platt::schema::HasDbType>::problems() }
+                };
+                fields.extend(quote! {
+                    fields.extend(#columns.into_iter().map(|column| {
+                        column.with_field(::std::option::Option::Some(#field_name.to_string()))
+                            .with_problems(#problems)
+                    }));
+                    foreign_keys.extend(
+                        <#ty_path as ::platt::schema::HasDbType>::foreign_keys(#column_name).into_iter()
+                            .map(|(columns, #references)| {
+                                #reference_options
+                                ::platt::schema::ForeignKeyConstraint::new(#table_name_str, columns, references)
+                            })
+                    );
+                    checks.extend(<#ty_path as ::platt::schema::HasDbType>::check(#column_name).map(|expr| {
+                        ::platt::schema::Check { name: #check_name.to_string(), expr }
+                    }));
                 });
                 filter_struct_getters.extend(quote!{
-                    pub fn #column_name_ident(&mut self) -> &mut #column_name_type_ident {
-                        &mut self.#column_name_ident
+                    pub fn #column_name_ident(&self) -> <#ty_path as ::platt::filter::Filterable>::Filter {
+                        <#ty_path as ::platt::filter::Filterable>::column(#column_name)
                     }
                 });
                 update_struct_fields.extend(quote!{
@@ -122,12 +351,84 @@ pub fn inner(model: TokenStream) -> TokenStream {
                 });
                 data_struct_fields.extend(quote!{
                     pub #column_name_ident: ::platt::query::TrackingMut<#ty_path>,
-                })
+                });
+                data_from_model.extend(quote! {
+                    #column_name_ident: ::platt::query::TrackingMut::new(model.#column_name_ident),
+                });
+                data_from_row.extend(quote!{
+                    #column_name_ident: ::platt::query::TrackingMut::new(
+                        <#ty_path as ::platt::value::FromSql>::from_row(row, #column_name)?
+                    ),
+                });
             }
             _ => panic!("Platt models can only contain type paths.")
         }
     }
 
+    let column_of = |field_name: &str| {
+        let field = struct_data.fields.iter().find(|field| field.ident.as_ref().unwrap() == field_name);
+        match field {
+            Some(field) => field.column.clone().unwrap_or_else(|| field_name.to_string()),
+            None => panic!("\"{}\" is not a field of {}.", field_name, model_name_str)
+        }
+    };
+    let mut indexes = quote! { let mut indexes = ::std::vec::Vec::new(); };
+    for index in &index_attrs {
+        let keys: Vec<String> = index.fields.iter().map(|key| {
+            if key.chars().all(|c| c.is_alphanumeric() || c == '_') { column_of(key) } else { key.clone() }
+        }).collect();
+        let unique = index.unique;
+        let option = |value: &Option<String>| match value {
+            Some(value) => quote! { ::std::option::Option::Some(#value.to_string()) },
+            None => quote! { ::std::option::Option::None }
+        };
+        let using = option(&index.using);
+        let predicate = option(&index.predicate);
+        let name = match &index.name {
+            Some(name) => quote! { name: #name.to_string(), },
+            None => quote! { }
+        };
+        indexes.extend(quote! {
+            indexes.push(::platt::schema::Index {
+                #name
+                unique: #unique,
+                using: #using,
+                predicate: #predicate,
+                ..::platt::schema::Index::new(#table_name_str, vec![#( #keys.to_string() ),*])
+            });
+        });
+    }
+
+    // A constraint on a single column is kept on the column, as the schema
+    // read back from the database has it.
+    let mut constraints = quote! { let mut uniques = ::std::vec::Vec::new(); };
+    for unique in &unique_attrs {
+        let columns: Vec<String> = unique.fields.iter().map(|field| column_of(field)).collect();
+        let default_name = format!("{}_{}_key", table_name_str, columns.join("_"));
+        match &unique.name {
+            Some(name) if *name != default_name => constraints.extend(quote! {
+                uniques.push(::platt::schema::Unique { name: #name.to_string(), columns: vec![#( #columns.to_string() ),*] });
+            }),
+            _ if columns.len() == 1 => {
+                let column = &columns[0];
+                constraints.extend(quote! {
+                    for column in fields.iter_mut().filter(|column| column.name() == #column) {
+                        column.db_type_mut().unique = true;
+                    }
+                });
+            }
+            _ => constraints.extend(quote! {
+                uniques.push(::platt::schema::Unique::new(#table_name_str, vec![#( #columns.to_string() ),*]));
+            })
+        }
+    }
+    for check in &check_attrs {
+        let (name, expr) = (&check.name, &check.expr);
+        constraints.extend(quote! {
+            checks.push(::platt::schema::Check { name: #name.to_string(), expr: #expr.to_string() });
+        });
+    }
+
     let mut filters_struct_path_types = Punctuated::new();
     filters_struct_path_types.push(syn::PathSegment {
         ident: filters_mod.clone(),
@@ -164,6 +465,26 @@ pub fn inner(model: TokenStream) -> TokenStream {
         }
     };
     let update_builder_name = format_ident!("{}Builder", update_name_struct);
+    let database_fn = match &platt_model.database {
+        Some(database) => quote! {
+            fn database() -> ::std::option::Option<String> {
+                ::std::option::Option::Some(#database.to_string())
+            }
+        },
+        None => quote! { }
+    };
+    let schema_fn = match platt_model.schema.as_deref() {
+        Some(schema) if schema != "public" => quote! {
+            fn schema() -> ::std::option::Option<String> {
+                ::std::option::Option::Some(#schema.to_string())
+            }
+        },
+        _ => quote! { }
+    };
+    let renamed_from = match &platt_model.renamed_from {
+        Some(old) => quote! { ::std::option::Option::Some(#old.to_string()) },
+        None => quote! { ::std::option::Option::None }
+    };
     let update_builder_attr = if platt_model.not_clonable.is_some() { quote!{ #[builder(pattern = "owned")] } } else { quote! { } };
 
     let mut data_struct_path_path = Punctuated::new();
@@ -184,20 +505,42 @@ pub fn inner(model: TokenStream) -> TokenStream {
         }
     };
 
+    let primary_key = match key_types.as_slice() {
+        [] => quote! { ::platt::uuid::Uuid },
+        [key_type] => quote! { #key_type },
+        key_types => quote! { ( #( #key_types ),* ) }
+    };
+
     let result = quote::quote! { 
+        #assertions
+
         pub mod #data_mod {
             use super::*;
 
             pub struct #data_name_struct {
                 #data_struct_fields
             }
+
+            impl ::platt::query::FromRow for #data_name_struct {
+                fn from_row(row: &::platt::db::Row) -> ::platt::error::Result<Self> {
+                    Ok(Self { #data_from_row })
+                }
+            }
+
+            /// The data of a model value, e.g. one built to be stored.
+            impl ::std::convert::From<#model_name> for #data_name_struct {
+                fn from(model: #model_name) -> Self {
+                    Self { #data_from_model }
+                }
+            }
         }
 
         pub mod #filters_mod {
             use super::*;
 
-            #filter_structs
-            pub struct #filter_name_struct #filter_struct_fields
+            /// Hands out the filter handle of every column.
+            #[derive(Default)]
+            pub struct #filter_name_struct { }
             impl #filter_name_struct { #filter_struct_getters }
         }
 
@@ -214,17 +557,42 @@ pub fn inner(model: TokenStream) -> TokenStream {
         }
 
         impl ::platt::schema::DbModel for #model_name {
+            type PrimaryKey = #primary_key;
+
             fn table_name() -> String {
-                #model_name_str.to_string()
+                #table_name_str.to_string()
             }
 
+            fn key_columns() -> ::std::vec::Vec<::platt::schema::Column> {
+                #key_columns
+                key
+            }
+
+            #database_fn
+
+            #schema_fn
+
             fn activate(schema: &mut ::platt::schema::Schema) {
+                #req_enums
                 #req_composites
                 #fields
+                #constraints
+                #indexes
                 #tables
-                tables.push(::platt::schema::Table::new(#model_name_str.to_string(), fields));
+                tables.push(
+                    ::platt::schema::Table::new(#table_name_str.to_string(), fields)
+                        .with_schema(<Self as ::platt::schema::DbModel>::schema())
+                        .with_model(::std::option::Option::Some(#model_name_str.to_string()))
+                        .with_database(<Self as ::platt::schema::DbModel>::database())
+                        .with_checks(checks)
+                        .with_renamed_from(#renamed_from)
+                        .with_indexes(indexes)
+                        .with_uniques(uniques)
+                        .with_foreign_keys(foreign_keys)
+                );
                 schema.add_tables(tables);
                 schema.add_composites(composites);
+                schema.add_enums(enums);
             }
         }
 
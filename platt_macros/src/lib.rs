@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 mod derive_enum;
 mod derive_model;
+mod model_attrs;
 
 #[proc_macro_derive(PlattEnum, attributes(platt))]
 pub fn derive_enum(model: TokenStream) -> TokenStream {
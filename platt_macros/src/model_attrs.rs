@@ -0,0 +1,200 @@
+//! The items of `#[platt(...)]` darling can not read, as syn 1 has no meta
+//! syntax for lists such as `fields = ["a", "b"]` or for keys such as
+//! `where`.
+
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::{bracketed, Token};
+
+/// An item of `#[platt(...)]`: a name followed by whatever comes before the
+/// next comma, such as `= "value"` or `(...)`.
+struct Item {
+    ident: syn::Ident,
+    rest: TokenStream2
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident = input.call(syn::Ident::parse_any)?;
+        let mut rest = TokenStream2::new();
+        while !input.is_empty() && !input.peek(Token![,]) {
+            rest.extend(std::iter::once(input.parse::<TokenTree>()?));
+        }
+        Ok(Self { ident, rest })
+    }
+}
+
+impl Item {
+    fn name(&self) -> String {
+        self.ident.to_string()
+    }
+
+    fn string(&self) -> String {
+        let parser = |input: ParseStream| {
+            input.parse::<Token![=]>()?;
+            input.parse::<syn::LitStr>()
+        };
+        match parser.parse2(self.rest.clone()) {
+            Ok(value) => value.value(),
+            Err(_) => panic!("Expected {} = \"...\".", self.ident)
+        }
+    }
+
+    fn strings(&self) -> Vec<String> {
+        let parser = |input: ParseStream| {
+            input.parse::<Token![=]>()?;
+            let content;
+            bracketed!(content in input);
+            Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&content)
+        };
+        match parser.parse2(self.rest.clone()) {
+            Ok(values) => values.iter().map(syn::LitStr::value).collect(),
+            Err(_) => panic!("Expected {} = [\"...\", ...].", self.ident)
+        }
+    }
+
+    fn flag(&self) -> bool {
+        if !self.rest.is_empty() {
+            panic!("{} does not take a value.", self.ident);
+        }
+        true
+    }
+
+    /// The items inside `name(...)`.
+    fn items(&self) -> Vec<Item> {
+        let parser = |input: ParseStream| {
+            let content;
+            syn::parenthesized!(content in input);
+            Punctuated::<Item, Token![,]>::parse_terminated(&content)
+        };
+        match parser.parse2(self.rest.clone()) {
+            Ok(items) => items.into_iter().collect(),
+            Err(_) => panic!("Expected {}(...).", self.ident)
+        }
+    }
+}
+
+/// Takes the items called `name` out of the `#[platt(...)]` attributes,
+/// leaving the rest for darling.
+fn take(attrs: &mut Vec<syn::Attribute>, name: &str) -> Vec<Item> {
+    let mut taken = Vec::new();
+    let mut kept_attrs = Vec::new();
+    for mut attr in attrs.drain(..) {
+        if !attr.path.is_ident("platt") {
+            kept_attrs.push(attr);
+            continue;
+        }
+        let items = attr.parse_args_with(Punctuated::<Item, Token![,]>::parse_terminated)
+            .unwrap_or_else(|error| panic!("Invalid #[platt(...)] attribute: {}", error));
+        let (found, kept): (Vec<Item>, Vec<Item>) = items.into_iter().partition(|item| item.ident == name);
+        taken.extend(found);
+        if !kept.is_empty() {
+            let kept = kept.iter().map(|item| {
+                let (ident, rest) = (&item.ident, &item.rest);
+                quote! { #ident #rest }
+            });
+            attr.tokens = quote! { ( #(#kept),* ) };
+            kept_attrs.push(attr);
+        }
+    }
+    *attrs = kept_attrs;
+    taken
+}
+
+/// `#[platt(index(fields = [...], unique, where = "...", using = "gin"))]`
+pub struct IndexAttr {
+    /// Field names, or SQL expressions.
+    pub fields: Vec<String>,
+    pub unique: bool,
+    pub predicate: Option<String>,
+    pub using: Option<String>,
+    pub name: Option<String>
+}
+
+pub fn take_indexes(attrs: &mut Vec<syn::Attribute>) -> Vec<IndexAttr> {
+    take(attrs, "index").iter().map(|index| {
+        let mut attr = IndexAttr { fields: Vec::new(), unique: false, predicate: None, using: None, name: None };
+        for item in index.items() {
+            match item.name().as_str() {
+                "fields" => attr.fields = item.strings(),
+                "unique" => attr.unique = item.flag(),
+                "where" => attr.predicate = Some(item.string()),
+                "using" => attr.using = Some(item.string()),
+                "name" => attr.name = Some(item.string()),
+                other => panic!("Unknown index option \"{}\", expected fields, unique, where, using or name.", other)
+            }
+        }
+        if attr.fields.is_empty() {
+            panic!("An index needs fields = [...].");
+        }
+        attr
+    }).collect()
+}
+
+/// `#[platt(unique(fields = [...]))]`, optionally with a `name`.
+pub struct UniqueAttr {
+    pub fields: Vec<String>,
+    pub name: Option<String>
+}
+
+pub fn take_uniques(attrs: &mut Vec<syn::Attribute>) -> Vec<UniqueAttr> {
+    take(attrs, "unique").iter().map(|unique| {
+        let mut attr = UniqueAttr { fields: Vec::new(), name: None };
+        for item in unique.items() {
+            match item.name().as_str() {
+                "fields" => attr.fields = item.strings(),
+                "name" => attr.name = Some(item.string()),
+                other => panic!("Unknown unique option \"{}\", expected fields or name.", other)
+            }
+        }
+        if attr.fields.is_empty() {
+            panic!("A unique constraint needs fields = [...].");
+        }
+        attr
+    }).collect()
+}
+
+/// `#[platt(check(name = "...", expr = "..."))]`
+pub struct CheckAttr {
+    pub name: String,
+    pub expr: String
+}
+
+pub fn take_checks(attrs: &mut Vec<syn::Attribute>) -> Vec<CheckAttr> {
+    take(attrs, "check").iter().map(|check| {
+        let (mut name, mut expr) = (None, None);
+        for item in check.items() {
+            match item.name().as_str() {
+                "name" => name = Some(item.string()),
+                "expr" => expr = Some(item.string()),
+                other => panic!("Unknown check option \"{}\", expected name or expr.", other)
+            }
+        }
+        match (name, expr) {
+            (Some(name), Some(expr)) => CheckAttr { name, expr },
+            _ => panic!("A check needs a name = \"...\" and an expr = \"...\".")
+        }
+    }).collect()
+}
+
+/// `#[platt(default = ...)]` on a field, as an SQL literal. Takes a string,
+/// number or bool, which may be negative unlike in other attributes.
+pub fn take_default(attrs: &mut Vec<syn::Attribute>) -> Option<String> {
+    let default = take(attrs, "default").pop()?;
+    let parser = |input: ParseStream| {
+        input.parse::<Token![=]>()?;
+        let negative = input.parse::<Option<Token![-]>>()?.is_some();
+        Ok((negative, input.parse::<syn::Lit>()?))
+    };
+    let sql = match parser.parse2(default.rest.clone()) {
+        Ok((false, syn::Lit::Str(value))) => format!("'{}'", value.value().replace('\'', "''")),
+        Ok((false, syn::Lit::Bool(value))) => if value.value { "TRUE" } else { "FALSE" }.to_string(),
+        Ok((negative, syn::Lit::Int(value))) => format!("{}{}", if negative { "-" } else { "" }, value.base10_digits()),
+        Ok((negative, syn::Lit::Float(value))) => format!("{}{}", if negative { "-" } else { "" }, value.base10_digits()),
+        _ => panic!("Expected default = followed by a string, number or bool.")
+    };
+    Some(sql)
+}